@@ -0,0 +1,31 @@
+//! 比较命中 hash 缓存与每次都重新搜索 PATH 时启动同一命令的耗时。
+//!
+//! 运行: cargo run --release --example spawn_bench [次数]
+
+use std::env;
+use std::time::Instant;
+
+use my_shell::shell::Shell;
+
+fn main() {
+    let iterations: usize = env::args().nth(1).and_then(|n| n.parse().ok()).unwrap_or(500);
+
+    let mut shell = Shell::new();
+    let start = Instant::now();
+    for _ in 0..iterations {
+        shell.run_line("true");
+    }
+    let cached = start.elapsed();
+
+    let mut shell = Shell::new();
+    let start = Instant::now();
+    for _ in 0..iterations {
+        shell.run_line("hash -r");
+        shell.run_line("true");
+    }
+    let uncached = start.elapsed();
+
+    println!("启动 `true` {} 次", iterations);
+    println!("使用缓存:   {:?} (平均 {:?})", cached, cached / iterations as u32);
+    println!("每次搜索:   {:?} (平均 {:?})", uncached, uncached / iterations as u32);
+}
@@ -0,0 +1,1630 @@
+//! builtins.rs
+
+use std::env;
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::Duration;
+
+use crate::command_hash;
+use crate::executor;
+use crate::jobs::JobState;
+use crate::parser::{self, ParsedCommand};
+use crate::prompt;
+use crate::shell::{Flow, Shell, Stopwatch, home_dir};
+use crate::terminal;
+
+/// 内置命令执行时的上下文：Shell 的会话状态（目录栈、变量、别名、作业表……）、命令本身、输出的去向，
+/// 以及是否在子 Shell（管道或后台）中执行
+pub struct Context<'a> {
+    pub shell: &'a mut Shell,
+    pub cmd: &'a ParsedCommand,
+    pub out: &'a mut dyn Write,
+    pub subshell: bool,
+}
+
+/// 在 Shell 进程内执行的命令，返回退出状态
+pub trait Builtin {
+    fn run(&self, ctx: &mut Context) -> i32;
+}
+
+/// 内置命令表中的一项：名字、实现和 help 显示的说明
+#[derive(Clone, Copy)]
+struct BuiltinSpec {
+    name: &'static str,
+    usage: &'static str,   // 用法，help 名字 的第一行
+    summary: &'static str, // 一句话说明，help 的列表中显示
+    details: &'static str, // 详细说明，可以有多行，help 名字 时显示
+    run: fn(&mut Context) -> i32,
+}
+
+impl Builtin for BuiltinSpec {
+    fn run(&self, ctx: &mut Context) -> i32 {
+        (self.run)(ctx)
+    }
+}
+
+/// 内置命令表，按名字排序。新增内置命令只需要在这里加一项，BUILTINS、type、help 和补全都会自动包含它。
+const REGISTRY: [BuiltinSpec; 33] = [
+    BuiltinSpec {
+        name: ".",
+        usage: ". 文件名 [参数 ...]",
+        summary: "在当前 Shell 中执行文件中的命令",
+        details: "和 source 相同。",
+        run: |ctx| run_source(ctx.shell, &ctx.cmd.name, &ctx.cmd.args),
+    },
+    BuiltinSpec {
+        name: "[",
+        usage: "[ 表达式 ]",
+        summary: "求值条件表达式",
+        details: "和 test 相同，但最后一个参数必须是 `]`。",
+        run: |ctx| run_test(&ctx.cmd.name, &ctx.cmd.args),
+    },
+    BuiltinSpec {
+        name: "alias",
+        usage: "alias [名字[=值] ...]",
+        summary: "定义或显示别名",
+        details: "`alias 名字=值` 定义别名，`alias 名字` 打印它的定义，\n没有参数时按名字排序列出所有别名。",
+        run: |ctx| run_alias(ctx.shell, &ctx.cmd.args, ctx.out),
+    },
+    BuiltinSpec {
+        name: "bg",
+        usage: "bg [作业]",
+        summary: "让停止的作业在后台继续运行",
+        details: "作业默认是当前作业，可以写成 `%N`、`%+` 或 `%-`。",
+        run: |ctx| run_bg(ctx.shell, &ctx.cmd.args, ctx.out),
+    },
+    BuiltinSpec {
+        name: "cd",
+        usage: "cd [目录 | -]",
+        summary: "切换当前目录",
+        details: "没有参数时回到主目录 ($HOME)，`cd -` 回到上一个目录（$OLDPWD）并打印它。每次切换目录都会更新环境变量 PWD 和 OLDPWD。\n不以 `/` 或 `.` 开头的目录先在 $CDPATH（冒号分隔，空项表示当前目录）中查找，找到时打印新的目录。\n打开 `shopt -s dotexpand` 后，`...` 这样的连续点号会展开成多级 `..`。",
+        run: |ctx| run_cd(ctx.shell, &ctx.cmd.args, ctx.out),
+    },
+    BuiltinSpec {
+        name: "dirs",
+        usage: "dirs",
+        summary: "显示目录栈",
+        details: "从当前目录开始，按从栈顶到栈底的顺序在一行中打印目录栈，主目录缩写成 `~`。",
+        run: |ctx| run_dirs(ctx.shell, &ctx.cmd.args, ctx.out),
+    },
+    BuiltinSpec {
+        name: "disown",
+        usage: "disown [-h] [-a] [%作业 | pid ...]",
+        summary: "把作业从作业表中删除",
+        details: "没有参数时作用于当前作业，`-a` 作用于所有作业。删除的作业继续运行，不再被 jobs 列出，\nShell 退出时也不会收到 SIGHUP。`-h` 只标记作业在 Shell 退出时不接收 SIGHUP，作业仍留在表中。\n作业不存在时返回 1。",
+        run: |ctx| run_disown(ctx.shell, &ctx.cmd.args),
+    },
+    BuiltinSpec {
+        name: "echo",
+        usage: "echo [-neE] [参数 ...]",
+        summary: "输出参数",
+        details: "用空格连接参数并输出换行。\n`-n` 不输出换行，`-e` 解释反斜杠转义序列，`-E` 不解释（默认）。\n打开 `shopt -s xpg_echo` 后默认解释转义序列，`-E` 仍可关闭。",
+        run: |ctx| run_echo(&ctx.cmd.args, ctx.shell.options.xpg_echo, ctx.out),
+    },
+    BuiltinSpec {
+        name: "exit",
+        usage: "exit [n]",
+        summary: "退出 Shell",
+        details: "退出状态为 n 的低 8 位，省略时为上一条命令的状态；n 不是数字时报错并以状态 2 退出。\n在管道中只结束所在的子 Shell。",
+        // 单独的 exit 由 run_command_line 处理，管道中的 exit 只退出子 Shell
+        run: |ctx| exit_status(&ctx.cmd.args, ctx.shell.last_status).unwrap_or(1),
+    },
+    BuiltinSpec {
+        name: "export",
+        usage: "export [名字[=值] ...]",
+        summary: "设置环境变量",
+        details: "`export 名字=值` 设置环境变量，之后启动的命令都会继承它；\n`export 名字` 把已有的 Shell 变量移到环境中；没有参数时列出所有导出的变量。",
+        run: |ctx| run_export(ctx.shell, &ctx.cmd.args, ctx.out),
+    },
+    BuiltinSpec {
+        name: "fg",
+        usage: "fg [作业]",
+        summary: "把作业放到前台运行",
+        details: "作业默认是当前作业。停止的作业会先继续运行，Shell 等待它结束或再次被 Ctrl-Z 停止。",
+        run: |ctx| run_fg(ctx.shell, &ctx.cmd.args),
+    },
+    BuiltinSpec {
+        name: "hash",
+        usage: "hash [-r] [名字 ...]",
+        summary: "记住或显示命令的完整路径",
+        details: "没有参数时列出缓存的命令和命中次数，`-r` 清空缓存，\n给出名字时在 PATH 中查找并记住这些命令。",
+        run: |ctx| run_hash(ctx.shell, &ctx.cmd.args, ctx.out),
+    },
+    BuiltinSpec {
+        name: "help",
+        usage: "help [名字 ...]",
+        summary: "显示内置命令的帮助",
+        details: "没有参数时列出所有内置命令，给出名字时显示这些命令的详细用法。\n有任何一个名字不是内置命令时返回 1。",
+        run: |ctx| run_help(&ctx.cmd.args, ctx.out),
+    },
+    BuiltinSpec {
+        name: "jobs",
+        usage: "jobs [-lp]",
+        summary: "列出作业",
+        details: "`-l` 同时显示 pid，`-p` 只显示 pid。已经结束的作业报告一次后从作业表中删除。",
+        run: |ctx| run_jobs(ctx.shell, &ctx.cmd.args, ctx.out),
+    },
+    BuiltinSpec {
+        name: "kill",
+        usage: "kill [-s 信号 | -信号] pid | %作业 ...",
+        summary: "给进程或作业发送信号",
+        details: "信号可以是编号或名字（`-9`、`-KILL`、`-SIGKILL`），默认为 SIGTERM。\n作业中的所有进程都会收到信号；任何一个目标失败时返回 1。",
+        run: |ctx| run_kill(ctx.shell, &ctx.cmd.args),
+    },
+    BuiltinSpec {
+        name: "popd",
+        usage: "popd",
+        summary: "从目录栈弹出目录并切换过去",
+        details: "成功后和 dirs 一样打印目录栈。",
+        run: |ctx| run_popd(ctx.shell, &ctx.cmd.args, ctx.out),
+    },
+    BuiltinSpec {
+        name: "pushd",
+        usage: "pushd [目录]",
+        summary: "把当前目录压入目录栈并切换目录",
+        details: "没有参数时交换当前目录和栈顶的目录。成功后和 dirs 一样打印目录栈。",
+        run: |ctx| run_pushd(ctx.shell, &ctx.cmd.args, ctx.out),
+    },
+    BuiltinSpec {
+        name: "pwd",
+        usage: "pwd",
+        summary: "打印当前工作目录",
+        details: "",
+        run: |ctx| run_pwd(ctx.out),
+    },
+    BuiltinSpec {
+        name: "read",
+        usage: "read [-r] [-p 提示] [名字 ...]",
+        summary: "读取一行并赋给变量",
+        details: "按空白分割后依次赋给给出的变量，最后一个变量得到剩下的全部内容；\n没有给出变量时整行保存在 REPLY 中。`-p` 先显示提示，`-r` 不把反斜杠当作转义符。\n遇到文件结尾时返回 1。",
+        run: |ctx| run_read(ctx.shell, ctx.cmd),
+    },
+    BuiltinSpec {
+        name: "reset",
+        usage: "reset",
+        summary: "复位终端",
+        details: "恢复 cooked 模式并发送终端复位序列，全屏程序把终端弄乱后使用。",
+        run: |ctx| run_reset(&ctx.cmd.name),
+    },
+    BuiltinSpec {
+        name: "sane",
+        usage: "sane",
+        summary: "复位终端",
+        details: "和 reset 相同。",
+        run: |ctx| run_reset(&ctx.cmd.name),
+    },
+    BuiltinSpec {
+        name: "set",
+        usage: "set [-emnx] [+emnx] [-o 选项] [+o 选项]",
+        summary: "设置 Shell 选项",
+        details: "没有参数或只有 `-o` 时列出选项。`-` 打开选项，`+` 关闭选项：\n`-e` (errexit) 命令失败时退出，`-m` (monitor) 作业控制，`-n` (noexec) 只打印命令不执行，\n`-x` (xtrace) 执行前打印展开后的命令，\n`-o helpcomplete` 补全时抓取 `--help` 中的长选项，`-o histignorefailed` 失败的命令不进入历史记录。",
+        run: |ctx| run_set(ctx.shell, &ctx.cmd.args, ctx.out),
+    },
+    BuiltinSpec {
+        name: "shopt",
+        usage: "shopt [-su] [选项 ...]",
+        summary: "设置 shopt 选项",
+        details: "`-s` 打开、`-u` 关闭选项，没有参数时列出所有选项，\n只给出名字时打印它们的状态（全部打开才返回 0）。\n可用的选项：dotexpand、xpg_echo。",
+        run: |ctx| run_shopt(ctx.shell, &ctx.cmd.args, ctx.out),
+    },
+    BuiltinSpec {
+        name: "source",
+        usage: "source 文件名 [参数 ...]",
+        summary: "在当前 Shell 中执行文件中的命令",
+        details: "其中的 export、cd 等会影响当前会话。给出参数时，执行期间它们是 `$1`、`$2` ……\n某一行出错时只打印错误并继续执行，返回最后一条命令的退出状态。",
+        run: |ctx| run_source(ctx.shell, &ctx.cmd.name, &ctx.cmd.args),
+    },
+    BuiltinSpec {
+        name: "test",
+        usage: "test 表达式",
+        summary: "求值条件表达式",
+        details: "真时返回 0，假时返回 1，表达式有错误时返回 2。",
+        run: |ctx| run_test(&ctx.cmd.name, &ctx.cmd.args),
+    },
+    BuiltinSpec {
+        name: "time",
+        usage: "time 管道",
+        summary: "报告命令执行的时间",
+        details: "命令结束后在标准错误上打印实际经过的时间 (real)，以及用户态 (user) 和内核态 (sys)\n的 CPU 时间，返回命令的退出状态。写在管道前面时统计整条管道，例如 `time a | b`。",
+        run: run_time,
+    },
+    BuiltinSpec {
+        name: "timeout",
+        usage: "timeout 时长 命令 [参数 ...]",
+        summary: "限时执行命令",
+        details: "超过时限就结束命令并返回 124。时长可以是小数，\n单位后缀 s、m、h、d 分别表示秒、分、时、天，为 0 时不限时。",
+        run: run_timeout,
+    },
+    BuiltinSpec {
+        name: "type",
+        usage: "type 名字 ...",
+        summary: "说明命令名的类型",
+        details: "按别名、内置命令、PATH 中的可执行文件的顺序说明每个名字是什么，\n有任何一个名字找不到时返回 1。",
+        run: |ctx| run_type(ctx.shell, &ctx.cmd.args, ctx.out),
+    },
+    BuiltinSpec {
+        name: "umask",
+        usage: "umask [掩码]",
+        summary: "显示或设置文件创建掩码",
+        details: "没有参数时以八进制打印当前的掩码，`umask 022` 设置新的掩码。",
+        run: |ctx| run_umask(&ctx.cmd.args, ctx.out),
+    },
+    BuiltinSpec {
+        name: "unalias",
+        usage: "unalias [-a] 名字 ...",
+        summary: "删除别名",
+        details: "`-a` 删除所有别名。",
+        run: |ctx| run_unalias(ctx.shell, &ctx.cmd.args),
+    },
+    BuiltinSpec {
+        name: "unset",
+        usage: "unset 名字 ...",
+        summary: "删除变量",
+        details: "变量不存在时也算成功。",
+        run: |ctx| run_unset(ctx.shell, &ctx.cmd.args),
+    },
+    BuiltinSpec {
+        name: "wait",
+        usage: "wait [%作业 | pid ...]",
+        summary: "等待后台作业结束",
+        details: "没有参数时等待所有正在运行的作业并返回 0；否则依次等待给出的作业，\n返回最后一个作业的退出状态，作业不存在时返回 127。等待过的作业从作业表中删除。",
+        run: |ctx| run_wait(ctx.shell, &ctx.cmd.args, ctx.out),
+    },
+    BuiltinSpec {
+        name: "which",
+        usage: "which 名字 ...",
+        summary: "显示命令的完整路径",
+        details: "打印在 PATH 中找到的第一个可执行文件，有任何一个名字找不到时返回 1。",
+        run: |ctx| run_which(&ctx.cmd.args, ctx.out),
+    },
+];
+
+/// 由 Shell 自身实现的内置命令的名字，按字母顺序排列
+pub const BUILTINS: &[&str] = &builtin_names(&REGISTRY);
+
+const fn builtin_names<const N: usize>(registry: &[BuiltinSpec; N]) -> [&'static str; N] {
+    let mut names = [""; N];
+    let mut i = 0;
+    while i < N {
+        names[i] = registry[i].name;
+        i += 1;
+    }
+    names
+}
+
+fn find_spec(name: &str) -> Option<&'static BuiltinSpec> {
+    let index = REGISTRY.binary_search_by(|spec| spec.name.cmp(name)).ok()?;
+    Some(&REGISTRY[index])
+}
+
+/// 按名字查找内置命令，找不到时返回 None
+pub fn find_builtin(name: &str) -> Option<&'static dyn Builtin> {
+    find_spec(name).map(|spec| spec as &dyn Builtin)
+}
+
+pub fn is_builtin(name: &str) -> bool {
+    find_builtin(name).is_some()
+}
+
+/// cd 内置命令：没有参数时回到 $HOME，`cd -` 回到上一个目录并打印它；
+/// 不以 `/` 或 `.` 开头的目录先在 $CDPATH 中查找，通过 CDPATH 找到时也打印新的目录；
+/// 打开 dotexpand 后，`...` 这样的连续点号会展开成多级 `..`
+fn run_cd(shell: &mut Shell, args: &[String], out: &mut dyn Write) -> i32 {
+    let arg = match args {
+        [] => "",
+        [arg] => arg.as_str(),
+        _ => {
+            eprintln!("cd: 参数过多");
+            return 1;
+        },
+    };
+
+    let target = if args.is_empty() {
+        match shell.variable_value("HOME") {
+            home if home.is_empty() => {
+                eprintln!("cd: HOME 未设置");
+                return 1;
+            },
+            home => home,
+        }
+    } else if arg == "-" {
+        match shell.variable_value("OLDPWD") {
+            dir if dir.is_empty() => {
+                eprintln!("cd: OLDPWD 未设置");
+                return 1;
+            },
+            dir => dir,
+        }
+    } else if shell.options.dotexpand {
+        expand_dots(arg)
+    } else {
+        arg.to_string()
+    };
+    let mut print = arg == "-";
+    let searchable = !args.is_empty() && arg != "-" && !target.starts_with(['/', '.']);
+    let cdpath = shell.variable_value("CDPATH");
+    let target = match searchable.then(|| search_cdpath(&target, &cdpath)).flatten() {
+        Some((found, from_cdpath)) => {
+            print = from_cdpath;
+            found.display().to_string()
+        },
+        _ => target,
+    };
+
+    if let Err(e) = change_dir(shell, Path::new(&target)) {
+        eprintln!("cd: {}: {}", if args.is_empty() { &target } else { arg }, e);
+        return 1;
+    }
+    if print {
+        let _ = match env::current_dir() {
+            Ok(dir) => writeln!(out, "{}", dir.display()),
+            Err(_) => writeln!(out, "{}", target),
+        };
+    }
+    0
+}
+
+/// 切换工作目录，并和 bash 一样更新环境变量 PWD 和 OLDPWD，`cd -` 回到 OLDPWD。
+/// 同名的 Shell 变量被删除，之后 `$PWD` 和 `$OLDPWD` 展开的是环境中的新值
+fn change_dir(shell: &mut Shell, target: &Path) -> io::Result<()> {
+    let previous = env::current_dir().ok();
+    env::set_current_dir(target)?;
+    if let Some(previous) = previous {
+        shell.set_env("OLDPWD", previous);
+    }
+    if let Ok(current) = env::current_dir() {
+        shell.set_env("PWD", current);
+    }
+    shell.variables.remove("OLDPWD");
+    shell.variables.remove("PWD");
+    Ok(())
+}
+
+/// pushd 内置命令：把当前目录压入目录栈并切换到参数指定的目录；
+/// 没有参数时交换当前目录和栈顶的目录。成功后和 dirs 一样打印目录栈。
+fn run_pushd(shell: &mut Shell, args: &[String], out: &mut dyn Write) -> i32 {
+    let current = match env::current_dir() {
+        Ok(dir) => dir,
+        Err(e) => {
+            eprintln!("pushd: {}", e);
+            return 1;
+        },
+    };
+    let target = match args {
+        [] => match shell.dir_stack.pop() {
+            Some(top) => top,
+            None => {
+                eprintln!("pushd: 没有其他目录");
+                return 1;
+            },
+        },
+        [dir] => PathBuf::from(dir),
+        _ => {
+            eprintln!("pushd: 参数过多");
+            return 1;
+        },
+    };
+    if let Err(e) = change_dir(shell, &target) {
+        eprintln!("pushd: {}: {}", target.display(), e);
+        if args.is_empty() {
+            shell.dir_stack.push(target); // 切换失败时栈保持不变
+        }
+        return 1;
+    }
+    shell.dir_stack.push(current);
+    run_dirs(shell, &[], out)
+}
+
+/// popd 内置命令：弹出栈顶的目录并切换过去，成功后打印目录栈
+fn run_popd(shell: &mut Shell, args: &[String], out: &mut dyn Write) -> i32 {
+    if !args.is_empty() {
+        eprintln!("popd: 参数过多");
+        return 1;
+    }
+    let Some(top) = shell.dir_stack.pop() else {
+        eprintln!("popd: 目录栈为空");
+        return 1;
+    };
+    if let Err(e) = change_dir(shell, &top) {
+        eprintln!("popd: {}: {}", top.display(), e);
+        shell.dir_stack.push(top);
+        return 1;
+    }
+    run_dirs(shell, &[], out)
+}
+
+/// dirs 内置命令：从当前目录开始，按从栈顶到栈底的顺序在一行中打印目录栈，主目录缩写成 `~`
+fn run_dirs(shell: &Shell, args: &[String], out: &mut dyn Write) -> i32 {
+    if !args.is_empty() {
+        eprintln!("dirs: 参数过多");
+        return 1;
+    }
+    let home = home_dir();
+    let current = env::current_dir().map(|dir| dir.display().to_string()).unwrap_or_else(|_| ".".to_string());
+    let dirs: Vec<String> = std::iter::once(current)
+        .chain(shell.dir_stack.iter().rev().map(|dir| dir.display().to_string()))
+        .map(|dir| prompt::abbreviate_home(&dir, &home))
+        .collect();
+    let _ = writeln!(out, "{}", dirs.join(" "));
+    0
+}
+
+/// `shopt` 认识的选项名，按字典序列出
+const SHOPT_OPTIONS: [&str; 2] = ["dotexpand", "xpg_echo"];
+
+/// shopt 内置命令：`shopt -s name` 打开、`shopt -u name` 关闭，
+/// 无参数时列出所有选项，只给出名字时打印状态（全部打开才返回 0）
+fn run_shopt(shell: &mut Shell, args: &[String], out: &mut dyn Write) -> i32 {
+    let enable = match args.first().map(String::as_str) {
+        Some("-s") => Some(true),
+        Some("-u") => Some(false),
+        _ => None,
+    };
+    let names = if enable.is_some() { &args[1..] } else { args };
+
+    if names.is_empty() {
+        for name in SHOPT_OPTIONS {
+            let on = *shopt_option(shell, name).expect("SHOPT_OPTIONS 中的选项都存在");
+            let _ = writeln!(out, "{:<20}\t{}", name, if on { "on" } else { "off" });
+        }
+        return 0;
+    }
+
+    let mut status = 0;
+    for name in names {
+        let option = match shopt_option(shell, name) {
+            Some(option) => option,
+            None => {
+                eprintln!("shopt: {}: 无效的 shell 选项名", name);
+                status = 1;
+                continue;
+            }
+        };
+        match enable {
+            Some(value) => *option = value,
+            None => {
+                let _ = writeln!(out, "{:<20}\t{}", name, if *option { "on" } else { "off" });
+                if !*option {
+                    status = 1;
+                }
+            }
+        }
+    }
+    status
+}
+
+/// 按名字找到 shopt 选项对应的开关
+fn shopt_option<'a>(shell: &'a mut Shell, name: &str) -> Option<&'a mut bool> {
+    match name {
+        "dotexpand" => Some(&mut shell.options.dotexpand),
+        "xpg_echo" => Some(&mut shell.options.xpg_echo),
+        _ => None,
+    }
+}
+
+/// hash 内置命令：无参数时列出缓存的命令，`hash -r` 清空缓存，
+/// `hash name...` 在 PATH 中查找并记住这些命令。
+fn run_hash(shell: &mut Shell, args: &[String], out: &mut dyn Write) -> i32 {
+    if args.is_empty() {
+        let entries = shell.command_hash.entries();
+        if entries.is_empty() {
+            let _ = writeln!(out, "hash: 哈希表为空");
+        } else {
+            let _ = writeln!(out, "命中\t命令");
+            for (_, path, hits) in entries {
+                let _ = writeln!(out, "{:4}\t{}", hits, path.display());
+            }
+        }
+        return 0;
+    }
+
+    let mut status = 0;
+    for arg in args {
+        if arg == "-r" {
+            shell.command_hash.clear();
+        } else if shell.command_hash.resolve(arg).is_none() {
+            eprintln!("hash: {}: 未找到", arg);
+            status = 1;
+        }
+    }
+    status
+}
+
+/// jobs 内置命令：列出后台作业及其状态，`-l` 同时显示 pid，`-p` 只显示 pid。
+/// 已经结束的作业报告一次后从作业表中删除。
+fn run_jobs(shell: &mut Shell, args: &[String], out: &mut dyn Write) -> i32 {
+    let (mut long, mut pids_only) = (false, false);
+    for arg in args {
+        match arg.as_str() {
+            "-l" => long = true,
+            "-p" => pids_only = true,
+            _ => {
+                eprintln!("jobs: {}: 无效的选项", arg);
+                return 2;
+            }
+        }
+    }
+
+    shell.jobs.reap();
+    for job in shell.jobs.iter() {
+        if pids_only {
+            let _ = writeln!(out, "{}", job.pid().unwrap_or(0));
+        } else if long {
+            let line = shell.jobs.describe(job);
+            let (head, rest) = line.split_at(line.find(' ').unwrap_or(line.len()));
+            let _ = writeln!(out, "{} {}{}", head, job.pid().unwrap_or(0), rest);
+        } else {
+            let _ = writeln!(out, "{}", shell.jobs.describe(job));
+        }
+    }
+    shell.jobs.remove_finished();
+    0
+}
+
+/// fg 内置命令：把后台或停止的作业（默认是当前作业）放到前台，打印它的命令行并等待它结束。
+/// 命令行直接写到终端，因为内置命令的输出要等命令结束后才会写出。
+/// 作业控制下作业再次被 Ctrl-Z 停止时，它留在作业表中。
+fn run_fg(shell: &mut Shell, args: &[String]) -> i32 {
+    let id = match shell.jobs.find(args.first().map(String::as_str)) {
+        Ok(id) => id,
+        Err(e) => {
+            eprintln!("fg: {}", e);
+            return 1;
+        }
+    };
+    let monitor = shell.options.monitor;
+    let Some(job) = shell.jobs.get_mut(id) else {
+        return 1;
+    };
+    println!("{}", job.command_line());
+    if monitor {
+        terminal::give_terminal_to(job.pgid());
+    }
+    job.resume();
+    job.state = JobState::Running;
+    let status = if monitor { job.wait_or_stop() } else { Some(job.wait()) };
+    if monitor {
+        terminal::reclaim_terminal();
+    }
+    match status {
+        Some(status) => {
+            shell.jobs.remove(id);
+            status
+        },
+        None => {
+            println!();
+            if let Some(job) = shell.jobs.get(id) {
+                eprintln!("{}", shell.jobs.describe(job));
+            }
+            executor::STOPPED_STATUS
+        },
+    }
+}
+
+/// bg 内置命令：让停止的作业（默认是当前作业）在后台继续运行
+fn run_bg(shell: &mut Shell, args: &[String], out: &mut dyn Write) -> i32 {
+    let id = match shell.jobs.find(args.first().map(String::as_str)) {
+        Ok(id) => id,
+        Err(e) => {
+            eprintln!("bg: {}", e);
+            return 1;
+        }
+    };
+    let Some(job) = shell.jobs.get_mut(id) else {
+        return 1;
+    };
+    if matches!(job.state, JobState::Done(_)) {
+        eprintln!("bg: 作业 {} 已经结束", id);
+        return 1;
+    }
+    job.resume();
+    job.state = JobState::Running;
+    let line = format!("[{}]{} {} &", id, shell.jobs.marker(id), shell.jobs.get(id).map_or("", |job| job.command_line()));
+    let _ = writeln!(out, "{}", line);
+    0
+}
+
+/// wait 内置命令：没有参数时等待所有后台作业结束，返回 0；
+/// 参数是 `%作业` 或 pid 时依次等待对应的作业，返回最后一个作业的退出状态，找不到作业时返回 127。
+/// 等待过的作业从作业表中删除，打开作业控制时和 jobs 一样报告它们结束。停止的作业不会被等待。
+fn run_wait(shell: &mut Shell, args: &[String], out: &mut dyn Write) -> i32 {
+    if args.is_empty() {
+        let ids: Vec<usize> = shell.jobs.iter().filter(|job| job.state != JobState::Stopped).map(|job| job.id).collect();
+        for id in ids {
+            wait_job(shell, id, out);
+        }
+        return 0;
+    }
+
+    let mut status = 0;
+    for arg in args {
+        let id = if arg.starts_with('%') {
+            shell.jobs.find(Some(arg)).ok()
+        } else if let Ok(pid) = arg.parse::<u32>() {
+            shell.jobs.find_pid(pid)
+        } else {
+            eprintln!("wait: `{}': 不是有效的 pid 或作业说明", arg);
+            status = 2;
+            continue;
+        };
+        status = match id {
+            Some(id) => wait_job(shell, id, out),
+            None => {
+                eprintln!("wait: {}: 没有该作业", arg);
+                127
+            },
+        };
+    }
+    status
+}
+
+/// 等待一个作业结束并把它从作业表中删除，返回它的退出状态；停止的作业不等待，返回停止的状态
+fn wait_job(shell: &mut Shell, id: usize, out: &mut dyn Write) -> i32 {
+    let Some(job) = shell.jobs.get_mut(id) else {
+        return 127;
+    };
+    let status = match job.state {
+        JobState::Done(status) => status,
+        JobState::Stopped => return executor::STOPPED_STATUS,
+        JobState::Running => job.wait(),
+    };
+    if shell.options.monitor
+        && let Some(job) = shell.jobs.get(id)
+    {
+        let _ = writeln!(out, "{}", shell.jobs.describe(job));
+    }
+    shell.jobs.remove(id);
+    status
+}
+
+/// disown 内置命令：把作业（默认是当前作业）从作业表中删除，它不再被报告，Shell 退出时也不会收到 SIGHUP；
+/// `-a` 作用于所有作业，`-h` 只标记作业在 Shell 退出时不接收 SIGHUP，作业仍留在表中。
+/// 作业可以用 `%作业` 或 pid 指定，找不到作业时返回 1。
+fn run_disown(shell: &mut Shell, args: &[String]) -> i32 {
+    let mut all = false;
+    let mut nohup = false;
+    let mut specs = Vec::new();
+    for arg in args {
+        match arg.as_str() {
+            "-a" => all = true,
+            "-h" => nohup = true,
+            "-ah" | "-ha" => {
+                all = true;
+                nohup = true;
+            },
+            option if option.len() > 1 && option.starts_with('-') => {
+                eprintln!("disown: {}: 无效的选项", option);
+                eprintln!("disown: 用法: disown [-h] [-a] [%作业 | pid ...]");
+                return 2;
+            },
+            spec => specs.push(spec),
+        }
+    }
+
+    let mut status = 0;
+    let ids: Vec<usize> = if all {
+        shell.jobs.iter().map(|job| job.id).collect()
+    } else if specs.is_empty() {
+        match shell.jobs.find(None) {
+            Ok(id) => vec![id],
+            Err(e) => {
+                eprintln!("disown: {}", e);
+                return 1;
+            },
+        }
+    } else {
+        specs
+            .into_iter()
+            .filter_map(|spec| {
+                let id = if spec.starts_with('%') {
+                    shell.jobs.find(Some(spec)).ok()
+                } else {
+                    spec.parse::<u32>().ok().and_then(|pid| shell.jobs.find_pid(pid))
+                };
+                if id.is_none() {
+                    eprintln!("disown: {}: 没有该作业", spec);
+                    status = 1;
+                }
+                id
+            })
+            .collect()
+    };
+
+    for id in ids {
+        if nohup {
+            if let Some(job) = shell.jobs.get_mut(id) {
+                job.nohup = true;
+            }
+        } else if let Some(mut job) = shell.jobs.remove(id) {
+            // 不再管理的作业由后台线程等待它结束，避免留下僵尸进程
+            thread::spawn(move || job.wait());
+        }
+    }
+    status
+}
+
+/// kill 内置命令：`kill [-SIGNAL | -s SIGNAL] pid | %job ...` 给进程或作业中的所有进程发送信号。
+/// 信号可以是编号或名字（`-9`、`-KILL`、`-SIGKILL`），默认为 SIGTERM；任何一个目标失败时返回 1。
+fn run_kill(shell: &mut Shell, args: &[String]) -> i32 {
+    let (signal, targets) = match args {
+        [flag, name, rest @ ..] if flag == "-s" => (name.as_str(), rest),
+        [flag, rest @ ..] if flag.len() > 1 && flag.starts_with('-') && !flag.starts_with("--") => (&flag[1..], rest),
+        _ => ("TERM", args),
+    };
+    let Some(signal) = parse_signal(signal) else {
+        eprintln!("kill: {}: 无效的信号声明", signal);
+        return 1;
+    };
+    if targets.is_empty() {
+        eprintln!("kill: 用法: kill [-s 信号 | -信号] pid | %作业 ...");
+        return 2;
+    }
+
+    let mut status = 0;
+    for target in targets {
+        let job = target.starts_with('%').then(|| shell.jobs.find(Some(target)).ok()).flatten();
+        let pids = if target.starts_with('%') {
+            match job.and_then(|id| shell.jobs.get(id)).map(|job| job.pids()) {
+                Some(pids) => pids.into_iter().map(|pid| pid as i32).collect(),
+                None => {
+                    eprintln!("kill: {}: 没有该作业", target);
+                    status = 1;
+                    continue;
+                },
+            }
+        } else {
+            match target.parse::<i32>() {
+                Ok(pid) => vec![pid],
+                Err(_) => {
+                    eprintln!("kill: {}: 参数必须是进程或作业 ID", target);
+                    status = 1;
+                    continue;
+                },
+            }
+        };
+        for pid in pids {
+            if let Err(e) = send_signal(pid, signal) {
+                eprintln!("kill: ({}) - {}", pid, e);
+                status = 1;
+            }
+        }
+        // 和 bash 一样，停止的作业收到 SIGTERM 或 SIGHUP 后再让它继续，它才能处理信号并退出
+        if let Some(job) = job.and_then(|id| shell.jobs.get_mut(id))
+            && job.state == JobState::Stopped
+            && [parse_signal("TERM"), parse_signal("HUP")].contains(&Some(signal))
+        {
+            job.resume();
+            job.state = JobState::Running;
+        }
+    }
+    status
+}
+
+/// timeout 内置命令：`timeout DURATION command [args...]` 执行命令，超过时限就结束它并返回 124（与 GNU timeout 一致）。
+/// 时限可以是小数，单位后缀 s、m、h、d 分别表示秒、分、时、天，为 0 时不限时。
+/// 命令使用 timeout 自己的重定向；在管道中时它的输出先被收集起来，再交给 executor 写入管道。
+fn run_timeout(ctx: &mut Context) -> i32 {
+    let (limit, command) = match ctx.cmd.args.as_slice() {
+        [duration, command @ ..] if !command.is_empty() => match parse_duration(duration) {
+            Some(limit) => (limit, command),
+            None => {
+                eprintln!("timeout: 无效的时间间隔: {}", duration);
+                return 125;
+            },
+        },
+        _ => {
+            eprintln!("timeout: 用法: timeout 时长 命令 [参数...]");
+            return 125;
+        },
+    };
+    let mut inner = ctx.cmd.clone();
+    inner.name = command[0].clone();
+    inner.args = command[1..].to_vec();
+    inner.background = false;
+
+    let mut status = 0;
+    let mut run = |shell: &mut Shell| {
+        let running = executor::spawn_pipeline(std::slice::from_ref(&inner), shell);
+        status = if limit.is_zero() || running.failure().is_some() {
+            running.wait().last
+        } else {
+            running.wait_timeout(limit, TIMEOUT_GRACE).unwrap_or(124)
+        };
+    };
+    if ctx.subshell {
+        let output = ctx.shell.capture_bytes(run);
+        let _ = ctx.out.write_all(&output);
+    } else {
+        run(ctx.shell);
+    }
+    status
+}
+
+/// time 内置命令：执行命令，结束后在 stderr 上报告它用的时间，返回命令的退出状态。
+/// 管道前的 `time` 由 run_command_line 统计整条管道，这里处理管道中间的 `time` 和没有参数的 `time`
+fn run_time(ctx: &mut Context) -> i32 {
+    let stopwatch = Stopwatch::start();
+    let mut status = 0;
+    if let Some((name, args)) = ctx.cmd.args.split_first() {
+        let mut inner = ctx.cmd.clone();
+        inner.name = name.clone();
+        inner.args = args.to_vec();
+        inner.background = false;
+        let mut run = |shell: &mut Shell| {
+            status = executor::spawn_pipeline(std::slice::from_ref(&inner), shell).wait().last;
+        };
+        if ctx.subshell {
+            let output = ctx.shell.capture_bytes(run);
+            let _ = ctx.out.write_all(&output);
+        } else {
+            run(ctx.shell);
+        }
+    }
+    stopwatch.report();
+    status
+}
+
+/// export 内置命令：`export NAME=value` 设置环境变量，之后启动的命令都会继承它；
+/// `export NAME` 把已有的 Shell 变量移到环境中；无参数时按名字排序列出所有导出的变量。
+fn run_export(shell: &mut Shell, args: &[String], out: &mut dyn Write) -> i32 {
+    if args.is_empty() {
+        let mut vars: Vec<(String, String)> = env::vars_os()
+            .map(|(name, value)| (name.to_string_lossy().into_owned(), value.to_string_lossy().into_owned()))
+            .collect();
+        vars.sort();
+        for (name, value) in vars {
+            let _ = writeln!(out, "declare -x {}=\"{}\"", name, escape_double_quoted(&value));
+        }
+        return 0;
+    }
+
+    let mut status = 0;
+    for arg in args {
+        let (name, value) = match arg.split_once('=') {
+            Some((name, value)) => (name, Some(value)),
+            None => (arg.as_str(), None),
+        };
+        if !parser::is_identifier(name) {
+            eprintln!("export: `{}': 不是有效的标识符", arg);
+            status = 1;
+            continue;
+        }
+        let shell_value = shell.variables.remove(name);
+        if let Some(value) = value.map(str::to_string).or(shell_value) {
+            shell.set_env(name, value);
+        }
+    }
+    status
+}
+
+/// unset 内置命令：删除给出的所有变量，和 bash 一样变量不存在时也算成功
+fn run_unset(shell: &mut Shell, args: &[String]) -> i32 {
+    let mut status = 0;
+    for name in args {
+        if !parser::is_identifier(name) {
+            eprintln!("unset: `{}': 不是有效的标识符", name);
+            status = 1;
+            continue;
+        }
+        shell.variables.remove(name);
+        shell.remove_env(name);
+    }
+    status
+}
+
+/// alias 内置命令：`alias name=value` 定义别名，`alias name` 打印它的定义，
+/// 无参数时按名字排序列出所有别名
+fn run_alias(shell: &mut Shell, args: &[String], out: &mut dyn Write) -> i32 {
+    if args.is_empty() {
+        let mut names: Vec<&String> = shell.aliases.keys().collect();
+        names.sort();
+        for name in names {
+            let _ = writeln!(out, "alias {}={}", name, single_quoted(&shell.aliases[name]));
+        }
+        return 0;
+    }
+
+    let mut status = 0;
+    for arg in args {
+        match arg.split_once('=') {
+            Some((name, value)) => {
+                if name.is_empty() || name.contains(|c: char| c.is_whitespace() || "/$`\\'\"|&;<>()".contains(c)) {
+                    eprintln!("alias: `{}': 无效的别名", name);
+                    status = 1;
+                    continue;
+                }
+                shell.aliases.insert(name.to_string(), value.to_string());
+            },
+            None => match shell.aliases.get(arg) {
+                Some(value) => {
+                    let _ = writeln!(out, "alias {}={}", arg, single_quoted(value));
+                },
+                None => {
+                    eprintln!("alias: {}: 未找到", arg);
+                    status = 1;
+                },
+            },
+        }
+    }
+    status
+}
+
+/// 命令的 `<`、here-document 或 here-string 提供的输入，没有输入重定向时为 None。
+/// 文件打不开时报告错误并返回 Err
+pub(crate) fn redirected_input(cmd: &ParsedCommand) -> Result<Option<Box<dyn BufRead>>, ()> {
+    if let Some(text) = cmd.stdin_text() {
+        return Ok(Some(Box::new(io::Cursor::new(text.into_bytes()))));
+    }
+    let Some(filepath) = &cmd.stdin_redirect else {
+        return Ok(None);
+    };
+    match fs::File::open(filepath) {
+        Ok(file) => Ok(Some(Box::new(io::BufReader::new(file)))),
+        Err(e) => {
+            eprintln!("my_shell: 无法打开输入文件 {}: {}", filepath, e);
+            Err(())
+        },
+    }
+}
+
+/// read 内置命令：读取一行，按空白分割后依次赋给给出的变量，最后一个变量得到剩下的全部内容；
+/// 没有给出变量时整行保存在 `REPLY` 中。`-p prompt` 先在 stderr 上显示提示，
+/// `-r` 不把反斜杠当作转义符。输入来自命令的 `<`、here-document 或 here-string，没有时读取 Shell 的 stdin。
+/// 和 bash 一样，遇到文件结尾时返回 1（已经读到的内容仍然会赋值）。
+fn run_read(shell: &mut Shell, cmd: &ParsedCommand) -> i32 {
+    let mut raw = false;
+    let mut prompt = None;
+    let mut args = cmd.args.iter();
+    let mut names = Vec::new();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-r" if names.is_empty() => raw = true,
+            "-p" if names.is_empty() => match args.next() {
+                Some(text) => prompt = Some(text),
+                None => {
+                    eprintln!("read: -p: 需要参数");
+                    return 2;
+                },
+            },
+            option if names.is_empty() && option.len() > 1 && option.starts_with('-') => {
+                eprintln!("read: {}: 无效的选项", option);
+                return 2;
+            },
+            name if !parser::is_identifier(name) => {
+                eprintln!("read: `{}': 不是有效的标识符", name);
+                return 1;
+            },
+            name => names.push(name.to_string()),
+        }
+    }
+
+    let Ok(input) = redirected_input(cmd) else {
+        return 1;
+    };
+    let mut input = input.unwrap_or_else(|| {
+        if let Some(prompt) = prompt {
+            eprint!("{}", prompt);
+        }
+        Box::new(io::stdin().lock())
+    });
+
+    // 没有 -r 时，行尾的反斜杠把下一行接上，其他反斜杠让下一个字符失去特殊含义
+    let mut line = String::new();
+    let mut complete = false;
+    loop {
+        let mut chunk = String::new();
+        match input.read_line(&mut chunk) {
+            Ok(0) => break,
+            Ok(_) => {},
+            Err(e) => {
+                eprintln!("read: 读取错误: {}", e);
+                return 1;
+            },
+        }
+        let ended = chunk.ends_with('\n');
+        let chunk = chunk.strip_suffix('\n').unwrap_or(&chunk);
+        if !raw && ended && chunk.ends_with('\\') && chunk.chars().rev().take_while(|&c| c == '\\').count() % 2 == 1 {
+            line.push_str(chunk);
+            line.pop();
+            continue;
+        }
+        line.push_str(chunk);
+        complete = ended;
+        break;
+    }
+
+    let values = if names.is_empty() {
+        names.push("REPLY".to_string());
+        vec![if raw { line } else { remove_backslashes(&line) }]
+    } else {
+        split_read_fields(&line, names.len(), raw)
+    };
+    let assignments: Vec<(String, String)> = names
+        .into_iter()
+        .zip(values.into_iter().chain(std::iter::repeat(String::new())))
+        .collect();
+    shell.run_assignments(&assignments);
+    if complete { 0 } else { 1 }
+}
+
+/// type 内置命令：按 Shell 解释命令名的顺序（别名、内置命令、PATH 中的可执行文件）说明每个名字是什么；
+/// 有任何一个名字都找不到时返回 1
+fn run_type(shell: &mut Shell, args: &[String], out: &mut dyn Write) -> i32 {
+    let path_var = env::var("PATH").unwrap_or_default();
+    let mut status = 0;
+    for name in args {
+        let _ = if let Some(value) = shell.aliases.get(name) {
+            writeln!(out, "{} is aliased to `{}'", name, value)
+        } else if is_builtin(name) {
+            writeln!(out, "{} is a shell builtin", name)
+        } else if let Some(path) = find_executable(name, &path_var) {
+            writeln!(out, "{} is {}", name, path.display())
+        } else {
+            eprintln!("type: {}: 未找到", name);
+            status = 1;
+            Ok(())
+        };
+    }
+    status
+}
+
+/// unalias 内置命令：删除给出的别名，`unalias -a` 删除所有别名
+fn run_unalias(shell: &mut Shell, args: &[String]) -> i32 {
+    if args.is_empty() {
+        eprintln!("unalias: 用法: unalias [-a] name [name ...]");
+        return 2;
+    }
+    let mut status = 0;
+    for name in args {
+        if name == "-a" {
+            shell.aliases.clear();
+        } else if shell.aliases.remove(name).is_none() {
+            eprintln!("unalias: {}: 未找到", name);
+            status = 1;
+        }
+    }
+    status
+}
+
+/// source 和 `.` 内置命令：在当前 Shell 中逐行执行文件，其中的 export、cd 等会影响当前会话。
+/// 文件名之后还有参数时，执行期间它们是 `$1`、`$2` ……，否则沿用当前的位置参数。
+/// 某一行出错时只打印错误并继续执行后面的行，返回最后一条命令的退出状态。
+/// 文件中的命令直接写到 Shell 的标准输出，不受 source 命令本身输出重定向的影响。
+fn run_source(shell: &mut Shell, name: &str, args: &[String]) -> i32 {
+    let Some(path) = args.first() else {
+        eprintln!("{}: 需要文件名参数", name);
+        return 2;
+    };
+    let content = match fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(e) => {
+            eprintln!("{}: {}: {}", name, path, e);
+            return 1;
+        }
+    };
+    let saved_args = (args.len() > 1).then(|| std::mem::replace(&mut shell.positional, args[1..].to_vec()));
+    if shell.run_script(&content) == Flow::Exit {
+        shell.exiting = true;
+    }
+    if let Some(saved_args) = saved_args {
+        shell.positional = saved_args;
+    }
+    shell.last_status
+}
+
+/// set 内置命令：`set -o` 列出选项，`set -o name`/`set +o name` 打开或关闭选项，
+/// 也支持 `-n`/`+n` 这样的短选项。
+fn run_set(shell: &mut Shell, args: &[String], out: &mut dyn Write) -> i32 {
+    if args.is_empty() || (args.len() == 1 && args[0] == "-o") {
+        let options = [
+            ("errexit", shell.options.errexit),
+            ("helpcomplete", shell.options.helpcomplete),
+            ("histignorefailed", shell.options.histignorefailed),
+            ("monitor", shell.options.monitor),
+            ("noexec", shell.options.noexec),
+            ("xtrace", shell.options.xtrace),
+        ];
+        for (name, enabled) in options {
+            let _ = writeln!(out, "{:<20}\t{}", name, if enabled { "on" } else { "off" });
+        }
+        return 0;
+    }
+
+    let mut status = 0;
+    let mut i = 0;
+    while i < args.len() {
+        let arg = args[i].as_str();
+        let enable = match arg.chars().next() {
+            Some('-') => true,
+            Some('+') => false,
+            _ => {
+                eprintln!("set: {}: 无效的参数", arg);
+                return 2;
+            }
+        };
+        match &arg[1..] {
+            "o" => {
+                match args.get(i + 1) {
+                    Some(name) => {
+                        if !set_option(shell, name, enable) {
+                            eprintln!("set: {}: 无效的选项名", name);
+                            status = 2;
+                        }
+                    },
+                    None => {
+                        eprintln!("set: {}: 缺少选项名", arg);
+                        status = 2;
+                    },
+                }
+                i += 2;
+                continue;
+            },
+            "e" => shell.options.errexit = enable,
+            "m" => shell.options.monitor = enable,
+            "n" => shell.options.noexec = enable,
+            "x" => shell.options.xtrace = enable,
+            _ => {
+                eprintln!("set: {}: 无效的选项", arg);
+                status = 2;
+            },
+        }
+        i += 1;
+    }
+    status
+}
+
+/// 按长选项名设置选项，选项名未知时返回 false
+fn set_option(shell: &mut Shell, name: &str, enable: bool) -> bool {
+    match name {
+        "noexec" => shell.options.noexec = enable,
+        "errexit" => shell.options.errexit = enable,
+        "helpcomplete" => shell.options.helpcomplete = enable,
+        "histignorefailed" => shell.options.histignorefailed = enable,
+        "monitor" => shell.options.monitor = enable,
+        "xtrace" => shell.options.xtrace = enable,
+        _ => return false,
+    }
+    true
+}
+
+/// exit 的退出状态：默认为上一条命令的状态，数字取低 8 位，不是数字时为 2，参数太多时为 None（不退出）
+pub(crate) fn exit_status(args: &[String], last_status: i32) -> Option<i32> {
+    match args {
+        [] => Some(last_status),
+        [code] => Some(match code.parse::<i64>() {
+            Ok(code) => (code & 0xff) as i32,
+            Err(_) => {
+                eprintln!("exit: {}: 需要数字参数", code);
+                2
+            },
+        }),
+        _ => {
+            eprintln!("exit: 参数太多");
+            None
+        },
+    }
+}
+
+/// reset 和 sane 内置命令：恢复 cooked 模式并发送终端复位序列
+fn run_reset(name: &str) -> i32 {
+    match terminal::reset() {
+        Ok(()) => 0,
+        Err(e) => {
+            eprintln!("{}: {}", name, e);
+            1
+        },
+    }
+}
+
+/// help 内置命令：没有参数时列出所有内置命令和一句话说明，名字按最长的对齐；
+/// `help 名字 ...` 显示这些命令的用法和详细说明，有任何一个名字不是内置命令时返回 1
+fn run_help(args: &[String], out: &mut dyn Write) -> i32 {
+    if args.is_empty() {
+        let width = REGISTRY.iter().map(|spec| spec.name.len()).max().unwrap_or(0);
+        let _ = writeln!(out, "内置命令（输入 `help 名字` 查看详细用法）：");
+        for spec in REGISTRY.iter() {
+            let _ = writeln!(out, "  {:<width$}  {}", spec.name, spec.summary, width = width);
+        }
+        return 0;
+    }
+
+    let mut status = 0;
+    for name in args {
+        let Some(spec) = find_spec(name) else {
+            eprintln!("help: 没有与 `{}' 匹配的帮助主题", name);
+            status = 1;
+            continue;
+        };
+        let _ = writeln!(out, "{}: {}", spec.name, spec.usage);
+        let _ = writeln!(out, "    {}。", spec.summary);
+        if !spec.details.is_empty() {
+            let _ = writeln!(out);
+            for line in spec.details.lines() {
+                let _ = writeln!(out, "    {}", line);
+            }
+        }
+    }
+    status
+}
+
+/// pwd 内置命令：打印当前工作目录
+fn run_pwd(out: &mut dyn Write) -> i32 {
+    match env::current_dir() {
+        Ok(path) => {
+            let _ = writeln!(out, "{}", path.display());
+            0
+        },
+        Err(e) => {
+            eprintln!("pwd: {}", e);
+            1
+        },
+    }
+}
+
+/// echo 内置命令：用空格连接参数并输出换行。
+/// 开头的 `-n` 不输出换行，`-e` 解释转义序列，`-E` 不解释；选项可以合写成 `-ne`。
+/// 没有 `-e`/`-E` 时由 `escapes` 决定是否解释（即 shopt xpg_echo）。
+fn run_echo(args: &[String], mut escapes: bool, out: &mut dyn Write) -> i32 {
+    let mut newline = true;
+    let mut words = args;
+    while let Some(option) = words.first().and_then(|arg| arg.strip_prefix('-')) {
+        if option.is_empty() || !option.chars().all(|c| matches!(c, 'n' | 'e' | 'E')) {
+            break;
+        }
+        for c in option.chars() {
+            match c {
+                'n' => newline = false,
+                'e' => escapes = true,
+                _ => escapes = false,
+            }
+        }
+        words = &words[1..];
+    }
+
+    let mut text = words.join(" ");
+    if escapes {
+        let (decoded, stop) = decode_echo_escapes(&text);
+        text = decoded;
+        newline &= !stop;
+    }
+    if newline {
+        text.push('\n');
+    }
+    match out.write_all(text.as_bytes()) {
+        Ok(()) => 0,
+        Err(e) => {
+            eprintln!("echo: 写入错误: {}", e);
+            1
+        }
+    }
+}
+
+/// 含有 `/` 的名字直接检查该文件，其他名字在 PATH 中查找
+fn find_executable(name: &str, path_var: &str) -> Option<PathBuf> {
+    if name.contains('/') {
+        command_hash::is_executable(std::path::Path::new(name)).then(|| PathBuf::from(name))
+    } else {
+        command_hash::search_path(name, path_var)
+    }
+}
+
+/// umask 内置命令：没有参数时以八进制打印当前的文件创建掩码，`umask 022` 设置新的掩码。
+/// 掩码属于整个进程，之后重定向创建的文件和启动的子进程都会遵守它。
+fn run_umask(args: &[String], out: &mut dyn Write) -> i32 {
+    let result = match args {
+        [] => umask(None).map(|mask| {
+            let _ = writeln!(out, "{:04o}", mask);
+        }),
+        [mode] => match u32::from_str_radix(mode, 8) {
+            Ok(mask) if mask <= 0o777 => umask(Some(mask)).map(|_| ()),
+            _ => Err(format!("{}: 八进制数超出范围", mode)),
+        },
+        _ => Err("参数过多".to_string()),
+    };
+    match result {
+        Ok(()) => 0,
+        Err(e) => {
+            eprintln!("umask: {}", e);
+            1
+        },
+    }
+}
+
+/// timeout 发出 SIGTERM 之后，等待多久再用 SIGKILL 强制结束命令
+const TIMEOUT_GRACE: Duration = Duration::from_secs(2);
+
+/// 解析 timeout 的时限：非负的小数，可以带 s、m、h、d 单位后缀
+fn parse_duration(text: &str) -> Option<Duration> {
+    let (number, unit) = match text.char_indices().last()? {
+        (i, 's') => (&text[..i], 1.0),
+        (i, 'm') => (&text[..i], 60.0),
+        (i, 'h') => (&text[..i], 3600.0),
+        (i, 'd') => (&text[..i], 86400.0),
+        _ => (text, 1.0),
+    };
+    let seconds: f64 = number.parse().ok()?;
+    Duration::try_from_secs_f64(seconds * unit).ok()
+}
+
+/// 把信号编号或名字（可以省略 `SIG` 前缀，不区分大小写）转换成信号编号
+#[cfg(unix)]
+fn parse_signal(name: &str) -> Option<i32> {
+    use nix::sys::signal::Signal;
+    if let Ok(number) = name.parse::<i32>() {
+        return (number == 0 || Signal::try_from(number).is_ok()).then_some(number);
+    }
+    let name = name.to_ascii_uppercase();
+    let name = if name.starts_with("SIG") { name } else { format!("SIG{}", name) };
+    name.parse::<Signal>().ok().map(|signal| signal as i32)
+}
+
+#[cfg(not(unix))]
+fn parse_signal(name: &str) -> Option<i32> {
+    name.parse().ok()
+}
+
+/// 给进程发送信号，信号 0 只检查进程是否存在
+#[cfg(unix)]
+fn send_signal(pid: i32, signal: i32) -> Result<(), String> {
+    use nix::sys::signal::{Signal, kill};
+    use nix::unistd::Pid;
+    let signal = if signal == 0 { None } else { Signal::try_from(signal).ok() };
+    kill(Pid::from_raw(pid), signal).map_err(|e| e.desc().to_string())
+}
+
+#[cfg(not(unix))]
+fn send_signal(_pid: i32, _signal: i32) -> Result<(), String> {
+    Err("当前平台不支持发送信号".to_string())
+}
+
+/// 设置文件创建掩码（为 None 时不修改），返回原来的掩码
+#[cfg(unix)]
+pub(crate) fn umask(mask: Option<u32>) -> Result<u32, String> {
+    use nix::libc::mode_t;
+    use nix::sys::stat::{self, Mode};
+    // umask 系统调用总是会设置新值，只读取时要把原来的值设回去
+    let old = stat::umask(Mode::from_bits_truncate(mask.unwrap_or(0) as mode_t));
+    if mask.is_none() {
+        stat::umask(old);
+    }
+    Ok(old.bits() as u32)
+}
+
+#[cfg(not(unix))]
+pub(crate) fn umask(_mask: Option<u32>) -> Result<u32, String> {
+    Err("当前平台不支持文件创建掩码".to_string())
+}
+
+/// which 内置命令：对每个名字打印在 PATH 中找到的第一个可执行文件的完整路径，
+/// 有任何一个名字找不到时返回 1。
+fn run_which(args: &[String], out: &mut dyn Write) -> i32 {
+    let path_var = env::var("PATH").unwrap_or_default();
+    let mut status = 0;
+    for name in args {
+        match find_executable(name, &path_var) {
+            Some(path) => {
+                let _ = writeln!(out, "{}", path.display());
+            },
+            None => status = 1,
+        }
+    }
+    if args.is_empty() { 1 } else { status }
+}
+
+/// 把 read 读到的一行按空白分成最多 `count` 个字段，最后一个字段保留剩下的内容（去掉首尾空白）。
+/// 没有 `-r` 时被反斜杠转义的空白不分割字段，反斜杠本身被去掉。
+fn split_read_fields(line: &str, count: usize, raw: bool) -> Vec<String> {
+    let mut fields = Vec::new();
+    let mut rest = line.trim_start();
+    while fields.len() + 1 < count && !rest.is_empty() {
+        let mut end = rest.len();
+        let mut chars = rest.char_indices();
+        while let Some((i, c)) = chars.next() {
+            if c == '\\' && !raw {
+                chars.next();
+            } else if c.is_whitespace() {
+                end = i;
+                break;
+            }
+        }
+        fields.push(rest[..end].to_string());
+        rest = rest[end..].trim_start();
+    }
+    if !rest.is_empty() {
+        fields.push(rest.trim_end().to_string());
+    }
+    if raw { fields } else { fields.iter().map(|field| remove_backslashes(field)).collect() }
+}
+
+/// 去掉 read 输入中的转义：`\x` 变成 `x`。输入中的引号是普通字符，所以不能用 parser::remove_quotes
+fn remove_backslashes(text: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => result.extend(chars.next()),
+            _ => result.push(c),
+        }
+    }
+    result
+}
+
+/// test 和 `[` 内置命令：求值条件表达式，真时返回 0，假时返回 1，表达式有错误时返回 2。
+/// `[` 的最后一个参数必须是 `]`。
+fn run_test(name: &str, args: &[String]) -> i32 {
+    let args = if name == "[" {
+        match args.split_last() {
+            Some((last, rest)) if last == "]" => rest,
+            _ => {
+                eprintln!("[: 缺少 `]'");
+                return 2;
+            }
+        }
+    } else {
+        args
+    };
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+    match eval_test(&args) {
+        Ok(true) => 0,
+        Ok(false) => 1,
+        Err(e) => {
+            eprintln!("{}: {}", name, e);
+            2
+        }
+    }
+}
+
+/// 和 bash 一样先按参数个数决定如何解释，这样 `[ -n ]`、`[ = ]` 这类参数恰好是运算符的表达式也能正确求值；
+/// 参数更多时按 `-o`、`-a`、`!` 的优先级从低到高拆分。
+fn eval_test(args: &[&str]) -> Result<bool, String> {
+    match *args {
+        [] => Ok(false),
+        [word] => Ok(!word.is_empty()),
+        ["!", ref rest @ ..] if args.len() <= 3 && !(args.len() == 3 && is_binary_test(args[1])) => {
+            eval_test(rest).map(|result| !result)
+        },
+        [op, operand] => eval_unary_test(op, operand),
+        [left, op, right] if is_binary_test(op) => eval_binary_test(left, op, right),
+        ["(", ref inner @ .., ")"] if inner.len() <= 2 => eval_test(inner),
+        [_, op, _] if op != "-a" && op != "-o" => Err(format!("{}: 需要二元表达式", op)),
+        _ => {
+            for op in ["-o", "-a"] {
+                if let Some(i) = args.iter().rposition(|arg| *arg == op).filter(|&i| i > 0 && i < args.len() - 1) {
+                    let (left, right) = (eval_test(&args[..i])?, eval_test(&args[i + 1..])?);
+                    return Ok(if op == "-o" { left || right } else { left && right });
+                }
+            }
+            match *args {
+                ["!", ref rest @ ..] => eval_test(rest).map(|result| !result),
+                ["(", ref inner @ .., ")"] => eval_test(inner),
+                _ => Err("参数太多".to_string()),
+            }
+        },
+    }
+}
+
+fn is_binary_test(op: &str) -> bool {
+    matches!(op, "=" | "==" | "!=" | "-eq" | "-ne" | "-lt" | "-le" | "-gt" | "-ge")
+}
+
+/// 一元表达式：文件测试和字符串是否为空
+fn eval_unary_test(op: &str, operand: &str) -> Result<bool, String> {
+    let metadata = || fs::metadata(operand);
+    Ok(match op {
+        "-e" => metadata().is_ok(),
+        "-f" => metadata().is_ok_and(|m| m.is_file()),
+        "-d" => metadata().is_ok_and(|m| m.is_dir()),
+        "-s" => metadata().is_ok_and(|m| m.len() > 0),
+        "-x" => command_hash::is_executable(std::path::Path::new(operand)),
+        "-L" | "-h" => fs::symlink_metadata(operand).is_ok_and(|m| m.file_type().is_symlink()),
+        "-z" => operand.is_empty(),
+        "-n" => !operand.is_empty(),
+        _ => return Err(format!("{}: 需要一元表达式", op)),
+    })
+}
+
+/// 二元表达式：字符串比较和整数比较
+fn eval_binary_test(left: &str, op: &str, right: &str) -> Result<bool, String> {
+    let integer = |word: &str| word.trim().parse::<i64>().map_err(|_| format!("{}: 需要整数表达式", word));
+    Ok(match op {
+        "=" | "==" => left == right,
+        "!=" => left != right,
+        _ => {
+            let (left, right) = (integer(left)?, integer(right)?);
+            match op {
+                "-eq" => left == right,
+                "-ne" => left != right,
+                "-lt" => left < right,
+                "-le" => left <= right,
+                "-gt" => left > right,
+                _ => left >= right,
+            }
+        },
+    })
+}
+
+/// 解释 echo -e 的转义序列：`\n`、`\t`、`\\`、`\0nnn`（八进制）、`\xHH`（十六进制）等。
+/// `\c` 之后的内容都不输出，此时返回的第二个值为 true；不认识的转义序列原样保留。
+fn decode_echo_escapes(text: &str) -> (String, bool) {
+    let mut result = String::new();
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            result.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('a') => result.push('\u{07}'),
+            Some('b') => result.push('\u{08}'),
+            Some('c') => return (result, true),
+            Some('e' | 'E') => result.push('\u{1b}'),
+            Some('f') => result.push('\u{0c}'),
+            Some('n') => result.push('\n'),
+            Some('r') => result.push('\r'),
+            Some('t') => result.push('\t'),
+            Some('v') => result.push('\u{0b}'),
+            Some('\\') => result.push('\\'),
+            Some(radix_char @ ('0' | 'x')) => {
+                let (radix, max) = if radix_char == '0' { (8, 3) } else { (16, 2) };
+                let mut value = 0;
+                let mut len = 0;
+                while len < max && let Some(digit) = chars.peek().and_then(|c| c.to_digit(radix)) {
+                    value = value * radix + digit;
+                    len += 1;
+                    chars.next();
+                }
+                if radix_char == 'x' && len == 0 {
+                    result.push_str("\\x");
+                } else {
+                    result.push(char::from_u32(value).unwrap_or('\u{fffd}'));
+                }
+            },
+            Some(other) => {
+                result.push('\\');
+                result.push(other);
+            },
+            None => result.push('\\'),
+        }
+    }
+    (result, false)
+}
+
+/// 在 CDPATH 中查找 `dir`：依次尝试每一项下的 `dir`，空的项表示当前目录。
+/// 找到时返回这个目录，以及它是否来自非空的项（和 bash 一样，这时 cd 要打印新的目录）
+fn search_cdpath(dir: &str, cdpath: &str) -> Option<(PathBuf, bool)> {
+    cdpath.split(':').find_map(|entry| {
+        let candidate = if entry.is_empty() { PathBuf::from(dir) } else { Path::new(entry).join(dir) };
+        candidate.is_dir().then_some((candidate, !entry.is_empty()))
+    })
+}
+
+/// 用单引号括起来，其中的单引号写成 `'\''`
+fn single_quoted(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "'\\''"))
+}
+
+/// 在 `"` 和 `\`、`$`、`` ` `` 前加上反斜杠，使值可以放进双引号中原样读回
+fn escape_double_quoted(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        if matches!(c, '"' | '\\' | '$' | '`') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// 把路径第一段的 `...` 展开成 `../..`，`....` 展开成 `../../..`，以此类推
+fn expand_dots(path: &str) -> String {
+    let (first, rest) = match path.find('/') {
+        Some(i) => path.split_at(i),
+        None => (path, ""),
+    };
+    if first.len() < 3 || !first.chars().all(|c| c == '.') {
+        return path.to_string();
+    }
+    format!("{}{}", vec![".."; first.len() - 1].join("/"), rest)
+}
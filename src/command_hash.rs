@@ -0,0 +1,109 @@
+//! command_hash.rs
+
+use std::collections::HashMap;
+use std::env;
+use std::path::{Path, PathBuf};
+
+/// 命令名到可执行文件绝对路径的缓存（即 bash 的 hash 表）。
+/// `std::process::Command` 每次启动都会重新搜索 PATH，命中缓存后直接使用绝对路径可以省掉这一步。
+/// 缓存记录建立时的 PATH 值，PATH 被修改后自动清空，新目录立即生效。
+#[derive(Debug, Default)]
+pub struct CommandHash {
+    path: String,                              // 建立缓存时的 PATH
+    entries: HashMap<String, (PathBuf, usize)>, // 命令名 -> (绝对路径, 命中次数)
+}
+
+impl CommandHash {
+    pub fn new() -> Self {
+        CommandHash::default()
+    }
+
+    /// 按当前环境变量 PATH 查找命令
+    pub fn resolve(&mut self, name: &str) -> Option<PathBuf> {
+        let path_var = env::var("PATH").unwrap_or_default();
+        self.resolve_in(name, &path_var)
+    }
+
+    /// 在给定的 PATH 中查找命令，返回可执行文件的绝对路径。
+    /// 含有 `/` 的名字不经过 PATH 搜索，返回 None 让调用者按原样使用。
+    pub fn resolve_in(&mut self, name: &str, path_var: &str) -> Option<PathBuf> {
+        if name.is_empty() || name.contains('/') {
+            return None;
+        }
+        if self.path != path_var {
+            self.entries.clear();
+            self.path = path_var.to_string();
+        }
+
+        if let Some((path, hits)) = self.entries.get_mut(name) {
+            // 可执行文件可能已被删除或移动，此时重新搜索
+            if is_executable(path) {
+                *hits += 1;
+                return Some(path.clone());
+            }
+            self.entries.remove(name);
+        }
+
+        let found = search_path(name, path_var)?;
+        self.entries.insert(name.to_string(), (found.clone(), 1));
+        Some(found)
+    }
+
+    /// 清空缓存 (hash -r)
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    /// 按命令名排序返回 (命令名, 路径, 命中次数)，供 hash 内置命令列出
+    pub fn entries(&self) -> Vec<(&str, &Path, usize)> {
+        let mut entries: Vec<(&str, &Path, usize)> = self
+            .entries
+            .iter()
+            .map(|(name, (path, hits))| (name.as_str(), path.as_path(), *hits))
+            .collect();
+        entries.sort();
+        entries
+    }
+}
+
+/// 依次在 PATH 的各个目录中查找可执行文件。
+/// 命令执行、hash、which 和 type 都通过它搜索 PATH，保证结果一致。
+pub fn search_path(name: &str, path_var: &str) -> Option<PathBuf> {
+    env::split_paths(path_var)
+        .flat_map(|dir| candidates(&dir, name))
+        .find(|candidate| is_executable(candidate))
+}
+
+/// 目录中可能对应命令名的文件
+#[cfg(not(windows))]
+fn candidates(dir: &Path, name: &str) -> Vec<PathBuf> {
+    vec![dir.join(name)]
+}
+
+/// Windows 上没有扩展名的命令名还要依次加上 PATHEXT 中的扩展名（`.COM;.EXE;.BAT;...`）
+#[cfg(windows)]
+fn candidates(dir: &Path, name: &str) -> Vec<PathBuf> {
+    let mut candidates = vec![dir.join(name)];
+    if Path::new(name).extension().is_none() {
+        let pathext = env::var("PATHEXT").unwrap_or_else(|_| ".COM;.EXE;.BAT;.CMD".to_string());
+        candidates.extend(pathext.split(';').filter(|ext| !ext.is_empty()).map(|ext| dir.join(format!("{}{}", name, ext))));
+    }
+    candidates
+}
+
+/// Unix 上要求是普通文件且带有执行权限；其他平台只检查文件是否存在
+pub fn is_executable(path: &Path) -> bool {
+    let metadata = match path.metadata() {
+        Ok(metadata) => metadata,
+        Err(_) => return false,
+    };
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        metadata.is_file() && metadata.permissions().mode() & 0o111 != 0
+    }
+    #[cfg(not(unix))]
+    {
+        metadata.is_file()
+    }
+}
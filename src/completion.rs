@@ -0,0 +1,285 @@
+//! completion.rs
+
+use std::cell::{RefCell, RefMut};
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::io::Read;
+use std::ops::Range;
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::command_hash::is_executable;
+use crate::parser;
+
+/// 运行 `--help` 的最长等待时间，超时的命令被杀掉，视为没有可补全的选项
+const HELP_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// 从 `command --help` 的输出中抓取长选项，按命令缓存。
+/// 运行 `--help` 有延迟也可能有副作用，所以只在 `set -o helpcomplete` 打开时使用。
+#[derive(Debug, Default)]
+pub struct HelpOptionCache {
+    cache: RefCell<HashMap<String, Vec<String>>>,
+}
+
+impl HelpOptionCache {
+    pub fn new() -> Self {
+        HelpOptionCache::default()
+    }
+
+    /// 返回命令支持的长选项（已排序、去重），第一次查询时运行 `command --help`
+    pub fn options_for(&self, command: &str) -> Vec<String> {
+        if let Some(options) = self.cache.borrow().get(command) {
+            return options.clone();
+        }
+        let options = run_help(command).map(|text| scrape_long_options(&text)).unwrap_or_default();
+        self.cache.borrow_mut().insert(command.to_string(), options.clone());
+        options
+    }
+}
+
+/// PATH 中所有可执行文件的名字，用于补全命令名和高亮输入中的命令名。
+/// 扫描 PATH 的各个目录比较慢，所以只在第一次使用时扫描；PATH 被修改后重新扫描。
+#[derive(Debug, Default)]
+pub struct CommandNameCache {
+    cache: RefCell<Option<(String, Vec<String>)>>, // (扫描时的 PATH, 排序后的命令名)
+}
+
+impl CommandNameCache {
+    pub fn new() -> Self {
+        CommandNameCache::default()
+    }
+
+    /// 以 `prefix` 开头的内置命令和当前 PATH 中的命令，已排序、去重
+    pub fn complete(&self, prefix: &str, builtins: &[&str]) -> Vec<String> {
+        let path_var = env::var("PATH").unwrap_or_default();
+        self.complete_in(prefix, builtins, &path_var)
+    }
+
+    /// 在给定的 PATH 中补全命令名
+    pub fn complete_in(&self, prefix: &str, builtins: &[&str], path_var: &str) -> Vec<String> {
+        let cache = self.scan(path_var);
+        let executables = cache.as_ref().map(|(_, names)| names.as_slice()).unwrap_or_default();
+
+        let mut names: Vec<String> = builtins
+            .iter()
+            .map(|name| name.to_string())
+            .chain(executables.iter().cloned())
+            .filter(|name| name.starts_with(prefix))
+            .collect();
+        names.sort();
+        names.dedup();
+        names
+    }
+
+    /// 当前 PATH 中是否有这个名字的可执行文件
+    pub fn contains(&self, name: &str) -> bool {
+        let path_var = env::var("PATH").unwrap_or_default();
+        self.contains_in(name, &path_var)
+    }
+
+    /// 给定的 PATH 中是否有这个名字的可执行文件。每次按键都会调用，所以只查缓存，不访问文件系统
+    pub fn contains_in(&self, name: &str, path_var: &str) -> bool {
+        let cache = self.scan(path_var);
+        cache.as_ref().is_some_and(|(_, names)| names.binary_search_by(|probe| probe.as_str().cmp(name)).is_ok())
+    }
+
+    /// 找不到命令时给出的建议：与 `name` 编辑距离最小（不超过 2）的内置命令或 PATH 中的命令
+    pub fn closest(&self, name: &str, builtins: &[&str]) -> Option<String> {
+        let path_var = env::var("PATH").unwrap_or_default();
+        self.closest_in(name, builtins, &path_var)
+    }
+
+    /// 在给定的 PATH 中查找最接近的命令名。距离相同时取字典序最小的名字；
+    /// 距离不能达到名字本身的长度，否则 `ab` 这样的短名字几乎能匹配任何两个字母的命令
+    pub fn closest_in(&self, name: &str, builtins: &[&str], path_var: &str) -> Option<String> {
+        let cache = self.scan(path_var);
+        let executables = cache.as_ref().map(|(_, names)| names.as_slice()).unwrap_or_default();
+        let limit = MAX_SUGGESTION_DISTANCE.min(name.chars().count().saturating_sub(1));
+
+        builtins
+            .iter()
+            .copied()
+            .chain(executables.iter().map(String::as_str))
+            .filter(|candidate| *candidate != name)
+            .filter_map(|candidate| Some((edit_distance(name, candidate, limit)?, candidate)))
+            .min()
+            .map(|(_, candidate)| candidate.to_string())
+    }
+
+    /// 缓存不存在或者 PATH 改变时重新扫描
+    fn scan(&self, path_var: &str) -> RefMut<'_, Option<(String, Vec<String>)>> {
+        let mut cache = self.cache.borrow_mut();
+        if cache.as_ref().is_none_or(|(path, _)| path != path_var) {
+            *cache = Some((path_var.to_string(), scan_path(path_var)));
+        }
+        cache
+    }
+}
+
+/// 建议的命令名与输入之间允许的最大编辑距离
+const MAX_SUGGESTION_DISTANCE: usize = 2;
+
+/// 两个字符串之间的编辑距离，超过 `limit` 时返回 None。
+/// 在 Levenshtein 距离（插入、删除、替换各算一步）的基础上把相邻两个字符互换也算一步，
+/// 否则 `gti` 与 `git` 的距离是 2，和许多毫不相干的三个字母的命令一样远
+pub fn edit_distance(a: &str, b: &str, limit: usize) -> Option<usize> {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    if a.len().abs_diff(b.len()) > limit {
+        return None;
+    }
+    // 动态规划表只保留最近的两行
+    let mut before: Vec<usize> = Vec::new();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for i in 0..a.len() {
+        let mut current = vec![i + 1; b.len() + 1];
+        for j in 0..b.len() {
+            let mut distance = (previous[j] + usize::from(a[i] != b[j])).min(previous[j + 1] + 1).min(current[j] + 1);
+            if i > 0 && j > 0 && a[i] == b[j - 1] && a[i - 1] == b[j] {
+                distance = distance.min(before[j - 1] + 1);
+            }
+            current[j + 1] = distance;
+        }
+        before = std::mem::replace(&mut previous, current);
+    }
+    Some(previous[b.len()]).filter(|&distance| distance <= limit)
+}
+
+/// 行首命令名所在的字节范围：跳过开头的空白和 `NAME=value` 形式的赋值，
+/// 命令名在空白或者 `|`、`;`、`&`、`<`、`>` 处结束。行中没有命令名时返回 None。
+pub fn command_word_span(line: &str) -> Option<Range<usize>> {
+    let mut start = 0;
+    loop {
+        start += line[start..].len() - line[start..].trim_start().len();
+        let rest = &line[start..];
+        let len = rest.find(|c: char| c.is_whitespace() || "|;&<>".contains(c)).unwrap_or(rest.len());
+        if len == 0 {
+            return None;
+        }
+        let word = &rest[..len];
+        let is_assignment = word.split_once('=').is_some_and(|(name, _)| parser::is_identifier(name));
+        if !is_assignment {
+            return Some(start..start + len);
+        }
+        start += len;
+    }
+}
+
+/// 列出 PATH 各个目录中的可执行文件名（已排序、去重），无法读取的目录直接跳过
+fn scan_path(path_var: &str) -> Vec<String> {
+    let mut names: Vec<String> = env::split_paths(path_var)
+        .filter_map(|dir| fs::read_dir(dir).ok())
+        .flatten()
+        .filter_map(Result::ok)
+        .filter(|entry| is_executable(&entry.path()))
+        .filter_map(|entry| entry.file_name().into_string().ok())
+        .collect();
+    names.sort();
+    names.dedup();
+    names
+}
+
+/// 判断从 `start` 开始的单词是否处于命令名的位置：
+/// 它是整行、或者 `|`、`;`、`&` 之后的第一个单词（前面可以有取反的 `!` 和 `time`）
+pub fn is_command_position(line: &str, start: usize) -> bool {
+    let segment_start = line[..start].rfind(['|', ';', '&']).map_or(0, |i| i + 1);
+    line[segment_start..start].split_whitespace().all(|word| word == "!" || word == "time")
+}
+
+/// 返回从 `start` 开始的单词所在命令的命令名：同一个 `|`、`;`、`&` 分段中跳过 `!` 和 `time` 之后的第一个单词。
+/// `start` 本身处于命令名的位置时返回 None
+pub fn segment_command(line: &str, start: usize) -> Option<&str> {
+    let segment_start = line[..start].rfind(['|', ';', '&']).map_or(0, |i| i + 1);
+    line[segment_start..start].split_whitespace().find(|word| *word != "!" && *word != "time")
+}
+
+/// 光标前是 `$NAME` 或 `${NAME` 的一部分时补全变量名：返回变量名开始的字节位置和候选的替换文本。
+/// `${` 形式的候选带上结束的 `}`。候选来自 `names`（环境变量和 Shell 变量），已排序、去重。
+/// 被反斜杠转义的 `$` 不是变量引用，返回 None。
+pub fn complete_variable(line: &str, pos: usize, names: &[String]) -> Option<(usize, Vec<String>)> {
+    let before = &line[..pos];
+    let start = before.trim_end_matches(|c: char| c.is_ascii_alphanumeric() || c == '_').len();
+    let (dollar, braced) = match before[..start].strip_suffix("${") {
+        Some(rest) => (rest.len(), true),
+        None => (before[..start].strip_suffix('$')?.len(), false),
+    };
+    if before[..dollar].ends_with('\\') {
+        return None;
+    }
+    let prefix = &before[start..];
+    let mut candidates: Vec<String> = names
+        .iter()
+        .filter(|name| name.starts_with(prefix))
+        .map(|name| if braced { format!("{}}}", name) } else { name.clone() })
+        .collect();
+    candidates.sort();
+    candidates.dedup();
+    Some((start, candidates))
+}
+
+/// 在帮助文本中查找 `--word` 形式的长选项，相当于正则 `--[A-Za-z0-9][A-Za-z0-9-]*`。
+/// 选项必须出现在单词开头，`=ARG` 之类的后缀不包含在结果中。
+pub fn scrape_long_options(help_text: &str) -> Vec<String> {
+    let chars: Vec<char> = help_text.chars().collect();
+    let mut options = Vec::new();
+    let mut i = 0;
+
+    while i + 2 < chars.len() {
+        let at_word_start = i == 0 || !(chars[i - 1].is_ascii_alphanumeric() || chars[i - 1] == '-');
+        if at_word_start && chars[i] == '-' && chars[i + 1] == '-' && chars[i + 2].is_ascii_alphanumeric() {
+            let len = chars[i + 2..]
+                .iter()
+                .take_while(|c| c.is_ascii_alphanumeric() || **c == '-')
+                .count();
+            options.push(chars[i..i + 2 + len].iter().collect::<String>());
+            i += 2 + len;
+        } else {
+            i += 1;
+        }
+    }
+
+    options.sort();
+    options.dedup();
+    options
+}
+
+/// 运行 `command --help`，合并 stdout 和 stderr 的输出（有些程序把帮助写到 stderr）
+fn run_help(command: &str) -> Option<String> {
+    let mut child = Command::new(command)
+        .arg("--help")
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .ok()?;
+
+    // 在后台线程中读取输出，避免输出填满管道后子进程阻塞
+    let stdout = read_in_background(child.stdout.take()?);
+    let stderr = read_in_background(child.stderr.take()?);
+
+    let deadline = Instant::now() + HELP_TIMEOUT;
+    loop {
+        match child.try_wait() {
+            Ok(Some(_)) => break,
+            Ok(None) if Instant::now() < deadline => thread::sleep(Duration::from_millis(10)),
+            _ => {
+                let _ = child.kill();
+                let _ = child.wait();
+                return None;
+            }
+        }
+    }
+    let mut text = stdout.join().ok()?;
+    text.push_str(&stderr.join().ok()?);
+    Some(text)
+}
+
+fn read_in_background(mut source: impl Read + Send + 'static) -> thread::JoinHandle<String> {
+    thread::spawn(move || {
+        let mut bytes = Vec::new();
+        let _ = source.read_to_end(&mut bytes);
+        String::from_utf8_lossy(&bytes).into_owned()
+    })
+}
@@ -0,0 +1,31 @@
+//! debug.rs
+
+use std::env;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// 打开调试跟踪：命令行传入 `--debug`，或者环境变量 RUSTSHELL_DEBUG 为非空且不为 0
+pub fn init(debug_flag: bool) {
+    let from_env = env::var("RUSTSHELL_DEBUG").is_ok_and(|value| !value.is_empty() && value != "0");
+    set_enabled(debug_flag || from_env);
+}
+
+pub fn set_enabled(enabled: bool) {
+    ENABLED.store(enabled, Ordering::Relaxed);
+}
+
+pub fn enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+/// 调试跟踪：打开时把格式化后的信息以 `[debug]` 前缀输出到 stderr，关闭时不做格式化
+macro_rules! trace {
+    ($($arg:tt)*) => {
+        if $crate::debug::enabled() {
+            eprintln!("[debug] {}", format_args!($($arg)*));
+        }
+    };
+}
+
+pub(crate) use trace;
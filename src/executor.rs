@@ -1,115 +1,575 @@
-use std::process::{Command, Stdio, Child};
-use std::fs::File;
-use crate::parser::ParsedCommand;
-
-/// 执行一系列通过管道连接的命令。
-/// 处理 I/O 重定向和管道的连接。
-pub fn execute_pipeline(parsed_commands: &[ParsedCommand]) {
-    let mut children: Vec<Child> = Vec::new();
-    let mut previous_command_stdout: Option<std::process::ChildStdout> = None;
-
-    for (i, parsed_cmd) in parsed_commands.iter().enumerate() {
-        let mut command_builder = Command::new(&parsed_cmd.name);
-        command_builder.args(&parsed_cmd.args);
-
-        // 设置标准输入
-        if let Some(prev_stdout) = previous_command_stdout.take() {
-            // 如果是管道中的后续命令，则将前一个命令的输出作为当前命令的输入
-            command_builder.stdin(prev_stdout);
-        } else if i == 0 {
-            // 如果是管道中的第一个命令，且有输入重定向
-            if let Some(filepath) = &parsed_cmd.stdin_redirect {
-                match File::open(filepath) {
-                    Ok(file) => {
-                        command_builder.stdin(Stdio::from(file));
-                    },
-                    Err(e) => {
-                        eprintln!("my_shell: 无法打开输入文件 {}: {}", filepath, e);
-                        // 如果输入文件无法打开，则清除之前启动的子进程，并中断管道
-                        for c in children.iter_mut() {
-                            let _ = c.kill(); 
-                        }
-                        children.clear();
-                        break; 
-                    }
-                }
-            }
-        }
-
-        // 设置标准输出
-        // 如果不是管道中的最后一个命令，则管道输出到下一个命令
-        if i < parsed_commands.len() -1 {
-            command_builder.stdout(Stdio::piped());
-        } else if let Some((filepath, append)) = &parsed_cmd.stdout_redirect {
-            // 如果是管道中的最后一个命令，且有输出重定向
-            let file_result = if *append {
-                File::options().create(true).append(true).open(filepath)
-            } else {
-                File::create(filepath)
-            };
-            match file_result {
-                Ok(file) => {
-                    command_builder.stdout(Stdio::from(file));
-                },
-                Err(e) => {
-                    eprintln!("my_shell: 无法打开输出文件 {}: {}", filepath, e);
-                    // 如果输出文件无法打开，则清除之前启动的子进程，并中断管道
-                    for c in children.iter_mut() {
-                        let _ = c.kill(); 
-                    }
-                    children.clear();
-                    break; 
-                }
-            }
-        } else {
-            // 如果没有输出重定向，并且是最后一个命令，则输出到当前 Shell 的 stdout
-            command_builder.stdout(Stdio::inherit());
-        }
-
-        // 设置标准错误（通常不参与管道，直接重定向或继承）
-        if let Some(filepath) = &parsed_cmd.stderr_redirect {
-            match File::create(filepath) { 
-                Ok(file) => {
-                    command_builder.stderr(Stdio::from(file));
-                },
-                Err(e) => {
-                    eprintln!("my_shell: 无法打开错误输出文件 {}: {}", filepath, e);
-                    // 如果错误输出文件无法打开，则清除之前启动的子进程，并中断管道
-                    for c in children.iter_mut() {
-                        let _ = c.kill(); 
-                    }
-                    children.clear();
-                    break; 
-                }
-            }
-        } else {
-            command_builder.stderr(Stdio::inherit());
-        }
-
-        // 尝试执行命令
-        let child_spawn_result = command_builder.spawn();
-
-        match child_spawn_result {
-            Ok(mut child) => {
-                if let Some(stdout) = child.stdout.take() {
-                    previous_command_stdout = Some(stdout);
-                }
-                children.push(child);
-            },
-            Err(e) => {
-                eprintln!("my_shell: {}: {}", parsed_cmd.name, e);
-                // 如果命令执行失败，清除之前启动的子进程，并中断管道
-                for c in children.iter_mut() {
-                    let _ = c.kill(); // 尝试杀死子进程
-                }
-                children.clear();
-                break; // 停止处理当前管道中的剩余命令
-            },
-        }
-    }
-
-    // 等待管道中的所有子进程完成
-    for mut child in children.drain(..) {
-        let _ = child.wait(); // 不关心输出，只等待完成
-    }
-} 
\ No newline at end of file
+use std::process::{Command, Stdio, Child, ExitStatus};
+use std::fs::File;
+use std::io::{self, PipeReader, PipeWriter, Write};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+use crate::command_hash::{self, CommandHash};
+use crate::debug::trace;
+use crate::parser::{OutputOperation, ParsedCommand};
+use crate::terminal;
+
+/// dry-run 模式下使用：只打印将要执行的管道，不启动任何进程。
+/// Shell 自身忽略 SIGPIPE，stdout 的读取端（例如 `my_shell -n script | head`）提前退出时
+/// 写入会失败；这时直接丢弃输出，而不是像 println! 那样 panic。
+pub fn print_pipeline(parsed_commands: &[ParsedCommand]) {
+    let _ = writeln!(io::stdout(), "{}", format_pipeline(parsed_commands));
+}
+
+/// 按照命令行的书写形式还原整条管道
+pub fn format_pipeline(parsed_commands: &[ParsedCommand]) -> String {
+    let mut line = String::new();
+    for (i, cmd) in parsed_commands.iter().enumerate() {
+        line.push_str(&cmd.to_string());
+        if i < parsed_commands.len() - 1 {
+            line.push_str(if cmd.pipe_stderr { " |& " } else { " | " });
+        } else if cmd.background {
+            line.push_str(" &");
+        }
+    }
+    line
+}
+
+/// 执行管道时由 Shell 提供的环境：命令路径缓存、命令替换的捕获管道、进程替换的输入管道，以及内置命令
+pub trait PipelineHost {
+    /// 命令名到绝对路径的缓存，避免每次启动都重新搜索 PATH
+    fn command_hash(&mut self) -> &mut CommandHash;
+
+    /// 最后一个命令没有输出重定向时写入这个管道（命令替换用它捕获输出），为 None 时继承 Shell 的 stdout
+    fn capture(&self) -> Option<&PipeWriter> {
+        None
+    }
+
+    /// 第一个命令没有其他输入来源时从这个管道读取（`>(...)` 进程替换用它接收外层命令的输出），为 None 时继承 Shell 的 stdin
+    fn input(&self) -> Option<&PipeReader> {
+        None
+    }
+
+    /// 是否打开作业控制：每条管道在自己的进程组中运行，前台管道运行期间占有终端
+    fn job_control(&self) -> bool {
+        false
+    }
+
+    /// 找不到命令 `name` 时建议的正确命令名，没有足够接近的名字时返回 None
+    fn suggest_command(&self, _name: &str) -> Option<String> {
+        None
+    }
+
+    /// 如果 `cmd` 是内置命令或子 Shell 组就执行它，输出写入 `out` 并返回退出状态；是外部命令时返回 None。
+    /// `subshell` 为 true（多个命令的管道或后台运行）时，内置命令对 Shell 状态的修改不应保留。
+    fn run_builtin(&mut self, _cmd: &ParsedCommand, _out: &mut dyn Write, _subshell: bool) -> Option<i32> {
+        None
+    }
+}
+
+/// 只执行外部命令
+impl PipelineHost for CommandHash {
+    fn command_hash(&mut self) -> &mut CommandHash {
+        self
+    }
+}
+
+/// 管道执行完毕后各阶段的退出状态
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PipelineResult {
+    pub statuses: Vec<i32>, // 按顺序排列的各阶段状态（即 bash 的 PIPESTATUS），被信号终止时为 128 + 信号值
+    pub last: i32,          // 整条管道的状态，即最后一个阶段的状态
+}
+
+impl PipelineResult {
+    /// 只有一个状态的结果，用于没有真正启动管道的情况（例如 dry-run、子 Shell 组）
+    pub fn single(status: i32) -> PipelineResult {
+        PipelineResult { statuses: vec![status], last: status }
+    }
+}
+
+/// 已经启动的管道：各阶段的子进程，以及正在写入 here-document 正文或内置命令输出的线程
+#[derive(Debug)]
+pub struct RunningPipeline {
+    pub children: Vec<Child>,
+    foreground: bool,            // 作业控制下占有终端的前台管道，结束后 Shell 要收回终端
+    writers: Vec<JoinHandle<()>>,
+    stages: Vec<Option<i32>>,    // 每个已处理阶段的状态：内置命令和出错的阶段已经确定，外部命令为 None，结束后从子进程取得
+    failure: Option<i32>,        // 第一个无法启动的阶段的状态
+}
+
+impl RunningPipeline {
+    /// 等待管道中的所有子进程完成，返回各阶段的状态，管道的状态取最后一个命令的状态。
+    /// 因为重定向失败或命令无法启动而没有执行的阶段，记录的是出错时确定的状态（127、126 或 1）。
+    pub fn wait(self) -> PipelineResult {
+        match self.finish(false) {
+            Ok(result) => result,
+            Err(_) => unreachable!("不检测停止时不会返回停止的进程"),
+        }
+    }
+
+    /// 和 `wait` 一样等待管道完成，但作业控制下的前台管道被 Ctrl-Z (SIGTSTP) 停止时不再等待：
+    /// Shell 收回终端，返回 Err(管道中的所有子进程)，由调用者登记成停止的作业。
+    pub fn wait_or_stop(self) -> Result<PipelineResult, Vec<Child>> {
+        let detect_stop = self.foreground;
+        self.finish(detect_stop)
+    }
+
+    fn finish(mut self, detect_stop: bool) -> Result<PipelineResult, Vec<Child>> {
+        let mut child_statuses = Vec::new();
+        for child in self.children.iter_mut() {
+            let waited = if detect_stop { wait_child_or_stop(child) } else { wait_child(child).map(ChildWait::Exited) };
+            child_statuses.push(match waited {
+                // 被 SIGPIPE 结束的阶段（下游先退出了）是管道的正常结束方式，只记为 141，不报告错误
+                Ok(ChildWait::Exited(status)) => exit_code(status),
+                Ok(ChildWait::Stopped) => {
+                    terminal::reclaim_terminal();
+                    println!(); // 终端回显的 ^Z 之后换行
+                    return Err(std::mem::take(&mut self.children));
+                },
+                Err(_) => 1,
+            });
+        }
+        for writer in self.writers {
+            let _ = writer.join();
+        }
+        let mut child_statuses = child_statuses.into_iter();
+        let statuses: Vec<i32> = self
+            .stages
+            .iter()
+            .map(|stage| stage.or_else(|| child_statuses.next()).unwrap_or(1))
+            .collect();
+        let last = statuses.last().copied().unwrap_or(0);
+        if self.foreground {
+            terminal::reclaim_terminal();
+            if last == 128 + 2 {
+                println!(); // 被 Ctrl-C (SIGINT) 中断后换行，提示符从新的一行开始
+            }
+        }
+        Ok(PipelineResult { statuses, last })
+    }
+
+    /// 带期限地等待管道结束：超过 `limit` 时给所有进程发送 SIGTERM，
+    /// 再过 `grace` 仍未结束的进程用 SIGKILL 强制结束。
+    /// 按时结束时返回管道的退出状态，超时返回 None。
+    pub fn wait_timeout(mut self, limit: Duration, grace: Duration) -> Option<i32> {
+        let start = Instant::now();
+        let mut terminated = false;
+        loop {
+            let finished = self.children.iter_mut().all(|child| !matches!(child.try_wait(), Ok(None)));
+            if finished {
+                break;
+            }
+            let elapsed = start.elapsed();
+            if !terminated && elapsed >= limit {
+                trace!("timeout: {:?} 已到，发送 SIGTERM", limit);
+                self.children.iter().for_each(terminate);
+                terminated = true;
+            } else if terminated && elapsed >= limit + grace {
+                trace!("timeout: 进程没有响应 SIGTERM，发送 SIGKILL");
+                for child in self.children.iter_mut() {
+                    let _ = child.kill();
+                }
+                break;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        let status = self.wait().last;
+        (!terminated).then_some(status)
+    }
+
+    /// 管道中是否有阶段因为错误而没有启动
+    pub fn failure(&self) -> Option<i32> {
+        self.failure
+    }
+}
+
+/// 执行一系列通过管道连接的命令，返回各阶段的退出状态。
+pub fn execute_pipeline(parsed_commands: &[ParsedCommand], host: &mut dyn PipelineHost) -> PipelineResult {
+    spawn_pipeline(parsed_commands, host).wait()
+}
+
+/// 启动一系列通过管道连接的命令，不等待它们结束。
+/// 处理 I/O 重定向和管道的连接；后台管道的第一个命令没有输入来源时从 /dev/null 读取，
+/// 避免和 Shell 争抢终端输入。
+/// 打开作业控制时整条管道放进一个新的进程组（组号为第一个外部命令的 pid），前台管道同时得到终端。
+/// 内置命令由 `host` 在当前进程中执行，它的输出先写入缓冲区，再由后台线程写到输出的去向，
+/// 这样输出超过管道缓冲区时也不会因为下游命令还没启动而阻塞；内置命令不读取标准输入。
+pub fn spawn_pipeline(parsed_commands: &[ParsedCommand], host: &mut dyn PipelineHost) -> RunningPipeline {
+    let mut children: Vec<Child> = Vec::new();
+    let mut failure: Option<i32> = None;
+    let mut stages: Vec<Option<i32>> = Vec::new();
+    let mut pgid: Option<u32> = None;
+    let mut foreground = false;
+    let job_control = host.job_control();
+    let mut previous_command_stdout: Option<Stdio> = None;
+    let mut writers: Vec<JoinHandle<()>> = Vec::new();
+
+    // 复制一份捕获用的管道和输入管道，执行内置命令时 host 需要被可变借用
+    let pipes = host
+        .capture()
+        .map(PipeWriter::try_clone)
+        .transpose()
+        .and_then(|capture| Ok((capture, host.input().map(PipeReader::try_clone).transpose()?)));
+    let (capture, mut input) = match pipes {
+        Ok(pipes) => pipes,
+        Err(e) => {
+            eprintln!("my_shell: 无法创建管道: {}", e);
+            return RunningPipeline { children, foreground, writers, stages: vec![Some(1)], failure: Some(1) };
+        }
+    };
+
+    for (i, parsed_cmd) in parsed_commands.iter().enumerate() {
+        let is_last = i == parsed_commands.len() - 1;
+        let mut stdin: Option<Stdio> = None;
+
+        // 设置标准输入。和 bash 一样先连接管道，再应用命令自己的重定向，
+        // 所以任何一个阶段上的 `<`、here-document 和 here-string 都优先于管道输入，前一个命令的输出被丢弃
+        let pipe_input = previous_command_stdout.take();
+        if parsed_cmd.herestring.is_some() || !parsed_cmd.heredocs.is_empty() {
+            // here-string 和 here-document 的内容在进程启动后由单独的线程写入
+            // 同一命令有多个 here-document 时，只有最后一个作为标准输入
+            stdin = Some(Stdio::piped());
+        } else if let Some(filepath) = &parsed_cmd.stdin_redirect {
+            match File::open(filepath) {
+                Ok(file) => {
+                    stdin = Some(Stdio::from(file));
+                },
+                Err(e) => {
+                    eprintln!("my_shell: 无法打开输入文件 {}: {}", filepath, e);
+                    skip_stage(&mut stages, &mut previous_command_stdout, &mut failure, 1);
+                    continue;
+                }
+            }
+        } else if pipe_input.is_some() {
+            // 如果是管道中的后续命令，则将前一个命令的输出作为当前命令的输入
+            stdin = pipe_input;
+        } else if i == 0
+            && let Some(reader) = input.take()
+        {
+            stdin = Some(Stdio::from(reader));
+        } else if i == 0 && parsed_cmd.background {
+            stdin = Some(Stdio::null());
+        }
+
+        // 设置标准输出和标准错误
+        let (stdout, stderr) = match connect_outputs(parsed_cmd, is_last, capture.as_ref()) {
+            Ok((out, err, next_stdin)) => {
+                if next_stdin.is_some() {
+                    previous_command_stdout = next_stdin;
+                }
+                (out, err)
+            },
+            Err(e) => {
+                eprintln!("my_shell: {}", e);
+                skip_stage(&mut stages, &mut previous_command_stdout, &mut failure, 1);
+                continue;
+            }
+        };
+
+        // 内置命令：执行后把输出交给后台线程写入 stdout 的去向
+        let mut output = Vec::new();
+        let subshell = parsed_commands.len() > 1 || parsed_cmd.background;
+        if let Some(status) = host.run_builtin(parsed_cmd, &mut output, subshell) {
+            trace!("stage {}: builtin {} -> {}", i, parsed_cmd.name, status);
+            writers.push(write_in_background(stdout.into_writer(), output));
+            stages.push(Some(status));
+            continue;
+        }
+
+        let mut command_builder = build_command(&parsed_cmd.name, &parsed_cmd.assignments, host.command_hash());
+        command_builder.args(&parsed_cmd.args);
+        command_builder.envs(parsed_cmd.assignments.iter().map(|(name, value)| (name, value)));
+        if let Some(stdin) = stdin {
+            command_builder.stdin(stdin);
+        }
+        command_builder.stdout(stdout.into_stdout());
+        command_builder.stderr(stderr);
+        #[cfg(unix)]
+        if job_control {
+            use std::os::unix::process::CommandExt;
+            command_builder.process_group(pgid.unwrap_or(0) as i32);
+        }
+
+        // 尝试执行命令。Rust 运行时让 Shell 自身忽略 SIGPIPE，但 Command 在 exec 之前会把子进程的
+        // SIGPIPE 恢复成默认处理，所以 `yes | head` 中的 yes 在 head 退出后直接被 SIGPIPE 结束，
+        // 不会因为写入失败而报错或者一直运行下去
+        trace!("stage {}: {:?}", i, command_builder);
+        let child_spawn_result = command_builder.spawn();
+
+        match child_spawn_result {
+            Ok(mut child) => {
+                trace!("stage {}: pid {}", i, child.id());
+                if job_control && pgid.is_none() {
+                    pgid = Some(child.id());
+                    if !parsed_cmd.background {
+                        terminal::give_terminal_to(child.id());
+                        foreground = true;
+                    }
+                }
+                if let (Some(text), Some(stdin)) = (parsed_cmd.stdin_text(), child.stdin.take()) {
+                    writers.push(write_in_background(stdin, text.into_bytes()));
+                }
+                children.push(child);
+                stages.push(None);
+            },
+            Err(e) => {
+                // PATH 中找不到命令时，看看是不是拼错了一个相近的命令名
+                let not_found = e.kind() == io::ErrorKind::NotFound && !parsed_cmd.name.contains('/');
+                match not_found.then(|| host.suggest_command(&parsed_cmd.name)).flatten() {
+                    Some(suggestion) => eprintln!("my_shell: {}: 未找到命令，您是想输入 '{}' 吗？", parsed_cmd.name, suggestion),
+                    None => eprintln!("my_shell: {}: {}", parsed_cmd.name, e),
+                }
+                // 与 bash 一致：找不到命令返回 127，找到了但无法执行返回 126
+                let code = if e.kind() == io::ErrorKind::NotFound { 127 } else { 126 };
+                // 丢弃 command_builder 时关闭了这个阶段两端的管道
+                skip_stage(&mut stages, &mut previous_command_stdout, &mut failure, code);
+            },
+        }
+    }
+
+    RunningPipeline { children, foreground, writers, stages, failure }
+}
+
+/// 记录无法启动的阶段的状态。和 bash 一样，管道中的其他命令照常执行：
+/// 前一个阶段写入已经关闭的管道，后一个阶段没有输入管道时从空的输入读到文件结束
+fn skip_stage(stages: &mut Vec<Option<i32>>, next_stdin: &mut Option<Stdio>, failure: &mut Option<i32>, code: i32) {
+    stages.push(Some(code));
+    next_stdin.get_or_insert_with(Stdio::null);
+    failure.get_or_insert(code);
+}
+
+/// 命令输出的去向。除了交给 Command 之外，还可以复制一份给 N>&M 使用，或者供内置命令写入。
+enum OutputTarget {
+    ShellStdout,      // Shell 自己的 stdout（通常是终端）
+    ShellStderr,      // Shell 自己的 stderr
+    File(File),       // 重定向的文件
+    Pipe(PipeWriter), // 连接下一个命令的管道，或命令替换捕获输出的管道
+}
+
+impl OutputTarget {
+    fn try_clone(&self) -> io::Result<OutputTarget> {
+        Ok(match self {
+            OutputTarget::ShellStdout => OutputTarget::ShellStdout,
+            OutputTarget::ShellStderr => OutputTarget::ShellStderr,
+            OutputTarget::File(file) => OutputTarget::File(file.try_clone()?),
+            OutputTarget::Pipe(writer) => OutputTarget::Pipe(writer.try_clone()?),
+        })
+    }
+
+    /// 作为子进程的 stdout
+    fn into_stdout(self) -> Stdio {
+        match self {
+            OutputTarget::ShellStdout => Stdio::inherit(),
+            OutputTarget::ShellStderr => Stdio::from(io::stderr()),
+            OutputTarget::File(file) => Stdio::from(file),
+            OutputTarget::Pipe(writer) => Stdio::from(writer),
+        }
+    }
+
+    /// 供内置命令写入
+    fn into_writer(self) -> Box<dyn Write + Send> {
+        match self {
+            OutputTarget::ShellStdout => Box::new(io::stdout()),
+            OutputTarget::ShellStderr => Box::new(io::stderr()),
+            OutputTarget::File(file) => Box::new(file),
+            OutputTarget::Pipe(writer) => Box::new(writer),
+        }
+    }
+
+    /// 作为子进程的 stderr：Shell 的 stdout 需要复制一份，不能用 inherit
+    fn into_stderr(self) -> Stdio {
+        match self {
+            OutputTarget::ShellStdout => Stdio::from(io::stdout()),
+            OutputTarget::ShellStderr => Stdio::inherit(),
+            target => target.into_stdout(),
+        }
+    }
+}
+
+/// 决定管道中一个命令的 stdout 和 stderr，返回 (stdout 的去向, stderr, 下一个命令的 stdin)。
+/// 和 bash 一样先把 stdout 连接到管道，再按出现顺序应用重定向：不是最后一个命令时 stdout 重定向仍然生效，
+/// 输出写入文件，下一个命令从管道中读到的是空输入。
+/// `N>&M` 复制 fd M 在那一刻的目标，所以 `> file 2>&1` 和 `2>&1 > file` 的结果不同；
+/// `|&` 等价于在所有重定向之后再加上 `2>&1`，stderr 跟随 stdout 最终的去向。
+fn connect_outputs(
+    parsed_cmd: &ParsedCommand,
+    is_last: bool,
+    capture: Option<&PipeWriter>,
+) -> Result<(OutputTarget, Stdio, Option<Stdio>), String> {
+    let mut next_stdin = None;
+
+    // 不考虑重定向时 fd 1 的目标
+    let original = if !is_last {
+        let (reader, writer) = io::pipe().map_err(|e| format!("无法创建管道: {}", e))?;
+        next_stdin = Some(Stdio::from(reader));
+        OutputTarget::Pipe(writer)
+    } else if let Some(writer) = capture {
+        // 命令替换中的最后一个命令：输出写入捕获用的管道
+        OutputTarget::Pipe(writer.try_clone().map_err(|e| format!("无法创建管道: {}", e))?)
+    } else {
+        OutputTarget::ShellStdout
+    };
+
+    let clone_error = |e: io::Error| format!("无法复制文件描述符: {}", e);
+    // fds[0] 是 fd 1，fds[1] 是 fd 2
+    let mut fds = [original, OutputTarget::ShellStderr];
+    // 同一个 fd 的文件只打开一次，之后出现的同类操作复制它
+    let (mut stdout_file, mut stderr_file): (Option<File>, Option<File>) = (None, None);
+    for operation in &parsed_cmd.output_operations {
+        match operation {
+            OutputOperation::StdoutToFile => {
+                let Some((filepath, append)) = &parsed_cmd.stdout_redirect else { continue };
+                let file = reuse_or_open(&mut stdout_file, filepath, *append)
+                    .map_err(|e| format!("无法打开输出文件 {}: {}", filepath, e))?;
+                fds[0] = OutputTarget::File(file);
+            },
+            OutputOperation::StderrToFile => {
+                let Some((filepath, append)) = &parsed_cmd.stderr_redirect else { continue };
+                let file = reuse_or_open(&mut stderr_file, filepath, *append)
+                    .map_err(|e| format!("无法打开错误输出文件 {}: {}", filepath, e))?;
+                fds[1] = OutputTarget::File(file);
+            },
+            OutputOperation::Duplicate { fd, target } => {
+                // 解析器保证 fd 和 target 只会是 1 或 2
+                let duplicated = fds[*target as usize - 1].try_clone().map_err(clone_error)?;
+                fds[*fd as usize - 1] = duplicated;
+            },
+        }
+    }
+
+    let [stdout, stderr] = fds;
+    let stderr = if parsed_cmd.pipe_stderr && !is_last {
+        stdout.try_clone().map_err(clone_error)?
+    } else {
+        stderr
+    };
+
+    Ok((stdout, stderr.into_stderr(), next_stdin))
+}
+
+/// 第一次用到重定向文件时打开它，之后复制已经打开的文件，避免 `>` 把前面写入的内容再次截断
+fn reuse_or_open(opened: &mut Option<File>, filepath: &str, append: bool) -> io::Result<File> {
+    match opened {
+        Some(file) => file.try_clone(),
+        None => opened.insert(open_output(filepath, append)?).try_clone(),
+    }
+}
+
+/// 打开输出重定向的文件：`>` 和 `2>` 截断文件，`>>` 和 `2>>` 追加到末尾，文件不存在时都会创建
+fn open_output(filepath: &str, append: bool) -> io::Result<File> {
+    if append {
+        File::options().create(true).append(true).open(filepath)
+    } else {
+        File::create(filepath)
+    }
+}
+
+/// 创建 Command：缓存中有绝对路径时直接使用，并让程序看到的 argv[0] 仍是用户输入的名字。
+/// 命令前有 `PATH=...` 赋值时和 bash 一样在这个 PATH 中查找，结果不放进缓存
+fn build_command(name: &str, assignments: &[(String, String)], hash: &mut CommandHash) -> Command {
+    let resolved = match assignments.iter().rfind(|(var, _)| var == "PATH") {
+        Some(_) if name.contains('/') => None,
+        Some((_, path_var)) => command_hash::search_path(name, path_var),
+        None => hash.resolve(name),
+    };
+    trace!("spawn: {} -> {}", name, resolved.as_ref().map_or("(由系统搜索 PATH)".to_string(), |p| p.display().to_string()));
+    match resolved {
+        Some(path) => {
+            #[allow(unused_mut)]
+            let mut command = Command::new(path);
+            #[cfg(unix)]
+            {
+                use std::os::unix::process::CommandExt;
+                command.arg0(name);
+            }
+            command
+        },
+        None => Command::new(name),
+    }
+}
+
+/// 请求子进程结束：Unix 上发送 SIGTERM，让它有机会清理；其他平台直接结束进程
+#[cfg(unix)]
+fn terminate(child: &Child) {
+    use nix::sys::signal::{Signal, kill};
+    use nix::unistd::Pid;
+    let _ = kill(Pid::from_raw(child.id() as i32), Signal::SIGTERM);
+}
+
+#[cfg(not(unix))]
+fn terminate(child: &Child) {
+    // Child::kill 需要可变引用，这里通过 taskkill 结束进程
+    let _ = Command::new("taskkill").args(["/F", "/PID", &child.id().to_string()]).status();
+}
+
+/// 前台命令被 Ctrl-Z 停止时 Shell 报告的退出状态，与 bash 一样是 128 + SIGTSTP
+#[cfg(unix)]
+pub const STOPPED_STATUS: i32 = 128 + nix::libc::SIGTSTP;
+#[cfg(not(unix))]
+pub const STOPPED_STATUS: i32 = 128 + 20;
+
+/// 等待子进程的结果
+pub enum ChildWait {
+    Exited(ExitStatus),
+    Stopped, // 被 SIGTSTP 或 SIGSTOP 等信号停止，之后可以用 SIGCONT 继续
+}
+
+/// 等待子进程结束或停止。
+/// 用 WNOWAIT 只查看状态而不回收进程，结束的进程仍由 Child::wait 回收，这样 Child 始终知道自己的退出状态；
+/// 停止的通知则要取走，以后再等待时不会重复报告。
+#[cfg(any(target_os = "linux", target_os = "android", target_os = "freebsd"))]
+pub fn wait_child_or_stop(child: &mut Child) -> io::Result<ChildWait> {
+    use nix::errno::Errno;
+    use nix::sys::wait::{Id, WaitPidFlag, WaitStatus, waitid};
+    use nix::unistd::Pid;
+
+    let pid = Id::Pid(Pid::from_raw(child.id() as i32));
+    loop {
+        match waitid(pid, WaitPidFlag::WEXITED | WaitPidFlag::WSTOPPED | WaitPidFlag::WNOWAIT) {
+            Ok(WaitStatus::Stopped(..)) => {
+                let _ = waitid(pid, WaitPidFlag::WSTOPPED | WaitPidFlag::WNOHANG);
+                return Ok(ChildWait::Stopped);
+            },
+            Err(Errno::EINTR) => continue,
+            // 已经结束，或者已经被回收（ECHILD）：由 Child::wait 回收或取出保存的状态
+            _ => return wait_child(child).map(ChildWait::Exited),
+        }
+    }
+}
+
+/// 没有 waitid 的平台无法在不回收进程的情况下检测停止，只等待进程结束
+#[cfg(not(any(target_os = "linux", target_os = "android", target_os = "freebsd")))]
+pub fn wait_child_or_stop(child: &mut Child) -> io::Result<ChildWait> {
+    wait_child(child).map(ChildWait::Exited)
+}
+
+/// 等待子进程结束。信号处理函数（如 SIGCHLD、SIGWINCH）打断系统调用时会返回 EINTR，
+/// 这不是真正的错误，重新等待即可；其他错误原样返回。
+pub fn wait_child(child: &mut Child) -> io::Result<ExitStatus> {
+    loop {
+        match child.wait() {
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            result => return result,
+        }
+    }
+}
+
+/// 把子进程的退出状态转换成 Shell 的状态码：正常退出取退出码，被信号终止时为 128 + 信号值
+pub fn exit_code(status: ExitStatus) -> i32 {
+    if let Some(code) = status.code() {
+        return code;
+    }
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::ExitStatusExt;
+        if let Some(signal) = status.signal() {
+            return 128 + signal;
+        }
+    }
+    1
+}
+
+/// 在后台线程中把数据（here-document 正文或内置命令的输出）写入子进程的 stdin 或输出的去向，写完后关闭。
+/// 如果在主线程里同步写入，数据超过管道缓冲区时会阻塞，而下游命令还没启动来读取输出，
+/// 整条管道就会死锁。读取端提前退出时写入会失败，直接忽略即可。
+fn write_in_background(mut target: impl Write + Send + 'static, data: Vec<u8>) -> JoinHandle<()> {
+    thread::spawn(move || {
+        let _ = target.write_all(&data);
+        let _ = target.flush();
+    })
+}
@@ -0,0 +1,78 @@
+//! history.rs
+
+/// 历史展开：在解析之前把命令行中的事件引用替换成历史记录中的命令。
+///   - `!!`：上一条命令
+///   - `!n`：第 n 条历史记录（从 1 开始），`!-n`：倒数第 n 条
+///   - `!prefix`：最近一条以 prefix 开头的命令
+///
+/// `!` 之后是空白、`=`、`(` 或者行尾时不是事件引用；单引号中、被反斜杠转义的 `!`、`$!` 和 `${!` 也不展开。
+/// 没有事件引用时返回 Ok(None)；找不到对应的记录时返回 Err，内容是出错的事件引用。
+pub fn expand_history(line: &str, entries: &[String]) -> Result<Option<String>, String> {
+    let chars: Vec<char> = line.chars().collect();
+    let mut result = String::new();
+    let mut expanded = false;
+    let mut in_single_quote = false;
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        match c {
+            '\'' => in_single_quote = !in_single_quote,
+            '\\' if !in_single_quote && i + 1 < chars.len() => {
+                result.push(c);
+                result.push(chars[i + 1]);
+                i += 2;
+                continue;
+            },
+            '!' if !in_single_quote && !chars[..i].ends_with(&['$']) && !chars[..i].ends_with(&['$', '{']) => {
+                let end = event_end(&chars, i + 1);
+                if end > i + 1 {
+                    let event: String = chars[i..end].iter().collect();
+                    let command = find_event(&event[1..], entries).ok_or_else(|| event.clone())?;
+                    result.push_str(command);
+                    expanded = true;
+                    i = end;
+                    continue;
+                }
+            },
+            _ => {},
+        }
+        result.push(c);
+        i += 1;
+    }
+    Ok(expanded.then_some(result))
+}
+
+/// 事件引用在 `start`（`!` 之后）开始，返回它结束的位置；不是事件引用时返回 `start`
+fn event_end(chars: &[char], start: usize) -> usize {
+    match chars.get(start) {
+        None => start,
+        Some('!') => start + 1,
+        Some(c) if c.is_whitespace() || matches!(c, '=' | '(') => start,
+        Some('-') | Some('0'..='9') => {
+            let digits = chars[start + 1..].iter().take_while(|c| c.is_ascii_digit()).count();
+            start + 1 + digits
+        },
+        Some(_) => {
+            let len = chars[start..]
+                .iter()
+                .take_while(|&&c| !c.is_whitespace() && !matches!(c, ';' | '|' | '&' | '<' | '>' | '(' | ')' | '\'' | '"'))
+                .count();
+            start + len
+        },
+    }
+}
+
+/// 按 `!` 之后的内容查找历史记录
+fn find_event<'a>(event: &str, entries: &'a [String]) -> Option<&'a String> {
+    if event == "!" {
+        return entries.last();
+    }
+    if let Some(offset) = event.strip_prefix('-') {
+        let offset: usize = offset.parse().ok()?;
+        return entries.len().checked_sub(offset).filter(|_| offset > 0).and_then(|i| entries.get(i));
+    }
+    if let Ok(number) = event.parse::<usize>() {
+        return number.checked_sub(1).and_then(|i| entries.get(i));
+    }
+    entries.iter().rev().find(|entry| entry.starts_with(event))
+}
@@ -0,0 +1,260 @@
+//! jobs.rs
+
+use std::fmt;
+use std::process::Child;
+
+use crate::executor;
+
+/// 作业的运行状态
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JobState {
+    Running,
+    Stopped,   // 前台运行时被 Ctrl-Z 停止，可以用 fg 或 bg 继续
+    Done(i32), // 全部进程已经结束，记录最后一个进程的退出状态
+}
+
+impl fmt::Display for JobState {
+    /// 与 bash 的 jobs 输出一致：`Running`、`Stopped`、`Done`，非 0 退出时为 `Exit N`
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            JobState::Running => write!(f, "Running"),
+            JobState::Stopped => write!(f, "Stopped"),
+            JobState::Done(0) => write!(f, "Done"),
+            JobState::Done(status) => write!(f, "Exit {}", status),
+        }
+    }
+}
+
+/// 一个在后台运行的管道
+#[derive(Debug)]
+pub struct Job {
+    pub id: usize,         // 作业号，即 `[1]` 中的数字
+    pub command: String,   // 启动作业的命令行
+    pub state: JobState,
+    pub nohup: bool,       // disown -h：Shell 退出时不给它发送 SIGHUP
+    children: Vec<Child>,  // 管道各阶段的子进程
+}
+
+impl Job {
+    /// 最后一个进程的 pid，启动作业时打印的就是它
+    pub fn pid(&self) -> Option<u32> {
+        self.children.last().map(Child::id)
+    }
+
+    /// 作业控制下作业的进程组号，即第一个进程的 pid
+    pub fn pgid(&self) -> u32 {
+        self.children.first().map_or(0, Child::id)
+    }
+
+    /// 管道中所有进程的 pid
+    pub fn pids(&self) -> Vec<u32> {
+        self.children.iter().map(Child::id).collect()
+    }
+
+    /// 不带末尾 `&` 的命令行，fg 和作业结束的报告使用
+    pub fn command_line(&self) -> &str {
+        self.command.strip_suffix(" &").unwrap_or(&self.command)
+    }
+
+    /// 给作业中所有进程发送 SIGCONT，让停止的作业继续运行
+    #[cfg(unix)]
+    pub fn resume(&self) {
+        use nix::sys::signal::{Signal, kill};
+        use nix::unistd::Pid;
+        for pid in self.pids() {
+            let _ = kill(Pid::from_raw(pid as i32), Signal::SIGCONT);
+        }
+    }
+
+    #[cfg(not(unix))]
+    pub fn resume(&self) {}
+
+    /// 给作业中所有进程发送 SIGHUP；和 bash 一样，停止的作业再发送 SIGCONT，它才能处理信号并退出
+    #[cfg(unix)]
+    pub fn hangup(&self) {
+        use nix::sys::signal::{Signal, kill};
+        use nix::unistd::Pid;
+        for pid in self.pids() {
+            let _ = kill(Pid::from_raw(pid as i32), Signal::SIGHUP);
+        }
+        if self.state == JobState::Stopped {
+            self.resume();
+        }
+    }
+
+    #[cfg(not(unix))]
+    pub fn hangup(&self) {}
+
+    /// 等待作业结束，作业控制下作业再次被 Ctrl-Z 停止时返回 None 并把它标记为 Stopped
+    pub fn wait_or_stop(&mut self) -> Option<i32> {
+        let mut status = 0;
+        for child in self.children.iter_mut() {
+            status = match executor::wait_child_or_stop(child) {
+                Ok(executor::ChildWait::Exited(exit)) => executor::exit_code(exit),
+                Ok(executor::ChildWait::Stopped) => {
+                    self.state = JobState::Stopped;
+                    return None;
+                },
+                Err(_) => 1,
+            };
+        }
+        self.state = JobState::Done(status);
+        Some(status)
+    }
+
+    /// 阻塞地等待作业中的所有进程结束，返回最后一个进程的退出状态
+    pub fn wait(&mut self) -> i32 {
+        let mut status = 0;
+        for child in self.children.iter_mut() {
+            status = match executor::wait_child(child) {
+                Ok(exit) => executor::exit_code(exit),
+                Err(_) => 1,
+            };
+        }
+        self.state = JobState::Done(status);
+        status
+    }
+
+    /// 不阻塞地检查作业是否结束；全部进程结束后返回最后一个进程的退出状态
+    fn poll(&mut self) -> Option<i32> {
+        let mut status = 0;
+        for child in self.children.iter_mut() {
+            // 已经回收的子进程再次调用 try_wait 会返回保存的状态
+            status = match child.try_wait() {
+                Ok(Some(exit)) => executor::exit_code(exit),
+                Ok(None) => return None,
+                Err(_) => 1,
+            };
+        }
+        Some(status)
+    }
+}
+
+/// 后台作业表
+#[derive(Debug, Default)]
+pub struct JobTable {
+    jobs: Vec<Job>,
+}
+
+impl JobTable {
+    pub fn new() -> Self {
+        JobTable::default()
+    }
+
+    /// 登记一个后台作业或被停止的前台作业，作业号取当前最大的作业号加一
+    pub fn add(&mut self, command: String, children: Vec<Child>, state: JobState) -> &Job {
+        let id = self.jobs.iter().map(|job| job.id).max().unwrap_or(0) + 1;
+        self.jobs.push(Job { id, command, state, nohup: false, children });
+        &self.jobs[self.jobs.len() - 1]
+    }
+
+    /// 回收已经结束的作业，避免留下僵尸进程。
+    /// 结束的作业标记为 Done 但仍留在表中，等报告给用户之后再由 `remove_finished` 删除。
+    pub fn reap(&mut self) {
+        for job in self.jobs.iter_mut().filter(|job| !matches!(job.state, JobState::Done(_))) {
+            if let Some(status) = job.poll() {
+                job.state = JobState::Done(status);
+            }
+        }
+    }
+
+    /// 不阻塞地回收已经结束的作业，从表中删除它们并返回 bash 格式的完成通知，
+    /// 例如 `[1]+  Done                    sleep 5`。还在运行的作业不受影响
+    pub fn take_finished_notices(&mut self) -> Vec<String> {
+        self.reap();
+        let notices = self
+            .jobs
+            .iter()
+            .filter(|job| matches!(job.state, JobState::Done(_)))
+            .map(|job| self.describe(job))
+            .collect();
+        self.remove_finished();
+        notices
+    }
+
+    /// 删除已经结束的作业并返回它们，每个结束的作业只报告一次
+    pub fn remove_finished(&mut self) -> Vec<Job> {
+        let (finished, running) = std::mem::take(&mut self.jobs)
+            .into_iter()
+            .partition(|job| matches!(job.state, JobState::Done(_)));
+        self.jobs = running;
+        finished
+    }
+
+    /// Shell 退出时调用：给还没有结束、也没有被 `disown -h` 标记的作业发送 SIGHUP
+    pub fn hangup(&mut self) {
+        self.reap();
+        for job in self.jobs.iter().filter(|job| !job.nohup && !matches!(job.state, JobState::Done(_))) {
+            job.hangup();
+        }
+    }
+
+    /// 根据作业说明找到作业号：`%N` 或 `N` 是作业号，`%%`、`%+` 或省略是当前作业，`%-` 是前一个作业
+    pub fn find(&self, spec: Option<&str>) -> Result<usize, String> {
+        let spec = spec.unwrap_or("%+");
+        let mut recent = self.jobs.iter().rev().map(|job| job.id);
+        let id = match spec {
+            "%%" | "%+" | "%" => recent.next(),
+            "%-" => recent.nth(1),
+            _ => spec
+                .strip_prefix('%')
+                .unwrap_or(spec)
+                .parse::<usize>()
+                .ok()
+                .filter(|id| self.jobs.iter().any(|job| job.id == *id)),
+        };
+        id.ok_or_else(|| format!("{}: 没有该作业", spec))
+    }
+
+    /// 找到包含进程 `pid` 的作业，返回它的作业号
+    pub fn find_pid(&self, pid: u32) -> Option<usize> {
+        self.jobs.iter().find(|job| job.pids().contains(&pid)).map(|job| job.id)
+    }
+
+    pub fn get(&self, id: usize) -> Option<&Job> {
+        self.jobs.iter().find(|job| job.id == id)
+    }
+
+    pub fn get_mut(&mut self, id: usize) -> Option<&mut Job> {
+        self.jobs.iter_mut().find(|job| job.id == id)
+    }
+
+    /// 从作业表中删除作业并返回它
+    pub fn remove(&mut self, id: usize) -> Option<Job> {
+        let index = self.jobs.iter().position(|job| job.id == id)?;
+        Some(self.jobs.remove(index))
+    }
+
+    /// 按启动顺序列出所有作业
+    pub fn iter(&self) -> impl Iterator<Item = &Job> {
+        self.jobs.iter()
+    }
+
+    /// 作业号后面的标记：`+` 是当前作业（最近启动的），`-` 是前一个作业
+    pub fn marker(&self, id: usize) -> char {
+        let mut recent = self.jobs.iter().rev().map(|job| job.id);
+        match (recent.next(), recent.next()) {
+            (Some(current), _) if current == id => '+',
+            (_, Some(previous)) if previous == id => '-',
+            _ => ' ',
+        }
+    }
+
+    /// 按 bash 的格式描述一个作业，例如 `[1]+  Running                 sleep 100 &`。
+    /// 结束和停止的作业不显示命令末尾的 `&`。
+    pub fn describe(&self, job: &Job) -> String {
+        let command = match job.state {
+            JobState::Running => job.command.as_str(),
+            JobState::Stopped | JobState::Done(_) => job.command_line(),
+        };
+        format!("[{}]{}  {:<24}{}", job.id, self.marker(job.id), job.state.to_string(), command)
+    }
+
+    pub fn len(&self) -> usize {
+        self.jobs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.jobs.is_empty()
+    }
+}
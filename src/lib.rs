@@ -0,0 +1,13 @@
+//! lib.rs
+
+pub mod parser;
+pub mod executor;
+pub mod shell;
+pub mod builtins;
+pub mod terminal;
+pub mod completion;
+pub mod command_hash;
+pub mod debug;
+pub mod jobs;
+pub mod prompt;
+pub mod history;
@@ -1,31 +1,136 @@
-use std::env;
-
 use rustyline::error::ReadlineError;
 use rustyline::{Editor, Result as RlResult};
 use rustyline::highlight::{Highlighter, MatchingBracketHighlighter};
 use rustyline::completion::{Completer, Pair};
 use rustyline::hint::Hinter;
-use rustyline::validate::Validator;
+use rustyline::validate::{ValidationContext, ValidationResult, Validator};
 use rustyline::{Context};
 use std::borrow::Cow;
+use std::cell::RefCell;
+use std::env;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::process;
+use std::panic::{self, AssertUnwindSafe};
+
+use my_shell::builtins::BUILTINS;
+use my_shell::command_hash::is_executable;
+use my_shell::completion::{self, HelpOptionCache};
+use my_shell::debug;
+use my_shell::history;
+use my_shell::parser;
+use my_shell::shell::{self, Flow, Shell};
+use my_shell::terminal::{self, TerminalState};
 
-mod parser;
-mod executor;
+/// MatchingBracketHighlighter 给匹配的括号加上的颜色
+const BRACKET_COLOR: &str = "\x1b[1;34m";
 
 // 定义一个辅助结构体，用于实现 rustyline 的 Completion、Hint 和 Highlight 特征
 struct MyHelper {
     completer: FilenameCompleter,
     highlighter: MatchingBracketHighlighter,
     hinter: HistoryHinter,
-    #[allow(dead_code)] // 允许死代码，因为这个字段是为了满足结构体要求，但实际值不被"读取"
-    validator: (), // 不需要特殊的验证器
+    shell: Rc<RefCell<Shell>>,         // 与主循环共享的 Shell 状态，用于读取选项
+    help_options: HelpOptionCache,     // set -o helpcomplete 时从 --help 输出抓取的长选项
 }
 
 impl Completer for MyHelper {
     type Candidate = Pair;
 
     fn complete(&self, line: &str, pos: usize, ctx: &Context<'_>) -> RlResult<(usize, Vec<Pair>)> {
-        self.completer.complete(line, pos, ctx)
+        if let Some(result) = self.complete_variable(line, pos) {
+            return Ok(result);
+        }
+        if self.shell.borrow().options.helpcomplete
+            && let Some(result) = self.complete_long_option(line, pos)
+        {
+            return Ok(result);
+        }
+        if let Some(result) = self.complete_command_name(line, pos) {
+            return Ok(result);
+        }
+        let (start, mut candidates) = self.completer.complete(line, pos, ctx)?;
+        candidates.splice(0..0, cd_shortcuts(line, pos));
+        Ok((start, candidates))
+    }
+}
+
+/// cd 的第一个参数额外提供 `-`（上一个目录）和 `..`（上级目录）两个候选
+fn cd_shortcuts(line: &str, pos: usize) -> Vec<Pair> {
+    let start = line[..pos].rfind([' ', '\t']).map_or(0, |i| i + 1);
+    let word = &line[start..pos];
+    let segment_start = line[..start].rfind(['|', ';', '&']).map_or(0, |i| i + 1);
+    let before: Vec<&str> = line[segment_start..start].split_whitespace().collect();
+    if before != ["cd"] {
+        return Vec::new();
+    }
+    ["-", ".."]
+        .iter()
+        .filter(|shortcut| shortcut.starts_with(word))
+        .map(|shortcut| Pair { display: shortcut.to_string(), replacement: shortcut.to_string() })
+        .collect()
+}
+
+impl MyHelper {
+    /// 光标处的单词是命令名时，用别名、内置命令和 PATH 中的命令补全；带 `/` 的单词仍按路径补全
+    fn complete_command_name(&self, line: &str, pos: usize) -> Option<(usize, Vec<Pair>)> {
+        let start = line[..pos].rfind([' ', '\t']).map_or(0, |i| i + 1);
+        let word = &line[start..pos];
+        if word.contains('/') || !completion::is_command_position(line, start) {
+            return None;
+        }
+        let candidates = self
+            .shell
+            .borrow()
+            .complete_command_name(word)
+            .into_iter()
+            .map(|name| Pair { display: name.clone(), replacement: name })
+            .collect();
+        Some((start, candidates))
+    }
+
+    /// 光标处的单词以 `$` 或 `${` 开头时，用环境变量和 Shell 变量的名字补全
+    fn complete_variable(&self, line: &str, pos: usize) -> Option<(usize, Vec<Pair>)> {
+        let names: Vec<String> = env::vars_os()
+            .filter_map(|(name, _)| name.into_string().ok())
+            .chain(self.shell.borrow().variables.keys().cloned())
+            .collect();
+        let (start, candidates) = completion::complete_variable(line, pos, &names)?;
+        let candidates = candidates
+            .into_iter()
+            .map(|name| Pair { display: name.clone(), replacement: name })
+            .collect();
+        Some((start, candidates))
+    }
+
+    /// 命令名能否被执行：别名、内置命令、PATH 中的命令，或者带 `/` 的可执行文件路径
+    fn resolves(&self, name: &str) -> bool {
+        if name.contains('/') {
+            return is_executable(Path::new(name));
+        }
+        let shell = self.shell.borrow();
+        BUILTINS.contains(&name) || shell.aliases.contains_key(name) || shell.command_names.contains(name)
+    }
+
+    /// 光标处的单词以 `--` 开头且不是命令名时，用该命令 `--help` 输出中的长选项补全
+    fn complete_long_option(&self, line: &str, pos: usize) -> Option<(usize, Vec<Pair>)> {
+        let start = line[..pos].rfind([' ', '\t']).map_or(0, |i| i + 1);
+        let word = &line[start..pos];
+        if !word.starts_with("--") {
+            return None;
+        }
+
+        let command = completion::segment_command(line, start)?;
+
+        let candidates = self
+            .help_options
+            .options_for(command)
+            .into_iter()
+            .filter(|option| option.starts_with(word))
+            .map(|option| Pair { display: option.clone(), replacement: option })
+            .collect();
+        Some((start, candidates))
     }
 }
 
@@ -41,16 +146,51 @@ impl Highlighter for MyHelper {
         Cow::Owned(format!("\x1b[90m{} [0m", hint))
     }
 
+    /// 在括号匹配的基础上，把行首的命令名标成绿色（能找到）或红色（找不到）
     fn highlight<'l>(&self, line: &'l str, pos: usize) -> Cow<'l, str> {
-        self.highlighter.highlight(line, pos)
+        let highlighted = self.highlighter.highlight(line, pos);
+        let Some(span) = completion::command_word_span(line) else {
+            return highlighted;
+        };
+        let word = &line[span.clone()];
+        // 带引号、转义或变量的命令名要展开后才知道是什么，不着色
+        if word.contains(['\'', '"', '\\', '$', '`']) {
+            return highlighted;
+        }
+        // 括号高亮在匹配的括号前后插入了转义序列，命令名之后的位置要相应地后移
+        let span = match highlighted.find(BRACKET_COLOR) {
+            Some(i) if i < span.start => {
+                let shift = highlighted.len() - line.len();
+                span.start + shift..span.end + shift
+            },
+            Some(i) if i < span.end => return highlighted,
+            _ => span,
+        };
+        let color = if self.resolves(word) { "\x1b[32m" } else { "\x1b[31m" };
+        let mut colored = highlighted.into_owned();
+        colored.insert_str(span.end, "\x1b[0m");
+        colored.insert_str(span.start, color);
+        Cow::Owned(colored)
     }
 
+    /// 光标在命令名中或紧跟在它后面时，每次按键都要重新高亮
     fn highlight_char(&self, line: &str, pos: usize) -> bool {
-        self.highlighter.highlight_char(line, pos)
+        let bracket = self.highlighter.highlight_char(line, pos);
+        bracket || completion::command_word_span(line).is_some_and(|span| pos <= span.end)
     }
 }
 
-impl Validator for MyHelper {}
+impl Validator for MyHelper {
+    /// 引号、括号没有闭合，行尾是反斜杠，或者 here-document 还没有结束标记时，
+    /// 回车只是换行，在同一个编辑缓冲区里继续输入；输入完整时立即提交
+    fn validate(&self, ctx: &mut ValidationContext) -> RlResult<ValidationResult> {
+        if parser::input_complete(ctx.input()) {
+            Ok(ValidationResult::Valid(None))
+        } else {
+            Ok(ValidationResult::Incomplete)
+        }
+    }
+}
 impl rustyline::Helper for MyHelper {}
 
 // 用于文件路径补全的 completer (rustyline 内置)
@@ -58,7 +198,91 @@ use rustyline::completion::FilenameCompleter;
 // 用于历史记录提示的 hinter (rustyline 内置)
 use rustyline::hint::HistoryHinter;
 
+/// 执行一行命令，并捕获执行过程中的 panic。
+/// 执行器或内置命令里的 bug 不应该让整个 Shell 崩溃：默认的 panic hook 已经把详细信息
+/// 打印到 stderr，这里再提示一条内部错误，然后回到提示符继续工作。
+/// panic 可能发生在把终端交给前台进程组之后，所以先收回终端、恢复启动时的终端设置，
+/// 否则下一次 readline 会因为 SIGTTIN 或 EIO 失败；被打断的子 Shell 对环境的修改也一并撤销。
+/// 子 Shell 中对工作目录和 Shell 变量的修改保存在展开时丢失的状态里，无法恢复。
+fn run_line_guarded(shell: &mut Shell, command_line: &str, terminal: &TerminalState) -> Flow {
+    match panic::catch_unwind(AssertUnwindSafe(|| shell.run_line(command_line))) {
+        Ok(flow) => flow,
+        Err(payload) => {
+            let message = payload
+                .downcast_ref::<&str>()
+                .map(|s| s.to_string())
+                .or_else(|| payload.downcast_ref::<String>().cloned())
+                .unwrap_or_else(|| "未知错误".to_string());
+            terminal::reclaim_terminal();
+            terminal.restore();
+            shell.recover_from_panic();
+            eprintln!("my_shell: 内部错误: {}", message);
+            Flow::Continue
+        }
+    }
+}
+
+/// 删除最近一条历史记录。
+/// rustyline 的 History 没有提供删除单条记录的接口，只能清空后重新添加其余记录。
+fn remove_last_history_entry(rl: &mut Editor<MyHelper>) {
+    let history = rl.history_mut();
+    let kept: Vec<String> = history.iter().take(history.len().saturating_sub(1)).cloned().collect();
+    history.clear();
+    for entry in kept {
+        history.add(entry);
+    }
+}
+
+/// 判断读取错误是否只是被信号打断，这类错误应当重试而不是退出 Shell
+fn is_interrupted(err: &ReadlineError) -> bool {
+    match err {
+        ReadlineError::Io(e) => e.kind() == std::io::ErrorKind::Interrupted,
+        #[cfg(unix)]
+        ReadlineError::Errno(errno) => *errno == nix::errno::Errno::EINTR,
+        ReadlineError::WindowResized => true,
+        _ => false,
+    }
+}
+
+/// 交互模式启动时执行的配置文件
+fn rc_path() -> PathBuf {
+    PathBuf::from(shell::home_dir()).join(".rust_shellrc")
+}
+
+/// 历史记录文件：优先使用 $RUST_SHELL_HISTFILE，否则为主目录下的 .rust_shell_history
+fn history_path() -> PathBuf {
+    match env::var("RUST_SHELL_HISTFILE") {
+        Ok(path) if !path.is_empty() => PathBuf::from(path),
+        _ => PathBuf::from(shell::home_dir()).join(".rust_shell_history"),
+    }
+}
+
 fn main() {
+    // --debug 或 RUSTSHELL_DEBUG=1 打开调试跟踪，--norc 不执行 ~/.rust_shellrc。
+    // 选项只能写在最前面，之后的第一个参数是脚本文件，再后面的参数原样交给脚本
+    let mut args = env::args().skip(1).peekable();
+    let mut debug = false;
+    let mut norc = false;
+    while let Some(flag) = args.next_if(|arg| arg == "--debug" || arg == "--norc") {
+        match flag.as_str() {
+            "--debug" => debug = true,
+            _ => norc = true,
+        }
+    }
+    debug::init(debug);
+    let args: Vec<String> = args.collect();
+
+    // 如果命令行参数给出了脚本文件，则非交互地执行脚本后退出，退出状态为最后一条命令（或 exit）的状态。
+    // 脚本中 `$0` 是脚本文件名，`$1`、`$2` …… 是其后的参数，`$#` 是参数个数
+    if let Some((script, script_args)) = args.split_first() {
+        let mut shell = Shell::new();
+        if let Err(e) = shell.run_file(script, script_args) {
+            eprintln!("my_shell: {}: {}", script, e);
+            process::exit(127);
+        }
+        process::exit(shell.last_status);
+    }
+
     // 创建 rustyline 编辑器实例
     let config = rustyline::Config::builder()
         .history_ignore_space(true)
@@ -66,89 +290,93 @@ fn main() {
         .edit_mode(rustyline::config::EditMode::Emacs)
         .build();
 
+    let shell = Rc::new(RefCell::new(Shell::new()));
+    shell.borrow_mut().options.monitor = terminal::init_job_control();
+    if !norc && shell.borrow_mut().run_rc_file(&rc_path()) == Flow::Exit {
+        process::exit(shell.borrow().last_status);
+    }
     let h = MyHelper {
         completer: FilenameCompleter::new(),
         highlighter: MatchingBracketHighlighter::new(),
         hinter: HistoryHinter {},
-        validator: (),
+        shell: Rc::clone(&shell),
+        help_options: HelpOptionCache::new(),
     };
     let mut rl = Editor::with_config(config).expect("无法创建 Editor");
     rl.set_helper(Some(h));
 
-    // 加载历史记录 (如果存在)
-    // let history_path = "history.txt";
-    // if rl.load_history(history_path).is_err() {
-    //     println!("没有找到历史记录文件: {}. 创建新的历史记录.", history_path);
-    // }
+    // 加载历史记录，文件不存在时先创建一个空文件
+    let history_path = history_path();
+    if let Err(e) = File::options().create(true).append(true).open(&history_path) {
+        eprintln!("my_shell: 无法创建历史记录文件 {}: {}", history_path.display(), e);
+    } else if let Err(e) = rl.load_history(&history_path) {
+        eprintln!("my_shell: 无法读取历史记录文件 {}: {}", history_path.display(), e);
+    }
+
+    // 保存启动时的终端设置，子进程把终端弄乱后在下一个提示符前恢复
+    let terminal = TerminalState::save();
 
     loop {
-        let readline = rl.readline("my_shell> "); // 使用 rustyline 读取输入
+        terminal.restore();
+        // 后台作业的完成通知在提示符之前打印，不会和正在编辑的一行混在一起
+        shell.borrow_mut().notify_finished_jobs(&mut std::io::stderr());
+        let prompt = shell.borrow_mut().prompt();
+        let readline = rl.readline(&prompt); // 使用 rustyline 读取输入
 
         match readline {
-            Ok(command_line) => {
+            Ok(input) => {
+                // 验证器保证了输入是完整的：先拼接续行得到命令行，其余的行是 here-document 的正文
+                let mut lines = input.lines();
+                let (command_line, _) = parser::join_continuation_lines(lines.next().unwrap_or(""), &mut lines);
+                let bodies: Vec<&str> = lines.collect();
                 let command_line = command_line.trim();
                 if command_line.is_empty() {
                     continue;
                 }
 
-                // 将命令添加到历史记录
-                rl.add_history_entry(command_line.to_string());
-
-                // 解析用户输入的命令，可能包含管道
-                let parsed_commands = match parser::parse_pipeline_commands(command_line) {
-                    Ok(cmds) => cmds,
-                    Err(e) => {
-                        eprintln!("my_shell: 解析错误: {}", e);
+                // 历史展开：回显展开后的命令，找不到对应的记录时不执行、也不加入历史记录
+                let entries: Vec<String> = rl.history().iter().cloned().collect();
+                let (input, command_line) = match history::expand_history(command_line, &entries) {
+                    Ok(Some(expanded)) => {
+                        println!("{}", expanded);
+                        let input = [expanded.as_str()].into_iter().chain(bodies).collect::<Vec<_>>().join("\n");
+                        (input, expanded)
+                    },
+                    Ok(None) => (input.clone(), command_line.to_string()),
+                    Err(event) => {
+                        eprintln!("my_shell: {}: event not found", event);
                         continue;
-                    }
+                    },
                 };
+                let command_line = command_line.as_str();
+
+                // 将命令添加到历史记录（以空格开头或与上一条重复时不会添加）
+                let added_to_history = rl.add_history_entry(command_line.to_string());
 
-                // 处理内置命令 (只对管道中的第一个命令进行检查)
-                // 确保 cd, exit, pwd 不会与其他外部命令通过管道组合
-                if parsed_commands.len() == 1 {
-                    let single_cmd = &parsed_commands[0];
-                    match single_cmd.name.as_str() {
-                        "exit" => {
-                            println!("Exiting my_shell.");
-                            break; // 退出主循环
-                        },
-                        "cd" => {
-                            // 处理 cd 命令：改变当前工作目录
-                            if single_cmd.args.len() == 0 {
-                                eprintln!("cd: 缺少操作数");
-                            } else if single_cmd.args.len() > 1 {
-                                eprintln!("cd: 参数过多");
-                            } else {
-                                let path = &single_cmd.args[0];
-                                if let Err(e) = env::set_current_dir(path) {
-                                    eprintln!("cd: {}: {}", path, e);
-                                }
-                            }
-                            continue; // cd 命令处理完毕，继续下一个循环
-                        },
-                        "pwd" => {
-                            // 处理 pwd 命令：打印当前工作目录
-                            match env::current_dir() {
-                                Ok(path) => println!("{}", path.display()),
-                                Err(e) => eprintln!("pwd: {}", e),
-                            }
-                            continue; // pwd 命令处理完毕，继续下一个循环
-                        },
-                        _ => { /* 不是内置命令，继续执行外部命令逻辑 */ }
-                    }
+                // 解析并执行这一行命令
+                let mut shell = shell.borrow_mut();
+                shell.lineno += 1;
+                if run_line_guarded(&mut shell, &input, &terminal) == Flow::Exit {
+                    println!("Exiting my_shell.");
+                    break; // 退出主循环
                 }
 
-                // 执行管道中的命令
-                executor::execute_pipeline(&parsed_commands);
+                // set -o histignorefailed：失败的命令不保留在历史记录中
+                if added_to_history && shell.options.histignorefailed && shell.last_status != 0 {
+                    remove_last_history_entry(&mut rl);
+                }
             },
-            Err(ReadlineError::Interrupted) => { // Ctrl-C
-                println!("Ctrl-C 捕获，退出.");
-                break;
+            Err(ReadlineError::Interrupted) => { // Ctrl-C：放弃正在输入的一行，重新显示提示符
+                continue;
             },
             Err(ReadlineError::Eof) => { // Ctrl-D
                 println!("Ctrl-D 捕获，退出.");
                 break;
             },
+            Err(err) if is_interrupted(&err) => {
+                // 读取被信号打断 (EINTR) 或终端大小改变，重新显示提示符即可
+                continue;
+            },
             Err(err) => {
                 eprintln!("读取命令行错误: {:?}", err);
                 break;
@@ -156,8 +384,14 @@ fn main() {
         }
     }
 
-    // 保存历史记录
-    // if let Err(err) = rl.save_history(history_path) {
-    //     eprintln!("保存历史记录错误: {:?}", err);
-    // }
+    // 结束会话：给没有 disown 的后台作业和停止的作业发送 SIGHUP
+    shell.borrow_mut().hangup_jobs();
+
+    // 保存历史记录：exit、Ctrl-C 和 Ctrl-D 都会跳出主循环到这里
+    if let Err(err) = rl.save_history(&history_path) {
+        eprintln!("my_shell: 保存历史记录错误: {}", err);
+    }
+    // 和 bash 一样，exit 没有给出状态和 Ctrl-D 时使用最后一条命令的状态
+    let status = shell.borrow().last_status;
+    process::exit(status);
 }
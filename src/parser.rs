@@ -1,97 +1,2347 @@
-//! parser.rs
-
-// 辅助结构体，用于存储解析后的命令信息
-#[derive(Debug, Clone)]
-pub struct ParsedCommand {
-    pub name: String,
-    pub args: Vec<String>,
-    pub stdin_redirect: Option<String>,
-    pub stdout_redirect: Option<(String, bool)>, // (文件名, 是否为追加模式)
-    pub stderr_redirect: Option<String>,         // (文件名) 对于 2>
-}
-
-/// 将单个命令行字符串（不含管道）解析为 ParsedCommand 结构体。
-/// 这个解析器是基础版本：它处理以空格分隔的参数和简单的 I/O 重定向（<, >, >>, 2>）。
-/// 它不处理以下情况：
-///   - 带引号的参数（例如, "hello world"）
-///   - 转义字符
-///   - 命令替换 (`$()`) 了
-///   - 后台进程 (`&`)
-pub fn parse_single_command(command_segment: &str) -> Result<ParsedCommand, String> {
-    let parts = command_segment.split_whitespace().collect::<Vec<&str>>();
-    if parts.is_empty() {
-        return Err("空命令段".to_string());
-    }
-
-    let name = parts[0].to_string();
-    let mut args = Vec::new();
-    let mut stdin_redirect: Option<String> = None;
-    let mut stdout_redirect: Option<(String, bool)> = None;
-    let mut stderr_redirect: Option<String> = None;
-
-    let mut i = 1; // 从第二个部分开始处理
-    while i < parts.len() {
-        match parts[i] {
-            "<" => {
-                if i + 1 < parts.len() {
-                    stdin_redirect = Some(parts[i+1].to_string());
-                    i += 2; // 跳过操作符和文件名
-                } else {
-                    return Err("输入重定向缺少文件名 (<)".to_string());
-                }
-            },
-            ">" => {
-                if i + 1 < parts.len() {
-                    stdout_redirect = Some((parts[i+1].to_string(), false)); // false 表示覆盖模式
-                    i += 2; // 跳过操作符和文件名
-                } else {
-                    return Err("输出重定向缺少文件名 (>)\nmy_shell: 解析错误:".to_string());
-                }
-            },
-            ">>" => {
-                if i + 1 < parts.len() {
-                    stdout_redirect = Some((parts[i+1].to_string(), true)); // true 表示追加模式
-                    i += 2; // 跳过操作符和文件名
-                } else {
-                    return Err("输出重定向缺少文件名 (>>)".to_string());
-                }
-            },
-            "2>" => {
-                if i + 1 < parts.len() {
-                    stderr_redirect = Some(parts[i+1].to_string());
-                    i += 2; // 跳过操作符和文件名
-                } else {
-                    return Err("标准错误重定向缺少文件名 (2>)".to_string());
-                }
-            },
-            _ => {
-                // 如果不是重定向操作符，则将其作为参数
-                args.push(parts[i].to_string());
-                i += 1;
-            }
-        }
-    }
-
-    Ok(ParsedCommand {
-        name,
-        args,
-        stdin_redirect,
-        stdout_redirect,
-        stderr_redirect,
-    })
-}
-
-/// 解析包含管道符的完整命令行。
-/// 将命令行分割成多个命令段，并为每个命令段调用 parse_single_command。
-pub fn parse_pipeline_commands(command_line: &str) -> Result<Vec<ParsedCommand>, String> {
-    let segments: Vec<&str> = command_line.split('|').collect();
-    let mut commands = Vec::new();
-
-    for segment in segments {
-        if segment.trim().is_empty() {
-            return Err("管道符 ' | ' 后不能有空命令.".to_string());
-        }
-        commands.push(parse_single_command(segment.trim())?);
-    }
-    Ok(commands)
-} 
\ No newline at end of file
+//! parser.rs
+
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::debug::trace;
+use std::iter::Peekable;
+use std::str::Chars;
+
+/// here-document (<<EOF) 的结束标记和正文。
+/// 解析命令行时只知道结束标记，正文由 `collect_heredoc_bodies` 从后续行中读取。
+#[derive(Debug, Clone)]
+pub struct HereDoc {
+    pub delimiter: String,
+    pub body: String,
+    pub expand: bool,     // 结束标记没有引号时，正文中的变量和命令替换会被展开
+    pub strip_tabs: bool, // <<-EOF：去掉正文各行和结束标记行开头的制表符
+}
+
+impl HereDoc {
+    /// 根据 `<<` 或 `<<-` 之后的单词创建 here-document。
+    /// 单词中只要有引号或反斜杠（tokenize 之后表现为转义），正文就按字面处理。
+    fn new(word: &str, strip_tabs: bool) -> HereDoc {
+        HereDoc {
+            delimiter: remove_quotes(word),
+            body: String::new(),
+            expand: !word.contains(['\\', '"']),
+            strip_tabs,
+        }
+    }
+
+    /// 判断一行输入是否是结束标记
+    pub fn is_terminator(&self, line: &str) -> bool {
+        let line = if self.strip_tabs { line.trim_start_matches('\t') } else { line };
+        line == self.delimiter
+    }
+}
+
+/// 输出重定向的操作，按在命令中出现的顺序执行。
+/// `N>&M` 复制的是它出现时 fd M 的目标，所以 `> file 2>&1` 让两者都写入文件，
+/// 而 `2>&1 > file` 让 stderr 留在原来的 stdout（终端或管道）上。
+/// 文件名保存在 `stdout_redirect` 和 `stderr_redirect` 中，同一个 fd 有多个文件重定向时只有最后一个生效。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputOperation {
+    StdoutToFile,                      // > file、>> file
+    StderrToFile,                      // 2> file、2>> file
+    Duplicate { fd: u32, target: u32 }, // N>&M：让 fd N 指向 fd M 当前的目标，目前只支持 1 和 2
+}
+
+// 辅助结构体，用于存储解析后的命令信息
+#[derive(Debug, Clone)]
+pub struct ParsedCommand {
+    pub assignments: Vec<(String, String)>,      // 命令名之前的 NAME=value，只对这条命令生效；没有命令名时设置 Shell 变量
+    pub name: String,                            // 只有赋值的命令和子 Shell 组，命令名为空
+    pub group: Option<String>,                   // 子 Shell 组 `( ... )` 括号中的命令列表，原样保留到执行时再解析
+    pub case: Option<CaseCommand>,               // case ... esac 命令，各分支的命令列表同样到执行时再解析
+    pub select: Option<SelectCommand>,           // select ... done 命令，循环体同样到执行时再解析
+    pub args: Vec<String>,
+    pub stdin_redirect: Option<String>,
+    pub stdout_redirect: Option<(String, bool)>, // (文件名, 是否为追加模式)
+    pub stderr_redirect: Option<(String, bool)>, // (文件名, 是否为追加模式) 对于 2> 和 2>>
+    pub output_operations: Vec<OutputOperation>, // 输出重定向和 N>&M 的先后顺序
+    pub pipe_stderr: bool,                       // 是否通过 |& 把 stderr 也送入管道
+    pub heredocs: Vec<HereDoc>,                  // <<EOF 按出现顺序记录，最后一个提供标准输入
+    pub herestring: Option<String>,              // <<< word：word 加上换行作为标准输入，优先于 here-document
+    pub background: bool,                        // 命令行以 & 结尾，整条管道在后台运行
+}
+
+impl ParsedCommand {
+    /// 由 Shell 写入标准输入的文本：here-string 加上换行，或者最后一个 here-document 的正文
+    pub fn stdin_text(&self) -> Option<String> {
+        match &self.herestring {
+            Some(word) => Some(format!("{}\n", word)),
+            None => self.heredocs.last().map(|heredoc| heredoc.body.clone()),
+        }
+    }
+
+    /// 命令是否带有任何重定向（包括 here-document 和 here-string）
+    pub fn has_redirections(&self) -> bool {
+        self.stdin_redirect.is_some()
+            || self.stdout_redirect.is_some()
+            || self.stderr_redirect.is_some()
+            || !self.output_operations.is_empty()
+            || !self.heredocs.is_empty()
+            || self.herestring.is_some()
+    }
+}
+
+/// 按照命令行的书写形式还原命令，用于 dry-run 等需要回显命令的场景
+impl fmt::Display for ParsedCommand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut words: Vec<String> = self.assignments.iter().map(|(name, value)| format!("{}={}", name, value)).collect();
+        if let Some(body) = &self.group {
+            words.push(format!("({})", body));
+        } else if let Some(case) = &self.case {
+            words.push(case.text.clone());
+        } else if let Some(select) = &self.select {
+            words.push(select.text.clone());
+        } else if !self.name.is_empty() || words.is_empty() {
+            words.push(self.name.clone());
+        }
+        write!(f, "{}", words.join(" "))?;
+        for arg in &self.args {
+            write!(f, " {}", arg)?;
+        }
+        if let Some(filepath) = &self.stdin_redirect {
+            write!(f, " < {}", filepath)?;
+        }
+        for heredoc in &self.heredocs {
+            let operator = if heredoc.strip_tabs { "<<-" } else { "<<" };
+            if heredoc.expand {
+                write!(f, " {}{}", operator, heredoc.delimiter)?;
+            } else {
+                write!(f, " {}'{}'", operator, heredoc.delimiter)?;
+            }
+        }
+        if let Some(word) = &self.herestring {
+            write!(f, " <<< {}", word)?;
+        }
+        for operation in &self.output_operations {
+            match (operation, &self.stdout_redirect, &self.stderr_redirect) {
+                (OutputOperation::StdoutToFile, Some((filepath, append)), _) => {
+                    write!(f, " {} {}", if *append { ">>" } else { ">" }, filepath)?;
+                },
+                (OutputOperation::StderrToFile, _, Some((filepath, append))) => {
+                    write!(f, " {} {}", if *append { "2>>" } else { "2>" }, filepath)?;
+                },
+                (OutputOperation::Duplicate { fd, target }, _, _) => write!(f, " {}>&{}", fd, target)?,
+                _ => {},
+            }
+        }
+        Ok(())
+    }
+}
+
+impl ParsedCommand {
+    /// `set -x` 打印的形式：赋值、命令名和展开后的参数，需要时加上单引号，不包含重定向
+    pub fn trace_line(&self) -> String {
+        let assignments = self.assignments.iter().map(|(name, value)| format!("{}={}", name, quote_for_trace(value)));
+        let words = std::iter::once(&self.name).chain(&self.args).filter(|_| !self.name.is_empty()).map(|word| quote_for_trace(word));
+        assignments.chain(words).collect::<Vec<_>>().join(" ")
+    }
+}
+
+/// 单词中有空白或 Shell 的特殊字符时用单引号括起来，其中的单引号写成 `'\''`
+fn quote_for_trace(word: &str) -> String {
+    let plain = !word.is_empty() && word.chars().all(|c| c.is_alphanumeric() || "_-./:=@%+,^".contains(c));
+    if plain { word.to_string() } else { format!("'{}'", word.replace('\'', "'\\''")) }
+}
+
+/// 把命令段按空白切分成单词，并处理引号。
+/// 引号可以出现在单词中间，例如 `a"b c"d` 得到一个单词 `ab cd`；引号没有闭合时返回 Err。
+/// 引号之外的反斜杠转义下一个字符，例如 `my\ file.txt` 是一个单词。
+/// 引号内的字符会加上反斜杠，使后续的变量展开把它们当作普通字符（展开时会去掉反斜杠）：
+///   - `'...'`：所有内容按字面处理
+///   - `"..."`：内容作为一个整体，但其中的变量引用仍会展开
+///   - `$'...'`：先按 ANSI-C 规则解释转义序列，再按字面处理
+///   - `$"..."`：与双引号相同
+///
+/// 命令替换 `$(...)` 和 `` `...` `` 统一保留成 `$(...)` 的形式，留到展开时执行。
+/// 单词开头没有被引用的 `(` 开始一个子 Shell 组，直到匹配的 `)` 为止的内容原样成为一个 `(...)` 单词。
+/// 单词开头没有被引用的 `#` 开始注释，之后的内容全部忽略；单词中间的 `#`（例如 `foo#bar`）是普通字符。
+pub fn tokenize(command_segment: &str) -> Result<Vec<String>, String> {
+    let mut tokens = Vec::new();
+    let mut current = String::new();
+    let mut in_word = false;
+    let mut braces = 0; // ${...} 的嵌套层数，其中的空白不分割单词
+    let mut chars = command_segment.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        if c.is_whitespace() && braces == 0 {
+            if in_word {
+                tokens.push(std::mem::take(&mut current));
+                in_word = false;
+            }
+        } else if c == '#' && !in_word {
+            break;
+        } else if c == '\\' {
+            // 引号之外的反斜杠让下一个字符失去特殊含义（空格、引号、| 和重定向符等）
+            match chars.next() {
+                Some(escaped) => {
+                    current.push('\\');
+                    current.push(escaped);
+                },
+                None => return Err("行尾不能是单独的反斜杠 (\\)".to_string()),
+            }
+            in_word = true;
+        } else if c == '\'' {
+            loop {
+                match chars.next() {
+                    Some('\'') => break,
+                    Some(literal) => {
+                        current.push('\\');
+                        current.push(literal);
+                    },
+                    None => return Err("缺少结束的单引号 (')".to_string()),
+                }
+            }
+            in_word = true;
+        } else if c == '"' {
+            read_double_quoted(&mut chars, &mut current)?;
+            in_word = true;
+        } else if c == '$' && chars.peek() == Some(&'\'') {
+            chars.next(); // 跳过开头的单引号
+            let mut quoted = String::new();
+            loop {
+                match chars.next() {
+                    Some('\'') => break,
+                    Some('\\') => {
+                        quoted.push('\\');
+                        if let Some(escaped) = chars.next() {
+                            quoted.push(escaped);
+                        }
+                    },
+                    Some(other) => quoted.push(other),
+                    None => return Err("$'...' 缺少结束的单引号".to_string()),
+                }
+            }
+            for literal in decode_ansi_c(&quoted).chars() {
+                current.push('\\');
+                current.push(literal);
+            }
+            in_word = true;
+        } else if c == '$' && chars.peek() == Some(&'"') {
+            // $"..." 是 bash 的本地化字符串，这里不做翻译，按普通双引号处理
+            chars.next();
+            read_double_quoted(&mut chars, &mut current)?;
+            in_word = true;
+        } else if c == '$' && chars.peek() == Some(&'{') {
+            // ${NAME:-word} 中的 word 可以有空格和引号，引号照常转成转义
+            chars.next();
+            current.push_str("${");
+            braces += 1;
+            in_word = true;
+        } else if (c == '$' && chars.peek() == Some(&'(')) || c == '`' {
+            if c == '$' {
+                chars.next();
+            }
+            let inner = read_command_substitution(&mut chars, c == '`')?;
+            current.push_str(&format!("$({})", inner));
+            in_word = true;
+        } else if (c == '<' || c == '>') && chars.peek() == Some(&'(') && !in_word {
+            // 单词开头的 <(...) 和 >(...) 是进程替换，整体保留，展开时再启动其中的命令
+            chars.next();
+            let inner = read_command_substitution(&mut chars, false).map_err(|_| format!("缺少结束的括号 ({}(...))", c))?;
+            current.push_str(&format!("{}({})", c, inner));
+            in_word = true;
+        } else if c == '(' && !in_word {
+            let inner = read_command_substitution(&mut chars, false).map_err(|_| "缺少结束的括号 ((...))".to_string())?;
+            tokens.push(format!("({})", inner));
+        } else {
+            match c {
+                '{' if braces > 0 => braces += 1,
+                '}' if braces > 0 => braces -= 1,
+                _ => {},
+            }
+            current.push(c);
+            in_word = true;
+        }
+    }
+    if in_word {
+        tokens.push(current);
+    }
+    Ok(tokens)
+}
+
+/// 把紧跟着文件名的重定向操作符（`>out.txt`、`2>>err.log`）拆成操作符和文件名。
+/// here-document、here-string 和 `2>&1` 另外处理；被引号或反斜杠保护的 `>` 以 `\` 开头，不会被当成操作符。
+/// `<(...)` 和 `>(...)` 是进程替换，不是重定向。
+fn split_attached_redirection(part: &str) -> Option<(&str, &str)> {
+    if part.starts_with("<<") || part == "2>&1" {
+        return None;
+    }
+    ["2>>", "&>>", ">>", "2>", "&>", ">", "<"]
+        .iter()
+        .find_map(|operator| part.strip_prefix(operator).map(|target| (*operator, target)))
+        .filter(|(_, target)| !target.is_empty() && !target.starts_with(['&', '(']))
+}
+
+/// 读取双引号中的内容（开头的引号已被消费），追加到当前单词。
+/// 除了 `$` 开头的变量引用外，所有字符都加上反斜杠保护；
+/// 反斜杠只转义 `$`、`"` 和 `\\`，其他情况下保留为普通字符。
+fn read_double_quoted(chars: &mut Peekable<Chars<'_>>, current: &mut String) -> Result<(), String> {
+    loop {
+        match chars.next() {
+            Some('"') => return Ok(()),
+            Some('\\') => match chars.peek() {
+                Some(&escaped) if matches!(escaped, '$' | '"' | '\\') => {
+                    chars.next();
+                    current.push('\\');
+                    current.push(escaped);
+                },
+                _ => current.push_str("\\\\"),
+            },
+            Some('`') => {
+                let inner = read_command_substitution(chars, true)?;
+                current.push_str(&format!("\"$({})\"", inner));
+            },
+            Some('$') if chars.peek() == Some(&'(') => {
+                // 双引号中的命令替换用不带转义的引号包起来，展开时输出不做字段分割
+                chars.next();
+                let inner = read_command_substitution(chars, false)?;
+                current.push_str(&format!("\"$({})\"", inner));
+            },
+            Some('$') if chars.peek() == Some(&'{') => {
+                // ${...} 保持原样，用不带转义的引号包起来，`${NAME:-word}` 中的 word 展开后也不做字段分割
+                current.push_str("\"$");
+                let mut depth = 0;
+                for c in chars.by_ref() {
+                    current.push(c);
+                    match c {
+                        '{' => depth += 1,
+                        '}' if depth == 1 => break,
+                        '}' => depth -= 1,
+                        _ => {},
+                    }
+                }
+                current.push('"');
+            },
+            Some('$') => {
+                // 变量引用留给 expand_variables 处理，和 ${...} 一样用不带转义的引号包起来，展开后不做字段分割。
+                // 变量名写成 `${NAME}`，否则 `"$HOME"x` 中引号后面的字符会被当成变量名的一部分
+                if chars.peek().is_some_and(|&c| c.is_ascii_digit() || c == '?' || c == '#') {
+                    current.push_str("\"$");
+                    current.extend(chars.next());
+                    current.push('"');
+                } else {
+                    let mut name = String::new();
+                    while let Some(&c) = chars.peek() {
+                        if c != '_' && !c.is_ascii_alphanumeric() {
+                            break;
+                        }
+                        name.push(c);
+                        chars.next();
+                    }
+                    if name.is_empty() {
+                        current.push_str("\\$"); // 后面不是变量名的 `$` 是普通字符
+                    } else {
+                        current.push_str(&format!("\"${{{}}}\"", name));
+                    }
+                }
+            },
+            Some(other) => {
+                current.push('\\');
+                current.push(other);
+            },
+            None => return Err("缺少结束的双引号 (\")".to_string()),
+        }
+    }
+}
+
+/// 读取命令替换 `$(...)` 或 `` `...` `` 的内容（开头的 `$(` 或反引号已被消费），原样返回。
+/// `$(...)` 中的括号可以嵌套，引号中的括号不参与配对。
+fn read_command_substitution(chars: &mut Peekable<Chars<'_>>, backquoted: bool) -> Result<String, String> {
+    let mut inner = String::new();
+    let mut depth = 0;
+    let mut quote: Option<char> = None;
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' if quote != Some('\'') => {
+                match chars.next() {
+                    // 反引号中的 \` 表示嵌套的反引号
+                    Some('`') if backquoted => inner.push('`'),
+                    Some(escaped) => {
+                        inner.push('\\');
+                        inner.push(escaped);
+                    },
+                    None => break,
+                }
+                continue;
+            },
+            '`' if backquoted => return Ok(inner),
+            '\'' | '"' if quote.is_none() => quote = Some(c),
+            _ if quote == Some(c) => quote = None,
+            '(' if quote.is_none() && !backquoted => depth += 1,
+            ')' if quote.is_none() && !backquoted => {
+                if depth == 0 {
+                    return Ok(inner);
+                }
+                depth -= 1;
+            },
+            _ => {},
+        }
+        inner.push(c);
+    }
+    Err(if backquoted { "缺少结束的反引号 (`)" } else { "缺少结束的括号 ($(...))" }.to_string())
+}
+
+/// 解释 `$'...'` 中的转义序列：`\n`、`\t`、`\\`、`\'`、八进制 `\nnn`、
+/// 十六进制 `\xHH`、Unicode `\uHHHH`/`\UHHHHHHHH` 以及控制字符 `\cX` 等。
+/// 无法识别的转义保持原样。
+fn decode_ansi_c(quoted: &str) -> String {
+    let chars: Vec<char> = quoted.chars().collect();
+    let mut result = String::new();
+    let mut i = 0;
+
+    // 从 chars[start..] 读取最多 max 个指定进制的数字，返回 (数值, 读取的位数)
+    let read_digits = |start: usize, max: usize, radix: u32| -> (u32, usize) {
+        let mut value = 0;
+        let mut len = 0;
+        while len < max {
+            match chars.get(start + len).and_then(|c| c.to_digit(radix)) {
+                Some(d) => value = value * radix + d,
+                None => break,
+            }
+            len += 1;
+        }
+        (value, len)
+    };
+
+    while i < chars.len() {
+        if chars[i] != '\\' || i + 1 == chars.len() {
+            result.push(chars[i]);
+            i += 1;
+            continue;
+        }
+
+        let escape = chars[i + 1];
+        i += 2;
+        match escape {
+            'a' => result.push('\u{07}'),
+            'b' => result.push('\u{08}'),
+            'e' | 'E' => result.push('\u{1b}'),
+            'f' => result.push('\u{0c}'),
+            'n' => result.push('\n'),
+            'r' => result.push('\r'),
+            't' => result.push('\t'),
+            'v' => result.push('\u{0b}'),
+            '\\' | '\'' | '"' | '?' => result.push(escape),
+            '0'..='7' => {
+                let (value, len) = read_digits(i - 1, 3, 8);
+                result.push(char::from_u32(value).unwrap_or('\u{fffd}'));
+                i += len - 1;
+            },
+            'x' | 'u' | 'U' => {
+                let max = match escape { 'x' => 2, 'u' => 4, _ => 8 };
+                let (value, len) = read_digits(i, max, 16);
+                if len == 0 {
+                    result.push('\\');
+                    result.push(escape);
+                } else {
+                    result.push(char::from_u32(value).unwrap_or('\u{fffd}'));
+                    i += len;
+                }
+            },
+            'c' if i < chars.len() => {
+                // \cX 表示 Ctrl-X
+                result.push(char::from_u32(chars[i].to_ascii_uppercase() as u32 ^ 0x40).unwrap_or('\u{fffd}'));
+                i += 1;
+            },
+            _ => {
+                result.push('\\');
+                result.push(escape);
+            }
+        }
+    }
+    result
+}
+
+/// 将单个命令行字符串（不含管道）解析为 ParsedCommand 结构体。
+/// 这个解析器是基础版本：它处理以空格分隔的参数和简单的 I/O 重定向（<, >, >>, 2>, 2>>, 2>&1, &>, &>>, <<, <<<）。
+/// `&>` 与 `> file 2>&1` 完全等价，在管道中间的命令上也和 `>` 一样优先于管道：两个流都写入文件。
+/// 单词的切分、引号和反斜杠转义由 tokenize 完成，命令替换 `$(...)` 在展开时执行。
+/// 命令名之前形如 `NAME=value` 的单词是变量赋值，例如 `MSG="hello world" cmd`。
+pub fn parse_single_command(command_segment: &str) -> Result<ParsedCommand, String> {
+    // case 和 select 命令到配对的 esac 或 done 为止整体解析，之后只能有重定向
+    let (case, command_segment) = match parse_case(command_segment)? {
+        Some((case, rest)) => (Some(case), rest),
+        None => (None, command_segment),
+    };
+    let (select, command_segment) = match parse_select(command_segment)? {
+        Some((select, rest)) => (Some(select), rest),
+        None => (None, command_segment),
+    };
+    let compound = case.is_some() || select.is_some();
+    let mut parts = tokenize(command_segment)?;
+    if compound {
+        // 复合命令没有命令名，占住第一个位置，之后的部分按重定向处理
+        parts.insert(0, String::new());
+    }
+    trace!("tokens: {:?}", parts);
+    if parts.is_empty() {
+        return Err("空命令段".to_string());
+    }
+
+    let assignments: Vec<(String, String)> = parts.iter().map_while(|part| split_assignment(part)).collect();
+    let parts = &parts[assignments.len()..];
+
+    let mut name = parts.first().cloned().unwrap_or_default();
+    // tokenize 只会在子 Shell 组的开头留下没有转义的 (
+    let group = name.starts_with('(').then(|| name[1..name.len() - 1].trim().to_string());
+    if let Some(body) = &group {
+        if body.is_empty() {
+            return Err("`)' 附近有语法错误".to_string());
+        }
+        if !assignments.is_empty() {
+            return Err(format!("`{}' 附近有语法错误", name));
+        }
+        name = String::new();
+    }
+    // 操作符和文件名之间可以没有空格（>out.txt），先把它们拆成两个部分
+    let parts: Vec<String> = parts
+        .iter()
+        .enumerate()
+        .flat_map(|(index, part)| match split_attached_redirection(part).filter(|_| index > 0) {
+            Some((operator, target)) => vec![operator.to_string(), target.to_string()],
+            None => vec![part.clone()],
+        })
+        .collect();
+    let mut args = Vec::new();
+    let mut stdin_redirect: Option<String> = None;
+    let mut stdout_redirect: Option<(String, bool)> = None;
+    let mut stderr_redirect: Option<(String, bool)> = None;
+    let mut output_operations: Vec<OutputOperation> = Vec::new();
+    let mut heredocs: Vec<HereDoc> = Vec::new();
+    let mut herestring: Option<String> = None;
+
+    let mut i = 1; // 从第二个部分开始处理
+    while i < parts.len() {
+        match parts[i].as_str() {
+            "<" => {
+                if i + 1 < parts.len() {
+                    stdin_redirect = Some(parts[i+1].to_string());
+                    i += 2; // 跳过操作符和文件名
+                } else {
+                    return Err("输入重定向缺少文件名 (<)".to_string());
+                }
+            },
+            ">" => {
+                if i + 1 < parts.len() {
+                    stdout_redirect = Some((parts[i+1].to_string(), false)); // false 表示覆盖模式
+                    output_operations.push(OutputOperation::StdoutToFile);
+                    i += 2; // 跳过操作符和文件名
+                } else {
+                    return Err("输出重定向缺少文件名 (>)\nmy_shell: 解析错误:".to_string());
+                }
+            },
+            ">>" => {
+                if i + 1 < parts.len() {
+                    stdout_redirect = Some((parts[i+1].to_string(), true)); // true 表示追加模式
+                    output_operations.push(OutputOperation::StdoutToFile);
+                    i += 2; // 跳过操作符和文件名
+                } else {
+                    return Err("输出重定向缺少文件名 (>>)".to_string());
+                }
+            },
+            "2>" | "2>>" => {
+                if i + 1 < parts.len() {
+                    stderr_redirect = Some((parts[i+1].to_string(), parts[i] == "2>>"));
+                    output_operations.push(OutputOperation::StderrToFile);
+                    i += 2; // 跳过操作符和文件名
+                } else {
+                    return Err(format!("标准错误重定向缺少文件名 ({})", parts[i]));
+                }
+            },
+            "&>" | "&>>" => {
+                // &> file 等价于 > file 2>&1，&>> 则以追加模式打开
+                if i + 1 < parts.len() {
+                    stdout_redirect = Some((parts[i+1].to_string(), parts[i] == "&>>"));
+                    output_operations.push(OutputOperation::StdoutToFile);
+                    output_operations.push(OutputOperation::Duplicate { fd: 2, target: 1 });
+                    i += 2; // 跳过操作符和文件名
+                } else {
+                    return Err(format!("输出重定向缺少文件名 ({})", parts[i]));
+                }
+            },
+            part if part.split_once(">&").is_some_and(|(fd, _)| fd.bytes().all(|b| b.is_ascii_digit())) => {
+                // N>&M 和 >&M（即 1>&M），记录它与文件重定向的先后顺序
+                let (fd, target) = part.split_once(">&").unwrap_or_default();
+                let (fd, target) = match (if fd.is_empty() { Ok(1) } else { fd.parse::<u32>() }, target.parse::<u32>()) {
+                    (Ok(fd), Ok(target)) => (fd, target),
+                    _ => return Err(format!("`{}' 附近有语法错误", part)),
+                };
+                if let Some(unsupported) = [fd, target].into_iter().find(|n| !(1..=2).contains(n)) {
+                    return Err(format!("暂不支持复制文件描述符 {} ({})", unsupported, part));
+                }
+                output_operations.push(OutputOperation::Duplicate { fd, target });
+                i += 1;
+            },
+            "<<<" => {
+                if i + 1 < parts.len() {
+                    herestring = Some(parts[i+1].to_string());
+                    i += 2; // 跳过操作符和单词
+                } else {
+                    return Err("here-string 缺少单词 (<<<)".to_string());
+                }
+            },
+            part if part.starts_with("<<<") => {
+                // 单词紧跟在操作符后面，例如 <<<word
+                herestring = Some(part[3..].to_string());
+                i += 1;
+            },
+            "<<" | "<<-" => {
+                if i + 1 < parts.len() {
+                    heredocs.push(HereDoc::new(&parts[i+1], parts[i] == "<<-"));
+                    i += 2; // 跳过操作符和结束标记
+                } else {
+                    return Err(format!("here-document 缺少结束标记 ({})", parts[i]));
+                }
+            },
+            part if part.starts_with("<<") && !part.starts_with("<<<") => {
+                // 结束标记紧跟在操作符后面，例如 <<EOF 或 <<-'EOF'
+                match part.strip_prefix("<<-") {
+                    Some(word) => heredocs.push(HereDoc::new(word, true)),
+                    None => heredocs.push(HereDoc::new(&part[2..], false)),
+                }
+                i += 1;
+            },
+            part if group.is_some() || compound => {
+                // 子 Shell 组和复合命令之后只能有重定向
+                return Err(format!("`{}' 附近有语法错误", part));
+            },
+            _ => {
+                // 如果不是重定向操作符，则将其作为参数
+                args.push(parts[i].to_string());
+                i += 1;
+            }
+        }
+    }
+
+    if stdin_redirect.is_some() && herestring.is_some() {
+        return Err("不能同时使用 < 和 <<< 重定向标准输入".to_string());
+    }
+
+    Ok(ParsedCommand {
+        assignments,
+        name,
+        group,
+        case,
+        select,
+        args,
+        stdin_redirect,
+        stdout_redirect,
+        stderr_redirect,
+        output_operations,
+        pipe_stderr: false,
+        heredocs,
+        herestring,
+        background: false,
+    })
+}
+
+/// 把 `NAME=value` 形式的单词拆成变量名和（仍带转义的）值。
+/// 变量名必须是合法的标识符，等号不能被引用，例如 `"A=1"` 和 `A\=1` 都不是赋值。
+fn split_assignment(word: &str) -> Option<(String, String)> {
+    let (name, value) = word.split_once('=')?;
+    is_identifier(name).then(|| (name.to_string(), value.to_string()))
+}
+
+/// 命令列表中一条管道与前一条管道的连接方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Connector {
+    Always, // 第一条管道或 `;` 之后：总是执行
+    And,    // &&：前一条管道成功（状态为 0）时才执行
+    Or,     // ||：前一条管道失败时才执行
+}
+
+/// 命令列表中的一条管道
+#[derive(Debug, Clone)]
+pub struct ListItem {
+    pub connector: Connector,
+    pub negated: bool, // 管道前有 `!`
+    pub timed: bool,   // 管道前有 `time`
+    pub commands: Vec<ParsedCommand>,
+}
+
+/// 解析由 `;`、`&`、`&&` 和 `||` 连接的命令列表。
+/// 运算符的优先级低于管道，`a | b && c` 解析为 `(a | b) && c`；`a & b` 在后台运行 a 后接着运行 b。
+/// 引号、反斜杠转义和命令替换中的运算符不参与分割。
+/// `;` 两侧的空命令（例如 `ls ;; pwd` 或行尾的 `;`）直接忽略。
+pub fn parse_command_list(command_line: &str) -> Result<Vec<ListItem>, String> {
+    let mut items = Vec::new();
+    let mut connector = Connector::Always;
+    let mut rest = command_line;
+    let mut after_background = false; // 上一条管道是否以后台运行的 `&` 结束
+
+    loop {
+        // 分隔符是单独的 `&` 时，它留在 segment 末尾，end 和 next 相同
+        let (segment, next, background) = match find_list_operator(rest) {
+            Some((end, next, op)) => (&rest[..end], Some((&rest[next..], op)), end == next),
+            None => (rest, None, false),
+        };
+
+        // 和 bash 一样，`time` 和 `!` 哪个在前都可以
+        let (time_first, segment) = parse_time(segment.trim());
+        let (negated, segment) = parse_negation(segment);
+        let (time_after, segment) = parse_time(segment);
+        let timed = time_first || time_after;
+        if segment.is_empty() && !negated {
+            let next_op = next.map(|(_, op)| op);
+            if connector != Connector::Always {
+                return Err("`&&' 或 `||' 之后缺少命令".to_string());
+            }
+            if next_op.is_some_and(|op| op != Connector::Always) {
+                return Err(format!("`{}' 附近有语法错误", if next_op == Some(Connector::And) { "&&" } else { "||" }));
+            }
+            // `&;` 中 `&` 已经结束了一条命令，后面的 `;` 和 bash 一样是语法错误
+            if after_background && next_op.is_some() {
+                return Err("`;' 附近有语法错误".to_string());
+            }
+        } else {
+            if segment.is_empty() {
+                return Err("`!' 之后缺少命令".to_string());
+            }
+            if segment == "&" {
+                return Err("`&' 附近有语法错误".to_string());
+            }
+            let commands = parse_pipeline_commands(segment)?;
+            items.push(ListItem { connector, negated, timed, commands });
+        }
+
+        after_background = background;
+        match next {
+            Some((next, op)) => {
+                rest = next;
+                connector = op;
+            },
+            None => break,
+        }
+    }
+    Ok(items)
+}
+
+/// 识别管道前的保留字 `time`，返回 (是否统计整条管道的执行时间, 剩余的命令行)。
+/// 后面没有命令的 `time` 仍然是普通的命令名，由 time 内置命令处理。
+pub fn parse_time(command_line: &str) -> (bool, &str) {
+    match command_line.strip_prefix("time") {
+        Some(after) if after.starts_with(char::is_whitespace) && !after.trim().is_empty() => (true, after.trim_start()),
+        _ => (false, command_line),
+    }
+}
+
+/// 查找第一个不在引号、转义、命令替换、子 Shell 组、case 或 select 命令中的 `;`、`&`、`&&` 或 `||`，
+/// 返回 (前一条管道结束的字节位置, 下一条管道开始的字节位置, 连接方式)。
+/// 单独的 `&` 留在前一条管道的末尾，由 `strip_background` 识别成后台运行；
+/// `|&`、`&>`、`>&` 和 `<&` 中的 `&` 属于管道或重定向，不是分隔符。
+fn find_list_operator(text: &str) -> Option<(usize, usize, Connector)> {
+    let bytes = text.as_bytes();
+    let mut quote: Option<u8> = None;
+    let mut depth = 0; // $(...) 和 (...) 的嵌套层数
+    let mut i = 0;
+    while i < bytes.len() {
+        let c = bytes[i];
+        match c {
+            b'\\' if quote != Some(b'\'') => i += 1,
+            b'\'' | b'"' | b'`' if quote.is_none() => quote = Some(c),
+            _ if quote == Some(c) => quote = None,
+            _ if quote.is_some() => {},
+            b'$' if bytes.get(i + 1) == Some(&b'(') => {
+                depth += 1;
+                i += 1;
+            },
+            b'(' => depth += 1,
+            b')' if depth > 0 => depth -= 1,
+            // case 和 select 命令中的 `;;`、`;&`、`;` 和 `|` 属于复合命令本身
+            b'c' | b's' if depth == 0 && let Some(end) = compound_at(text, i, false) => {
+                i = end?;
+                continue;
+            },
+            b'&' | b'|' if depth == 0 && bytes.get(i + 1) == Some(&c) => {
+                return Some((i, i + 2, if c == b'&' { Connector::And } else { Connector::Or }));
+            },
+            b'&' if depth == 0
+                && bytes.get(i + 1) != Some(&b'>')
+                && !(i > 0 && matches!(bytes[i - 1], b'>' | b'<' | b'|')) =>
+            {
+                return Some((i + 1, i + 1, Connector::Always));
+            },
+            b';' if depth == 0 => return Some((i, i + 1, Connector::Always)),
+            _ => {},
+        }
+        i += 1;
+    }
+    None
+}
+
+/// 别名展开：把每条简单命令的第一个单词替换成别名的值，发生在切分单词之前。
+/// 带引号或反斜杠的单词不会匹配别名；替换进来的文本不再展开，所以 `alias ls='ls -F'` 不会无限递归。
+/// 命令替换、子 Shell 组、case 和 select 命令中的命令在执行时才展开。
+pub fn expand_aliases(command_line: &str, aliases: &HashMap<String, String>) -> String {
+    if aliases.is_empty() {
+        return command_line.to_string();
+    }
+    let bytes = command_line.as_bytes();
+    let mut result = String::with_capacity(command_line.len());
+    let mut copied = 0; // command_line 中已经复制到 result 的部分
+    let mut quote: Option<u8> = None;
+    let mut depth = 0; // $(...) 和 (...) 的嵌套层数
+    let mut command_start = true; // 下一个单词是否是命令名
+    let mut i = 0;
+    while i < bytes.len() {
+        let c = bytes[i];
+        if command_start && quote.is_none() && depth == 0 && !c.is_ascii_whitespace() {
+            command_start = false;
+            let end = command_line[i..]
+                .find(|c: char| c.is_whitespace() || "|&;<>()".contains(c))
+                .map_or(bytes.len(), |len| i + len);
+            let word = &command_line[i..end];
+            if word == "!" || word == "time" {
+                // 取反的 ! 和 time 之后才是命令名
+                command_start = true;
+                i = end;
+                continue;
+            }
+            if let Some(Some(end)) = compound_at(command_line, i, false) {
+                // case 和 select 命令中的命令在执行时才展开
+                i = end;
+                continue;
+            }
+            if let Some(value) = aliases.get(word) {
+                trace!("alias: {} -> {}", word, value);
+                result.push_str(&command_line[copied..i]);
+                result.push_str(value);
+                copied = end;
+                i = end;
+                continue;
+            }
+        }
+        match c {
+            b'\\' if quote != Some(b'\'') => i += 1,
+            b'\'' | b'"' | b'`' if quote.is_none() => quote = Some(c),
+            _ if quote == Some(c) => quote = None,
+            _ if quote.is_some() => {},
+            b'$' if bytes.get(i + 1) == Some(&b'(') => {
+                depth += 1;
+                i += 1;
+            },
+            b'(' => depth += 1,
+            b')' if depth > 0 => depth -= 1,
+            b'|' | b';' if depth == 0 => command_start = true,
+            // 2>&1、>&2 和 &> 中的 & 是重定向的一部分
+            b'&' if depth == 0 && i.checked_sub(1).map(|p| bytes[p]) != Some(b'>') && bytes.get(i + 1) != Some(&b'>') => {
+                command_start = true;
+            },
+            _ => {},
+        }
+        i += 1;
+    }
+    result.push_str(&command_line[copied..]);
+    result
+}
+
+/// 识别管道前的保留字 `!`，返回 (是否对退出状态取反, 剩余的命令行)。
+/// `!` 必须是独立的单词，`!cmd` 不算；连续多个 `!` 相互抵消。
+pub fn parse_negation(command_line: &str) -> (bool, &str) {
+    let mut negated = false;
+    let mut rest = command_line.trim_start();
+    while let Some(after) = rest.strip_prefix('!') {
+        if !after.starts_with(char::is_whitespace) {
+            break;
+        }
+        negated = !negated;
+        rest = after.trim_start();
+    }
+    (negated, rest)
+}
+
+/// 解析包含管道符的完整命令行。
+/// 将命令行分割成多个命令段，并为每个命令段调用 parse_single_command。
+/// `|&` 与 `|` 一样分割命令，但会让左侧命令的 stderr 也进入管道。
+/// 行尾单独的 `&` 表示在后台运行整条管道，它不会成为最后一个命令的参数。
+pub fn parse_pipeline_commands(command_line: &str) -> Result<Vec<ParsedCommand>, String> {
+    let mut commands = Vec::new();
+    let (mut rest, background) = strip_background(command_line);
+
+    loop {
+        let (segment, next, pipe_stderr) = match find_unescaped(rest, '|') {
+            Some(pos) if rest[pos + 1..].starts_with('&') => (&rest[..pos], Some(&rest[pos + 2..]), true),
+            Some(pos) => (&rest[..pos], Some(&rest[pos + 1..]), false),
+            None => (rest, None, false),
+        };
+
+        if segment.trim().is_empty() {
+            return Err("管道符 ' | ' 后不能有空命令.".to_string());
+        }
+        let mut command = parse_single_command(segment.trim())?;
+        command.pipe_stderr = pipe_stderr;
+        commands.push(command);
+
+        match next {
+            Some(next) => rest = next,
+            None => break,
+        }
+    }
+    for command in commands.iter_mut() {
+        command.background = background;
+    }
+    trace!("parsed: {:?}", commands);
+    Ok(commands)
+}
+
+/// 去掉行尾表示后台运行的 `&`，返回 (剩余的命令行, 是否在后台运行)。
+/// `\&`、`&&` 和 `|&` 结尾的情况不算。
+fn strip_background(command_line: &str) -> (&str, bool) {
+    let trimmed = command_line.trim_end();
+    let Some(rest) = trimmed.strip_suffix('&') else {
+        return (command_line, false);
+    };
+    let backslashes = rest.chars().rev().take_while(|&c| c == '\\').count();
+    if backslashes % 2 == 1 || rest.ends_with(['&', '|']) {
+        return (command_line, false);
+    }
+    (rest, true)
+}
+
+/// 查找第一个没有被反斜杠转义、也不在引号、命令替换、子 Shell 组、case 或 select 命令中的字符 `target`，返回它的字节位置
+fn find_unescaped(text: &str, target: char) -> Option<usize> {
+    let mut chars = text.char_indices().peekable();
+    let mut previous = ' ';
+    while let Some((pos, c)) = chars.next() {
+        if let Some(end) = compound_at(text, pos, false) {
+            let end = end?;
+            while chars.next_if(|&(next, _)| next < end).is_some() {}
+            previous = 'c';
+            continue;
+        }
+        // 单词开头的 ( 是子 Shell 组，< 和 > 之后的 ( 是进程替换
+        let group = c == '(' && (previous.is_whitespace() || matches!(previous, '|' | '&' | ';' | '(' | '<' | '>'));
+        previous = c;
+        if c == '\\' {
+            chars.next(); // 跳过被转义的字符
+        } else if group || (c == '$' && chars.peek().is_some_and(|&(_, next)| next == '(')) {
+            // 跳过 $(...) 和 (...)，其中的括号可以嵌套
+            let mut depth = if group { 1 } else { 0 };
+            for (_, c) in chars.by_ref() {
+                match c {
+                    '(' => depth += 1,
+                    ')' if depth == 1 => break,
+                    ')' => depth -= 1,
+                    _ => {},
+                }
+            }
+        } else if c == '`' || c == '\'' {
+            for (_, next) in chars.by_ref() {
+                if next == c {
+                    break;
+                }
+            }
+        } else if c == '"' {
+            // 双引号中只有反斜杠能转义字符，包括 \"
+            while let Some((_, next)) = chars.next() {
+                match next {
+                    '\\' => {
+                        chars.next();
+                    },
+                    '"' => break,
+                    _ => {},
+                }
+            }
+        } else if c == target {
+            return Some(pos);
+        }
+    }
+    None
+}
+
+/// case 命令：`case WORD in PATTERN) LIST ;; ... esac`
+#[derive(Debug, Clone)]
+pub struct CaseCommand {
+    pub text: String,             // 从 case 到 esac 的原文，用于回显命令
+    pub word: String,             // 要匹配的单词（tokenize 之后，仍带转义）
+    pub clauses: Vec<CaseClause>,
+}
+
+/// case 命令的一个分支
+#[derive(Debug, Clone)]
+pub struct CaseClause {
+    pub patterns: Vec<String>,       // `|` 分隔的模式（tokenize 之后，仍带转义），执行时才展开
+    pub body: String,                // 匹配时执行的命令列表，原样保留到执行时再解析
+    pub terminator: CaseTerminator,
+}
+
+/// 分支命令列表的结束方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CaseTerminator {
+    Break,       // ;;（或者直接遇到 esac）：结束 case 命令
+    FallThrough, // ;&：不再匹配，接着执行下一个分支的命令列表
+    Continue,    // ;;&：继续用后面分支的模式匹配
+}
+
+/// 复合命令的开始保留字和配对的结束保留字
+const COMPOUND_KEYWORDS: [(&str, &str); 2] = [("case", "esac"), ("select", "done")];
+
+/// 判断 `text` 中第 `i` 个字节开始的是否是保留字 `word`：
+/// 它必须是一个完整的单词，并且出现在命令名的位置上。
+/// `in_compound` 为 true 时位于复合命令内部，模式结尾的 `)` 和 `case WORD in` 的 `in` 之后也是命令名的位置。
+fn keyword_at(text: &str, i: usize, word: &str, in_compound: bool) -> bool {
+    // 先按字节比较，`i` 可能在多字节字符的中间
+    if !text.as_bytes()[i..].starts_with(word.as_bytes()) {
+        return false;
+    }
+    let after = &text[i + word.len()..];
+    let ends = match word {
+        "case" | "select" => after.starts_with(char::is_whitespace),
+        _ => after.is_empty() || after.starts_with(|c: char| c.is_whitespace() || ";&|)".contains(c)),
+    };
+    ends && command_position(&text[..i], in_compound)
+}
+
+/// 判断紧跟在 `before` 之后的单词是否是命令名
+fn command_position(before: &str, in_compound: bool) -> bool {
+    let before = before.trim_end_matches([' ', '\t']);
+    if before.is_empty() || before.ends_with(['\n', ';', '&', '|', '(']) || (in_compound && before.ends_with(')')) {
+        return true;
+    }
+    let (rest, last) = before.rsplit_once(char::is_whitespace).unwrap_or(("", before));
+    match last {
+        "!" | "time" => command_position(rest, in_compound),
+        // 只有紧跟在 `case WORD` 之后的 in 才是保留字
+        "in" => in_compound && rest.split_whitespace().count() == 1,
+        _ => false,
+    }
+}
+
+/// `text` 中第 `i` 个字节开始的是 case 或 select 命令时，返回 Some(配对的 esac 或 done 之后的字节位置)，
+/// 嵌套的复合命令各自配对；还没有结束保留字时返回 Some(None)。
+fn compound_at(text: &str, i: usize, in_compound: bool) -> Option<Option<usize>> {
+    let (open, close) = COMPOUND_KEYWORDS.into_iter().find(|(open, _)| keyword_at(text, i, open, in_compound))?;
+    let body = i + open.len();
+    let inner = &text[body..];
+    match scan_top_level(inner, true, |j| keyword_at(inner, j, close, true)) {
+        Ok(Some(end)) => Some(Some(body + end + close.len())),
+        _ => Some(None),
+    }
+}
+
+/// 依次查看 `text` 中不在引号、反斜杠转义、括号或嵌套的复合命令中的字节，
+/// 返回第一个使 `found` 为 true 的字节位置。没有配对的 `)` 也交给 `found` 判断（case 分支的模式以它结束）。
+/// 遇到没有结束保留字的复合命令时返回 Err(它开始的位置)。
+fn scan_top_level(text: &str, in_compound: bool, mut found: impl FnMut(usize) -> bool) -> Result<Option<usize>, usize> {
+    let bytes = text.as_bytes();
+    let mut quote: Option<u8> = None;
+    let mut depth = 0; // $(...) 和 (...) 的嵌套层数
+    let mut i = 0;
+    while i < bytes.len() {
+        let c = bytes[i];
+        match quote {
+            Some(b'\'') if c == b'\'' => quote = None,
+            Some(b'\'') => {},
+            Some(_) if c == b'\\' => i += 1,
+            Some(open) if c == open => quote = None,
+            Some(_) => {},
+            None => match c {
+                b'\\' => i += 1,
+                b'\'' | b'"' | b'`' => quote = Some(c),
+                b'(' => depth += 1,
+                b')' if depth > 0 => depth -= 1,
+                _ if depth > 0 => {},
+                _ => match compound_at(text, i, in_compound) {
+                    Some(Some(end)) => {
+                        i = end;
+                        continue;
+                    },
+                    Some(None) => return Err(i),
+                    None if found(i) => return Ok(Some(i)),
+                    None => {},
+                },
+            },
+        }
+        i += 1;
+    }
+    Ok(None)
+}
+
+/// 命令行中是否有还没有结束保留字的复合命令（引号和括号中的不算）
+fn unclosed_compound(line: &str) -> bool {
+    scan_top_level(line, false, |_| false).is_err()
+}
+
+/// 如果命令段以保留字 `case` 开头，解析到配对的 `esac` 为止，返回 case 命令和 esac 之后剩下的部分。
+/// 分支之间和 esac 之前的换行符和 `;` 一样是分隔符；分支的命令列表中引号之外的换行符换成 `;`。
+pub fn parse_case(command_segment: &str) -> Result<Option<(CaseCommand, &str)>, String> {
+    let text = command_segment.trim_start();
+    if !keyword_at(text, 0, "case", false) {
+        return Ok(None);
+    }
+    let end = compound_at(text, 0, false).flatten().ok_or("`case' 缺少配对的 `esac'")?;
+    let (source, rest) = text.split_at(end);
+    let inner = source["case".len()..source.len() - "esac".len()].trim_start();
+
+    let word_end = scan_top_level(inner, true, |i| inner.as_bytes()[i].is_ascii_whitespace() || inner.as_bytes()[i] == b';')
+        .ok()
+        .flatten()
+        .unwrap_or(inner.len());
+    let word = match tokenize(&inner[..word_end])?.as_slice() {
+        [word] => word.clone(),
+        _ => return Err("`case' 之后缺少单词".to_string()),
+    };
+    let mut clauses_text = inner[word_end..]
+        .trim_start()
+        .strip_prefix("in")
+        .filter(|after| after.is_empty() || after.starts_with(|c: char| c.is_whitespace() || c == ';'))
+        .ok_or("`case' 的单词之后缺少 `in'")?;
+
+    let mut clauses = Vec::new();
+    loop {
+        clauses_text = clauses_text.trim_start_matches(|c: char| c.is_whitespace() || c == ';');
+        if clauses_text.is_empty() {
+            break;
+        }
+        // 模式之前可以有一个 `(`
+        let text = clauses_text.strip_prefix('(').unwrap_or(clauses_text);
+        let bytes = text.as_bytes();
+        let close = scan_top_level(text, true, |i| bytes[i] == b')')
+            .ok()
+            .flatten()
+            .ok_or_else(|| format!("`{}' 附近有语法错误：模式缺少 `)'", text.split_whitespace().next().unwrap_or(text)))?;
+        let mut patterns = Vec::new();
+        let mut list = &text[..close];
+        loop {
+            let list_bytes = list.as_bytes();
+            let bar = scan_top_level(list, true, |i| list_bytes[i] == b'|').ok().flatten();
+            let pattern = &list[..bar.unwrap_or(list.len())];
+            match tokenize(pattern)?.as_slice() {
+                [pattern] => patterns.push(pattern.clone()),
+                _ => return Err(format!("`{})' 附近有语法错误", &text[..close])),
+            }
+            match bar {
+                Some(bar) => list = &list[bar + 1..],
+                None => break,
+            }
+        }
+
+        let after = &text[close + 1..];
+        let after_bytes = after.as_bytes();
+        let terminator_at = scan_top_level(after, true, |i| after_bytes[i] == b';' && matches!(after_bytes.get(i + 1), Some(b';' | b'&')))
+            .map_err(|_| "`case' 缺少配对的 `esac'".to_string())?;
+        let (body, terminator, next) = match terminator_at {
+            Some(i) if after[i..].starts_with(";;&") => (&after[..i], CaseTerminator::Continue, &after[i + 3..]),
+            Some(i) if after[i..].starts_with(";;") => (&after[..i], CaseTerminator::Break, &after[i + 2..]),
+            Some(i) => (&after[..i], CaseTerminator::FallThrough, &after[i + 2..]),
+            None => (after, CaseTerminator::Break, ""),
+        };
+        clauses.push(CaseClause { patterns, body: newlines_to_semicolons(body.trim()), terminator });
+        clauses_text = next;
+    }
+
+    trace!("case: {:?} {:?}", word, clauses);
+    Ok(Some((CaseCommand { text: source.to_string(), word, clauses }, rest)))
+}
+
+/// select 命令：`select NAME [in WORD ...]; do LIST; done`
+#[derive(Debug, Clone)]
+pub struct SelectCommand {
+    pub text: String,               // 从 select 到 done 的原文，用于回显命令
+    pub name: String,               // 保存所选单词的变量名
+    pub words: Option<Vec<String>>, // 菜单中的单词（tokenize 之后，仍带转义），执行时展开；省略 `in` 时为 None，使用位置参数
+    pub body: String,               // 每次选择之后执行的命令列表，原样保留到执行时再解析
+}
+
+/// 如果命令段以保留字 `select` 开头，解析到配对的 `done` 为止，返回 select 命令和 done 之后剩下的部分。
+/// 单词列表和 `do` 之间用 `;` 或换行符分隔，循环体中引号之外的换行符换成 `;`。
+pub fn parse_select(command_segment: &str) -> Result<Option<(SelectCommand, &str)>, String> {
+    let text = command_segment.trim_start();
+    if !keyword_at(text, 0, "select", false) {
+        return Ok(None);
+    }
+    let end = compound_at(text, 0, false).flatten().ok_or("`select' 缺少配对的 `done'")?;
+    let (source, rest) = text.split_at(end);
+    let inner = &source["select".len()..source.len() - "done".len()];
+
+    let do_at = scan_top_level(inner, true, |i| keyword_at(inner, i, "do", true))
+        .ok()
+        .flatten()
+        .ok_or("`select' 缺少 `do'")?;
+    let head = inner[..do_at].trim_end();
+    let mut parts = tokenize(head.strip_suffix(';').unwrap_or(head))?.into_iter();
+    let name = parts.next().filter(|name| is_identifier(name)).ok_or("`select' 之后缺少变量名")?;
+    let words = match parts.next() {
+        Some(word) if word == "in" => Some(parts.collect()),
+        Some(word) => return Err(format!("`{}' 附近有语法错误", remove_quotes(&word))),
+        None => None,
+    };
+    let body = inner[do_at + "do".len()..].trim();
+    if body.is_empty() {
+        return Err("`done' 附近有语法错误".to_string());
+    }
+
+    trace!("select: {} {:?} {:?}", name, words, body);
+    Ok(Some((SelectCommand { text: source.to_string(), name, words, body: newlines_to_semicolons(body) }, rest)))
+}
+
+/// 把命令列表中引号、括号和嵌套的复合命令之外的换行符换成 `;`
+fn newlines_to_semicolons(list: &str) -> String {
+    let bytes = list.as_bytes();
+    let mut newlines = Vec::new();
+    let _ = scan_top_level(list, true, |i| {
+        if bytes[i] == b'\n' {
+            newlines.push(i);
+        }
+        false
+    });
+    let mut result = list.to_string();
+    for i in newlines {
+        result.replace_range(i..i + 1, ";");
+    }
+    result
+}
+
+/// 一行输入没有结束的原因，决定如何接上下一行
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Incomplete {
+    Backslash, // 行尾是没有被转义的反斜杠：去掉反斜杠，直接接上下一行
+    Quote,     // 引号没有闭合：换行符是引号内容的一部分
+    Paren,     // `$(`、`<(`、`>(` 或 `(` 没有闭合：换行符相当于 `;`
+    Compound,  // case 命令还没有 esac，或者 select 命令还没有 done：保留换行符，解析复合命令时当作分隔符
+}
+
+impl Incomplete {
+    /// 把下一行接到 `line` 后面
+    pub fn join(self, line: &mut String, next: &str) {
+        match self {
+            Incomplete::Backslash => {
+                line.pop();
+            },
+            Incomplete::Quote => line.push('\n'),
+            // 刚写完 `(` 或者运算符时换行不表示命令结束
+            Incomplete::Paren if line.trim_end().ends_with(['(', ';', '|', '&']) => line.push(' '),
+            Incomplete::Paren => line.push_str("; "),
+            Incomplete::Compound => {
+                // 换行之后的注释不再是行尾的注释，接上下一行之前先去掉
+                line.truncate(strip_comment(line).len());
+                line.push('\n');
+                line.push_str(strip_comment(next));
+                return;
+            },
+        }
+        line.push_str(next);
+    }
+}
+
+/// 判断一行输入是否需要续行：行尾有未转义的反斜杠，单引号、双引号没有闭合，
+/// 引号之外的 `(`（包括 `$(`）多于 `)`，或者 case、select 命令还没有结束。注释中的引号、反斜杠和括号不算。
+pub fn incomplete_line(line: &str) -> Option<Incomplete> {
+    let line = strip_comment(line);
+    let mut quote: Option<char> = None;
+    let mut ansi_c = false; // $'...' 中反斜杠仍然转义下一个字符
+    let mut depth = 0usize; // 引号之外没有闭合的括号层数
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match (quote, c) {
+            (Some('\''), '\\') if !ansi_c => {},
+            // 反斜杠和它转义的字符一起跳过；后面没有字符时就是行尾的反斜杠
+            (_, '\\') if chars.next().is_none() => return Some(Incomplete::Backslash),
+            (None, '$') if chars.peek() == Some(&'\'') => {
+                chars.next();
+                quote = Some('\'');
+                ansi_c = true;
+            },
+            (None, '\'' | '"') => quote = Some(c),
+            (None, '(') => depth += 1,
+            (None, ')') => depth = depth.saturating_sub(1),
+            (Some(open), _) if open == c => {
+                quote = None;
+                ansi_c = false;
+            },
+            _ => {},
+        }
+    }
+    match quote {
+        Some(_) => Some(Incomplete::Quote),
+        None if depth > 0 => Some(Incomplete::Paren),
+        None if unclosed_compound(line) => Some(Incomplete::Compound),
+        None => None,
+    }
+}
+
+/// 从 `first` 开始依次接上续行，得到一条完整的命令行。
+/// 输入在命令完整之前就用完时，第二个返回值是仍然缺少的续行类型。
+pub fn join_continuation_lines<'a>(first: &str, lines: &mut impl Iterator<Item = &'a str>) -> (String, Option<Incomplete>) {
+    let mut command_line = first.to_string();
+    while let Some(incomplete) = incomplete_line(&command_line) {
+        match lines.next() {
+            Some(next) => incomplete.join(&mut command_line, next),
+            None => return (command_line, Some(incomplete)),
+        }
+    }
+    (command_line, None)
+}
+
+/// 判断行编辑器中（可能有多行的）输入是否已经可以执行：
+/// 每条命令都不再需要续行，命令中的每个 here-document 都已经有了结束标记。
+/// 最后的空行也算一行，所以行尾的反斜杠后面再按一次回车就会提交。
+pub fn input_complete(input: &str) -> bool {
+    let mut lines = input.split('\n');
+    while let Some(first) = lines.next() {
+        let (command_line, incomplete) = join_continuation_lines(first, &mut lines);
+        if incomplete.is_some() {
+            return false;
+        }
+        for heredoc in pending_heredocs(&command_line) {
+            if !lines.any(|line| heredoc.is_terminator(line)) {
+                return false;
+            }
+        }
+    }
+    true
+}
+
+/// 去掉命令行末尾的注释：从单词开头（行首、空白或 `;`、`|`、`&` 之后）没有被引用的 `#` 到行尾。
+/// 引号、反斜杠转义和命令替换中的 `#` 不是注释。
+/// 注释要在分割命令列表和展开别名之前去掉，否则注释中的 `;`、`|` 和引号会影响解析。
+pub fn strip_comment(command_line: &str) -> &str {
+    let bytes = command_line.as_bytes();
+    let mut quote: Option<u8> = None;
+    let mut depth = 0; // $(、<( 和 >( 的嵌套层数
+    let mut i = 0;
+    while i < bytes.len() {
+        let c = bytes[i];
+        match quote {
+            Some(b'\'') if c == b'\'' => quote = None,
+            Some(b'\'') => {},
+            Some(_) if c == b'\\' => i += 1,
+            Some(open) if c == open => quote = None,
+            Some(_) => {},
+            None => match c {
+                b'\\' => i += 1,
+                b'\'' | b'"' | b'`' => quote = Some(c),
+                b'$' | b'<' | b'>' if bytes.get(i + 1) == Some(&b'(') => {
+                    depth += 1;
+                    i += 1;
+                },
+                b')' if depth > 0 => depth -= 1,
+                b'#' if depth == 0 && (i == 0 || matches!(bytes[i - 1], b' ' | b'\t' | b';' | b'|' | b'&')) => {
+                    return &command_line[..i];
+                },
+                _ => {},
+            },
+        }
+        i += 1;
+    }
+    command_line
+}
+
+/// 返回命令行中所有 here-document（按出现顺序，正文为空）。
+/// 主循环用它判断输入完这一行之后还需要继续读取多少行正文。
+pub fn pending_heredocs(command_line: &str) -> Vec<HereDoc> {
+    let command_line = strip_comment(command_line);
+    if !command_line.contains("<<") {
+        return Vec::new();
+    }
+    match parse_command_list(command_line) {
+        Ok(items) => items
+            .into_iter()
+            .flat_map(|item| item.commands)
+            .flat_map(|cmd| cmd.heredocs)
+            .collect(),
+        Err(_) => Vec::new(),
+    }
+}
+
+/// 按 here-document 在整行中出现的顺序（跨越管道中的各个命令），从命令行之后的各行中依次读取正文。
+/// 读到与结束标记完全相同的行为止；如果输入提前结束，则像 bash 一样给出警告并使用已读到的内容。
+/// `<<-` 的正文各行去掉开头的制表符。
+pub fn collect_heredoc_bodies<'a>(commands: &mut [ParsedCommand], lines: &mut impl Iterator<Item = &'a str>) {
+    for command in commands.iter_mut() {
+        for heredoc in command.heredocs.iter_mut() {
+            let mut terminated = false;
+            for line in lines.by_ref() {
+                if heredoc.is_terminator(line) {
+                    terminated = true;
+                    break;
+                }
+                let line = if heredoc.strip_tabs { line.trim_start_matches('\t') } else { line };
+                heredoc.body.push_str(line);
+                heredoc.body.push('\n');
+            }
+            if !terminated {
+                eprintln!("my_shell: 警告: here-document 被文件结束符终止 (需要 `{}')", heredoc.delimiter);
+            }
+        }
+    }
+}
+
+/// 把 here-document 的正文转换成单词的内部形式，供 substitute 展开。
+/// 正文中的引号是普通字符；只有 `$` 引用、命令替换以及 `\$`、`` \` ``、`\\` 这几个转义有特殊含义。
+pub fn heredoc_word(body: &str) -> String {
+    let chars: Vec<char> = body.chars().collect();
+    let mut word = String::new();
+    let mut i = 0;
+    while i < chars.len() {
+        match chars[i] {
+            '\\' if matches!(chars.get(i + 1), Some('$' | '`' | '\\')) => {
+                word.push('\\');
+                word.push(chars[i + 1]);
+                i += 2;
+                continue;
+            },
+            '$' if chars.get(i + 1) == Some(&'(') => {
+                if let Some(close) = find_closing_paren(&chars, i + 2) {
+                    word.extend(&chars[i..=close]);
+                    i = close + 1;
+                    continue;
+                }
+                word.push_str("\\$");
+            },
+            '`' => {
+                // 反引号统一改写成 $(...)
+                if let Some(len) = chars[i + 1..].iter().position(|&c| c == '`') {
+                    word.push_str("$(");
+                    word.extend(&chars[i + 1..i + 1 + len]);
+                    word.push(')');
+                    i += len + 2;
+                    continue;
+                }
+                word.push_str("\\`");
+            },
+            '$' => {
+                // 变量名保持原样，留给 substitute 处理
+                word.push('$');
+                let len = match chars.get(i + 1) {
+                    Some('{') => find_closing_brace(&chars, i + 2).map_or(0, |end| end - i),
+                    Some(&c) if c.is_ascii_digit() || c == '?' || c == '#' => 1,
+                    _ => chars[i + 1..]
+                        .iter()
+                        .enumerate()
+                        .take_while(|&(n, &c)| c == '_' || c.is_ascii_alphabetic() || (n > 0 && c.is_ascii_digit()))
+                        .count(),
+                };
+                word.extend(&chars[i + 1..i + 1 + len]);
+                i += len;
+            },
+            c => {
+                word.push('\\');
+                word.push(c);
+            },
+        }
+        i += 1;
+    }
+    word
+}
+
+/// 把单词开头的 `~` 或 `~/` 换成主目录，`~user` 或 `~user/` 换成该用户的主目录，其他位置的 `~` 保持不变。
+/// 和 bash 一样，用户不存在时保持原样。
+/// 引号中的 `~` 已被 tokenize 转义成 `\~`，不会被展开；用户名中有引号或转义时也不展开。
+/// 主目录的每个字符都加上反斜杠，之后的 expand_variables 会把它们当作普通字符。
+pub fn expand_tilde(word: &str, home: &str) -> String {
+    let Some(rest) = word.strip_prefix('~') else {
+        return word.to_string();
+    };
+    let (user, rest) = rest.split_at(rest.find('/').unwrap_or(rest.len()));
+    let home = if user.is_empty() {
+        home.to_string()
+    } else if user.contains(['\\', '"', '$']) {
+        return word.to_string();
+    } else {
+        match user_home(user) {
+            Some(dir) => dir,
+            None => return word.to_string(),
+        }
+    };
+    let mut result = String::new();
+    for c in home.chars() {
+        result.push('\\');
+        result.push(c);
+    }
+    result.push_str(rest);
+    result
+}
+
+/// 在密码数据库中查找用户的主目录
+#[cfg(unix)]
+fn user_home(name: &str) -> Option<String> {
+    match nix::unistd::User::from_name(name) {
+        Ok(Some(user)) => Some(user.dir.display().to_string()),
+        _ => None,
+    }
+}
+
+#[cfg(not(unix))]
+fn user_home(_name: &str) -> Option<String> {
+    None
+}
+
+/// 花括号展开：`a{b,c}d` 展开成 `abd acd`，`{1..5}`、`{a..e}` 和带步长的 `{0..10..2}` 展开成序列。
+/// 在其他展开之前进行，嵌套的花括号递归展开；被转义（即被引用）的花括号、`${...}` 和命令替换中的花括号不展开。
+/// 和 bash 一样，没有逗号也不是合法序列的花括号（例如 `{abc}`）按字面保留。
+pub fn expand_braces(word: &str) -> Vec<String> {
+    let chars: Vec<char> = word.chars().collect();
+    let mut i = 0;
+    while i < chars.len() {
+        match chars[i] {
+            '\\' => i += 1,
+            '$' if chars.get(i + 1) == Some(&'(') => i = find_closing_paren(&chars, i + 2).unwrap_or(chars.len()),
+            '<' | '>' if i == 0 && chars.get(1) == Some(&'(') => i = find_closing_paren(&chars, 2).unwrap_or(chars.len()),
+            '$' if chars.get(i + 1) == Some(&'{') => i = find_closing_brace(&chars, i + 2).unwrap_or(chars.len()),
+            '{' => {
+                if let Some(close) = find_closing_brace(&chars, i + 1)
+                    && let Some(items) = brace_items(&chars[i + 1..close])
+                {
+                    let prefix: String = chars[..i].iter().collect();
+                    let suffix: String = chars[close + 1..].iter().collect();
+                    return items
+                        .iter()
+                        .flat_map(|item| expand_braces(&format!("{}{}{}", prefix, item, suffix)))
+                        .collect();
+                }
+            },
+            _ => {},
+        }
+        i += 1;
+    }
+    vec![word.to_string()]
+}
+
+/// 找到与 `{` 配对的 `}` 的位置，`start` 是 `{` 之后第一个字符的下标
+fn find_closing_brace(chars: &[char], start: usize) -> Option<usize> {
+    let mut depth = 0;
+    let mut i = start;
+    while i < chars.len() {
+        match chars[i] {
+            '\\' => i += 1,
+            '$' if chars.get(i + 1) == Some(&'(') => i = find_closing_paren(chars, i + 2)?,
+            '{' => depth += 1,
+            '}' if depth == 0 => return Some(i),
+            '}' => depth -= 1,
+            _ => {},
+        }
+        i += 1;
+    }
+    None
+}
+
+/// 花括号中的内容展开成的各项：顶层逗号分隔的列表或者序列；都不是时返回 None
+fn brace_items(body: &[char]) -> Option<Vec<String>> {
+    let mut items = Vec::new();
+    let mut current = String::new();
+    let mut depth = 0;
+    let mut i = 0;
+    while i < body.len() {
+        match body[i] {
+            '\\' if i + 1 < body.len() => {
+                current.push('\\');
+                i += 1;
+            },
+            '$' if body.get(i + 1) == Some(&'(') => {
+                let close = find_closing_paren(body, i + 2).unwrap_or(body.len() - 1);
+                current.extend(&body[i..close]);
+                i = close;
+            },
+            '{' => depth += 1,
+            '}' => depth -= 1,
+            ',' if depth == 0 => {
+                items.push(std::mem::take(&mut current));
+                i += 1;
+                continue;
+            },
+            _ => {},
+        }
+        current.push(body[i]);
+        i += 1;
+    }
+    if items.is_empty() {
+        return brace_sequence(&current);
+    }
+    items.push(current);
+    Some(items)
+}
+
+/// 展开 `x..y` 或 `x..y..step` 形式的序列，`x` 和 `y` 同为整数或同为单个字母。
+/// 整数序列中任一端带前导 0 时，所有数字补 0 到相同的宽度（`{01..10}`）。
+fn brace_sequence(body: &str) -> Option<Vec<String>> {
+    let parts: Vec<&str> = body.split("..").collect();
+    let (start, end, step) = match parts[..] {
+        [start, end] => (start, end, 1),
+        [start, end, step] => (start, end, step.parse::<i64>().ok()?.unsigned_abs().max(1)),
+        _ => return None,
+    };
+    let step = step as usize;
+
+    if let (Ok(first), Ok(last)) = (start.parse::<i64>(), end.parse::<i64>()) {
+        let padded = |s: &str| s.trim_start_matches('-').len() > 1 && s.trim_start_matches('-').starts_with('0');
+        let width = if padded(start) || padded(end) { start.len().max(end.len()) } else { 0 };
+        let values: Vec<i64> = if first <= last {
+            (first..=last).step_by(step).collect()
+        } else {
+            (last..=first).rev().step_by(step).collect()
+        };
+        return Some(values.iter().map(|value| format!("{:0width$}", value, width = width)).collect());
+    }
+
+    let letter = |s: &str| -> Option<char> {
+        let mut chars = s.chars();
+        chars.next().filter(|c| c.is_ascii_alphabetic() && chars.next().is_none())
+    };
+    let (first, last) = (letter(start)? as u8, letter(end)? as u8);
+    let letters: Vec<u8> = if first <= last {
+        (first..=last).step_by(step).collect()
+    } else {
+        (last..=first).rev().step_by(step).collect()
+    };
+    // 和 bash 一样，`{Z..a}` 这样跨过大小写的序列中的 `\`、`` ` `` 等字符需要转义
+    Some(letters.iter().map(|&c| escape_if_special(c as char)).collect())
+}
+
+fn escape_if_special(c: char) -> String {
+    if c.is_ascii_alphanumeric() { c.to_string() } else { format!("\\{}", c) }
+}
+
+/// 展开单词中的 `$NAME`、`${NAME}`、`$?` 和 `$0` 这样的位置参数引用，并去掉引号。
+/// 变量的值由 `lookup` 提供；`lookup` 返回 None 的引用原样保留。
+/// 反斜杠后面的字符按字面处理，反斜杠本身被去掉（引号内容由 tokenize 转成这种形式）。
+pub fn expand_variables(word: &str, lookup: &mut dyn FnMut(&str) -> Option<String>) -> String {
+    remove_quotes(&substitute_variables(word, lookup))
+}
+
+/// 展开单词时需要的外部信息：变量的值、命令替换的输出和进程替换的路径。
+/// 返回 None 的变量引用、命令替换或进程替换原样保留。
+pub trait Expander {
+    fn variable(&mut self, name: &str) -> Option<String>;
+
+    fn command_output(&mut self, _command: &str) -> Option<String> {
+        None
+    }
+
+    /// 进程替换：启动 `command` 并返回外层命令读写的文件路径。
+    /// `read` 为 true 时是 `<(...)`，外层命令读取它的输出；为 false 时是 `>(...)`，外层命令写入它的输入
+    fn process_substitution(&mut self, _command: &str, _read: bool) -> Option<String> {
+        None
+    }
+
+    /// `${NAME:=word}` 给空的或未设置的变量赋默认值
+    fn assign(&mut self, _name: &str, _value: &str) {}
+
+    /// `${!prefix*}` 列出以 `prefix` 开头的变量名，按字典序排列
+    fn variable_names(&mut self, _prefix: &str) -> Vec<String> {
+        Vec::new()
+    }
+
+    /// 展开失败（例如算术展开中除以 0），Shell 应该放弃执行这一行剩下的命令
+    fn expansion_error(&mut self, message: &str) {
+        eprintln!("my_shell: {}", message);
+    }
+}
+
+/// 只提供变量、不执行命令替换的 Expander
+struct VariablesOnly<'a>(&'a mut dyn FnMut(&str) -> Option<String>);
+
+impl Expander for VariablesOnly<'_> {
+    fn variable(&mut self, name: &str) -> Option<String> {
+        (self.0)(name)
+    }
+}
+
+/// 只做变量替换（不执行命令替换），保留单词中的转义
+pub fn substitute_variables(word: &str, lookup: &mut dyn FnMut(&str) -> Option<String>) -> String {
+    substitute(word, &mut VariablesOnly(lookup))
+}
+
+/// 进行变量替换和命令替换，保留单词中的转义，替换进来的值也逐字符转义。
+/// 结果仍可交给 expand_glob 判断哪些通配符没有被引用，最后由 remove_quotes 去掉转义。
+/// 不在双引号中的变量展开和命令替换，结果中的空白不加转义，留给 split_fields 分割成多个参数。
+pub fn substitute(word: &str, expander: &mut dyn Expander) -> String {
+    let chars: Vec<char> = word.chars().collect();
+    let mut result = String::new();
+    let mut quoted = false; // 是否在双引号包围的命令替换中（引号由 tokenize 加上）
+    let mut i = 0;
+
+    while i < chars.len() {
+        if chars[i] == '\\' && i + 1 < chars.len() {
+            result.push(chars[i]);
+            result.push(chars[i + 1]);
+            i += 2;
+            continue;
+        }
+        if chars[i] == '"' {
+            quoted = !quoted;
+            i += 1;
+            continue;
+        }
+        // $((...)) 是算术展开：内层括号正好在外层的右括号之前闭合
+        if chars[i] == '$' && chars.get(i + 1) == Some(&'(') && chars.get(i + 2) == Some(&'(')
+            && let Some(close) = find_closing_paren(&chars, i + 2)
+            && find_closing_paren(&chars, i + 3) == Some(close - 1)
+        {
+            let expression: String = chars[i + 3..close - 1].iter().collect();
+            let expression = remove_quotes(&substitute(&expression, expander));
+            let value = evaluate_arithmetic(&expression, &mut |name| expander.variable(name));
+            match value {
+                Ok(value) => result.push_str(&value.to_string()),
+                Err(e) => expander.expansion_error(&format!("{}: {}", expression.trim(), e)),
+            }
+            i = close + 1;
+            continue;
+        }
+        if chars[i] == '$' && chars.get(i + 1) == Some(&'(')
+            && let Some(close) = find_closing_paren(&chars, i + 2)
+        {
+            let command: String = chars[i + 2..close].iter().collect();
+            match expander.command_output(&command) {
+                // 和 bash 一样去掉输出末尾的换行
+                Some(output) => push_expansion(&mut result, output.trim_end_matches('\n'), quoted),
+                None => {
+                    for c in &chars[i..=close] {
+                        result.push('\\');
+                        result.push(*c);
+                    }
+                },
+            }
+            i = close + 1;
+            continue;
+        }
+        // 和 tokenize 一致，只有单词开头的 <(...) 和 >(...) 是进程替换，替换成一个不做字段分割的路径
+        if i == 0 && matches!(chars[0], '<' | '>') && chars.get(1) == Some(&'(')
+            && let Some(close) = find_closing_paren(&chars, 2)
+        {
+            let command: String = chars[2..close].iter().collect();
+            let path = expander.process_substitution(&command, chars[0] == '<');
+            let path: Vec<char> = path.map_or_else(|| chars[..=close].to_vec(), |path| path.chars().collect());
+            for c in path {
+                result.push('\\');
+                result.push(c);
+            }
+            i = close + 1;
+            continue;
+        }
+        if chars[i] != '$' {
+            result.push(chars[i]);
+            i += 1;
+            continue;
+        }
+        if chars.get(i + 1) == Some(&'{') && chars.get(i + 2) == Some(&'!')
+            && let Some(close) = find_closing_brace(&chars, i + 2)
+        {
+            result.push_str(&expand_indirect(&chars[i + 3..close], quoted, expander));
+            i = close + 1;
+            continue;
+        }
+        if chars.get(i + 1) == Some(&'{')
+            && let Some(close) = find_closing_brace(&chars, i + 2)
+            && let Some((name, operator, word)) = split_modifier(&chars[i + 2..close])
+        {
+            result.push_str(&expand_modifier(&name, operator, &word, quoted, expander));
+            i = close + 1;
+            continue;
+        }
+
+        // 找出变量名的范围，以及整个引用（含 $ 和花括号）结束的位置
+        let (name_start, name_end, end) = if chars.get(i + 1) == Some(&'{') {
+            match chars[i + 2..].iter().position(|&c| c == '}') {
+                Some(len) => (i + 2, i + 2 + len, i + 3 + len),
+                None => (i + 2, i + 2, i + 1), // 没有闭合的花括号，按普通字符处理
+            }
+        } else if chars.get(i + 1).is_some_and(|&c| c.is_ascii_digit() || c == '?' || c == '#') {
+            (i + 1, i + 2, i + 2) // $0 到 $9 只取一位数字，$? 是上一条命令的退出状态，$# 是参数个数
+        } else {
+            let len = chars[i + 1..]
+                .iter()
+                .enumerate()
+                .take_while(|&(n, &c)| c == '_' || c.is_ascii_alphabetic() || (n > 0 && c.is_ascii_digit()))
+                .count();
+            (i + 1, i + 1 + len, i + 1 + len)
+        };
+
+        let name: String = chars[name_start..name_end].iter().collect();
+        let value = if name.is_empty() { None } else { expander.variable(&name) };
+        match value {
+            Some(value) => {
+                push_expansion(&mut result, &value, quoted);
+                i = end;
+            },
+            _ => {
+                result.push('$');
+                i += 1;
+            }
+        }
+    }
+    result
+}
+
+/// 展开 `${!...}`，`body` 是 `!` 之后、`}` 之前的内容：
+///   - `${!prefix*}` 和 `${!prefix@}`：以 prefix 开头的变量名，用空格隔开
+///   - `${!name}`：间接引用，把 name 的值当作变量名再取一次值，目标未设置时为空
+fn expand_indirect(body: &[char], quoted: bool, expander: &mut dyn Expander) -> String {
+    let body: String = body.iter().collect();
+    let mut result = String::new();
+    if let Some(prefix) = body.strip_suffix(['*', '@'])
+        && is_identifier(prefix)
+    {
+        // 和命令替换一样，不在双引号中时名字之间的空格不加转义，留给 split_fields 分割
+        for (n, name) in expander.variable_names(prefix).iter().enumerate() {
+            if n > 0 {
+                result.push_str(if quoted { "\\ " } else { " " });
+            }
+            for c in name.chars() {
+                result.push('\\');
+                result.push(c);
+            }
+        }
+        return result;
+    }
+    let target = expander.variable(&body).unwrap_or_default();
+    if target.is_empty() {
+        return result;
+    }
+    let special = target.len() == 1 && matches!(target.as_bytes()[0], b'0'..=b'9' | b'?' | b'#');
+    if !special && !is_identifier(&target) {
+        expander.expansion_error(&format!("{}: 无效的间接引用", target));
+        return result;
+    }
+    push_expansion(&mut result, &expander.variable(&target).unwrap_or_default(), quoted);
+    result
+}
+
+/// 把展开得到的值逐字符加上转义；不在双引号中时空白不加转义，留给 split_fields 做字段分割
+fn push_expansion(result: &mut String, value: &str, quoted: bool) {
+    for c in value.chars() {
+        if quoted || !c.is_whitespace() {
+            result.push('\\');
+        }
+        result.push(c);
+    }
+}
+
+/// 把 `${...}` 的内容拆成变量名、修饰符（`-`、`=`、`?` 或 `+`）和其后的单词。
+/// 只识别带冒号的形式；没有修饰符时返回 None，整个内容按变量名处理
+fn split_modifier(body: &[char]) -> Option<(String, char, String)> {
+    let len = match body.first() {
+        Some(&c) if c.is_ascii_digit() || c == '?' || c == '#' => 1,
+        _ => body
+            .iter()
+            .enumerate()
+            .take_while(|&(n, &c)| c == '_' || c.is_ascii_alphabetic() || (n > 0 && c.is_ascii_digit()))
+            .count(),
+    };
+    match body.get(len..len + 2) {
+        Some(&[':', operator]) if len > 0 && matches!(operator, '-' | '=' | '?' | '+') => {
+            Some((body[..len].iter().collect(), operator, body[len + 2..].iter().collect()))
+        },
+        _ => None,
+    }
+}
+
+/// 展开带修饰符的变量引用，变量为空和未设置的处理相同：
+///   - `${NAME:-word}`：变量为空时使用 word
+///   - `${NAME:=word}`：变量为空时使用 word，同时把它赋给变量
+///   - `${NAME:?word}`：变量为空时报告错误，word 是错误信息
+///   - `${NAME:+word}`：变量不为空时使用 word，否则为空
+///
+/// word 中的变量、命令替换和算术展开只在用到时才展开；在双引号中时结果整体加上转义，不做字段分割
+fn expand_modifier(name: &str, operator: char, word: &str, quoted: bool, expander: &mut dyn Expander) -> String {
+    let value = expander.variable(name).unwrap_or_default();
+    let expand_word = |expander: &mut dyn Expander| {
+        let expanded = substitute(word, expander);
+        if quoted { escape_unquoted(&expanded) } else { expanded }
+    };
+    match (operator, value.is_empty()) {
+        ('-', true) | ('+', false) => expand_word(expander),
+        ('+', true) => String::new(),
+        ('=', true) => {
+            let expanded = expand_word(expander);
+            if is_identifier(name) {
+                expander.assign(name, &remove_quotes(&expanded));
+            } else {
+                expander.expansion_error(&format!("${}: 不能这样赋值", name));
+            }
+            expanded
+        },
+        ('?', true) => {
+            let message = remove_quotes(&substitute(word, expander));
+            let message = if message.is_empty() { "参数为空或未设置" } else { &message };
+            expander.expansion_error(&format!("{}: {}", name, message));
+            String::new()
+        },
+        _ => {
+            let mut result = String::new();
+            push_expansion(&mut result, &value, quoted);
+            result
+        },
+    }
+}
+
+/// 给还没有转义的字符加上反斜杠，去掉不带转义的双引号
+fn escape_unquoted(word: &str) -> String {
+    let mut result = String::new();
+    let mut chars = word.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => {
+                result.push(c);
+                result.extend(chars.next());
+            },
+            '"' => {},
+            c => {
+                result.push('\\');
+                result.push(c);
+            },
+        }
+    }
+    result
+}
+
+/// 算术表达式的语法树
+enum Arithmetic {
+    Number(i64),
+    Variable(String),
+    Unary(&'static str, Box<Arithmetic>),
+    Binary(&'static str, Box<Arithmetic>, Box<Arithmetic>),
+    Conditional(Box<Arithmetic>, Box<Arithmetic>, Box<Arithmetic>), // a ? b : c
+}
+
+/// 算术表达式中的运算符，较长的排在前面以便优先匹配
+const ARITHMETIC_OPERATORS: &[&str] = &[
+    "**", "<<", ">>", "<=", ">=", "==", "!=", "&&", "||", "+", "-", "*", "/", "%", "<", ">", "&", "^", "|", "!", "~", "?",
+    ":", "(", ")",
+];
+
+/// 二元运算符的优先级，数字越大结合得越紧；`?:` 的优先级最低
+fn binary_precedence(op: &str) -> Option<u8> {
+    Some(match op {
+        "?" => 1,
+        "||" => 2,
+        "&&" => 3,
+        "|" => 4,
+        "^" => 5,
+        "&" => 6,
+        "==" | "!=" => 7,
+        "<" | "<=" | ">" | ">=" => 8,
+        "<<" | ">>" => 9,
+        "+" | "-" => 10,
+        "*" | "/" | "%" => 11,
+        "**" => 12,
+        _ => return None,
+    })
+}
+
+/// 算术表达式的词法单元
+#[derive(Debug, Clone, PartialEq)]
+enum ArithmeticToken {
+    Number(i64),
+    Name(String),
+    Operator(&'static str),
+}
+
+fn tokenize_arithmetic(expression: &str) -> Result<Vec<ArithmeticToken>, String> {
+    let mut tokens = Vec::new();
+    let mut rest = expression.trim_start();
+    while !rest.is_empty() {
+        let c = rest.chars().next().unwrap_or(' ');
+        let len = if c.is_ascii_alphanumeric() || c == '_' {
+            let len = rest.find(|c: char| !(c.is_ascii_alphanumeric() || c == '_')).unwrap_or(rest.len());
+            let word = &rest[..len];
+            tokens.push(if c.is_ascii_digit() {
+                ArithmeticToken::Number(parse_arithmetic_number(word)?)
+            } else {
+                ArithmeticToken::Name(word.to_string())
+            });
+            len
+        } else {
+            let op = ARITHMETIC_OPERATORS
+                .iter()
+                .find(|op| rest.starts_with(*op))
+                .ok_or_else(|| format!("语法错误：无效的算术运算符 (错误符号是 \"{}\")", rest))?;
+            tokens.push(ArithmeticToken::Operator(op));
+            op.len()
+        };
+        rest = rest[len..].trim_start();
+    }
+    Ok(tokens)
+}
+
+/// 整数常量：十进制，`0x` 开头的十六进制，`0` 开头的八进制
+fn parse_arithmetic_number(word: &str) -> Result<i64, String> {
+    let parsed = if let Some(hex) = word.strip_prefix("0x").or_else(|| word.strip_prefix("0X")) {
+        i64::from_str_radix(hex, 16)
+    } else if word.len() > 1 && word.starts_with('0') {
+        i64::from_str_radix(&word[1..], 8)
+    } else {
+        word.parse::<i64>()
+    };
+    parsed.map_err(|_| format!("数值太大或者不是有效的整数 (错误符号是 \"{}\")", word))
+}
+
+/// 递归下降（按优先级爬升）的算术表达式解析器
+struct ArithmeticParser {
+    tokens: Vec<ArithmeticToken>,
+    pos: usize,
+}
+
+impl ArithmeticParser {
+    fn peek_operator(&self) -> Option<&'static str> {
+        match self.tokens.get(self.pos) {
+            Some(ArithmeticToken::Operator(op)) => Some(op),
+            _ => None,
+        }
+    }
+
+    fn expect(&mut self, expected: &str) -> Result<(), String> {
+        if self.peek_operator() == Some(expected) {
+            self.pos += 1;
+            Ok(())
+        } else {
+            Err(format!("语法错误：缺少 `{}'", expected))
+        }
+    }
+
+    fn expression(&mut self, min_precedence: u8) -> Result<Arithmetic, String> {
+        let mut left = self.unary()?;
+        while let Some(op) = self.peek_operator() {
+            let Some(precedence) = binary_precedence(op).filter(|&p| p >= min_precedence) else {
+                break;
+            };
+            self.pos += 1;
+            left = if op == "?" {
+                let then = self.expression(1)?;
+                self.expect(":")?;
+                let otherwise = self.expression(1)?;
+                Arithmetic::Conditional(Box::new(left), Box::new(then), Box::new(otherwise))
+            } else {
+                // ** 是右结合的，其他二元运算符都是左结合的
+                let next = if op == "**" { precedence } else { precedence + 1 };
+                Arithmetic::Binary(op, Box::new(left), Box::new(self.expression(next)?))
+            };
+        }
+        Ok(left)
+    }
+
+    fn unary(&mut self) -> Result<Arithmetic, String> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        match token {
+            Some(ArithmeticToken::Number(value)) => Ok(Arithmetic::Number(value)),
+            Some(ArithmeticToken::Name(name)) => Ok(Arithmetic::Variable(name)),
+            Some(ArithmeticToken::Operator(op @ ("+" | "-" | "!" | "~"))) => Ok(Arithmetic::Unary(op, Box::new(self.unary()?))),
+            Some(ArithmeticToken::Operator("(")) => {
+                let inner = self.expression(1)?;
+                self.expect(")")?;
+                Ok(inner)
+            },
+            Some(ArithmeticToken::Operator(op)) => Err(format!("语法错误：需要操作数 (错误符号是 \"{}\")", op)),
+            None => Err("语法错误：需要操作数".to_string()),
+        }
+    }
+}
+
+/// 计算算术表达式，语义和 bash 一致：64 位有符号整数，溢出时回绕，比较和逻辑运算的结果为 1 或 0。
+/// 变量的值由 `lookup` 提供，未设置或为空的变量按 0 计算，值本身也可以是表达式。
+/// `&&`、`||` 和 `?:` 只计算需要的一侧，所以 `0 && 1/0` 不会报错。
+pub fn evaluate_arithmetic(expression: &str, lookup: &mut dyn FnMut(&str) -> Option<String>) -> Result<i64, String> {
+    evaluate_arithmetic_at_depth(expression, lookup, 0)
+}
+
+fn evaluate_arithmetic_at_depth(
+    expression: &str,
+    lookup: &mut dyn FnMut(&str) -> Option<String>,
+    depth: usize,
+) -> Result<i64, String> {
+    if depth > 32 {
+        return Err("表达式递归层数过多".to_string());
+    }
+    let tokens = tokenize_arithmetic(expression)?;
+    if tokens.is_empty() {
+        return Ok(0); // 和 bash 一样，$(( )) 的值为 0
+    }
+    let mut parser = ArithmeticParser { tokens, pos: 0 };
+    let tree = parser.expression(1)?;
+    if let Some(token) = parser.tokens.get(parser.pos) {
+        return Err(format!("语法错误：多余的符号 {:?}", token));
+    }
+    eval_arithmetic(&tree, lookup, depth)
+}
+
+fn eval_arithmetic(tree: &Arithmetic, lookup: &mut dyn FnMut(&str) -> Option<String>, depth: usize) -> Result<i64, String> {
+    let eval = |tree: &Arithmetic, lookup: &mut dyn FnMut(&str) -> Option<String>| eval_arithmetic(tree, lookup, depth);
+    Ok(match tree {
+        Arithmetic::Number(value) => *value,
+        Arithmetic::Variable(name) => match lookup(name) {
+            Some(value) if !value.trim().is_empty() => evaluate_arithmetic_at_depth(&value, lookup, depth + 1)?,
+            _ => 0,
+        },
+        Arithmetic::Unary(op, operand) => {
+            let value = eval(operand, lookup)?;
+            match *op {
+                "-" => value.wrapping_neg(),
+                "!" => (value == 0) as i64,
+                "~" => !value,
+                _ => value,
+            }
+        },
+        Arithmetic::Conditional(condition, then, otherwise) => {
+            if eval(condition, lookup)? != 0 { eval(then, lookup)? } else { eval(otherwise, lookup)? }
+        },
+        Arithmetic::Binary("&&", left, right) => (eval(left, lookup)? != 0 && eval(right, lookup)? != 0) as i64,
+        Arithmetic::Binary("||", left, right) => (eval(left, lookup)? != 0 || eval(right, lookup)? != 0) as i64,
+        Arithmetic::Binary(op, left, right) => {
+            let (left, right) = (eval(left, lookup)?, eval(right, lookup)?);
+            match *op {
+                "+" => left.wrapping_add(right),
+                "-" => left.wrapping_sub(right),
+                "*" => left.wrapping_mul(right),
+                "/" | "%" if right == 0 => return Err("除数为 0".to_string()),
+                "/" => left.wrapping_div(right),
+                "%" => left.wrapping_rem(right),
+                "**" if right < 0 => return Err("指数小于 0".to_string()),
+                "**" => left.wrapping_pow(right.min(u32::MAX as i64) as u32),
+                "<<" => left.wrapping_shl(right as u32),
+                ">>" => left.wrapping_shr(right as u32),
+                "<" => (left < right) as i64,
+                "<=" => (left <= right) as i64,
+                ">" => (left > right) as i64,
+                ">=" => (left >= right) as i64,
+                "==" => (left == right) as i64,
+                "!=" => (left != right) as i64,
+                "&" => left & right,
+                "^" => left ^ right,
+                _ => left | right,
+            }
+        },
+    })
+}
+
+/// 是否是合法的变量名：由字母、数字和下划线组成，且不以数字开头
+pub fn is_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars.next().is_some_and(|c| c == '_' || c.is_ascii_alphabetic())
+        && chars.all(|c| c == '_' || c.is_ascii_alphanumeric())
+}
+
+/// 找到与 `$(` 配对的 `)` 的位置，`start` 是 `$(` 之后第一个字符的下标
+fn find_closing_paren(chars: &[char], start: usize) -> Option<usize> {
+    let mut depth = 0;
+    let mut quote: Option<char> = None;
+    let mut i = start;
+    while i < chars.len() {
+        match chars[i] {
+            '\\' if quote != Some('\'') => i += 1,
+            c @ ('\'' | '"') if quote.is_none() => quote = Some(c),
+            c if quote == Some(c) => quote = None,
+            '(' if quote.is_none() => depth += 1,
+            ')' if quote.is_none() => {
+                if depth == 0 {
+                    return Some(i);
+                }
+                depth -= 1;
+            },
+            _ => {},
+        }
+        i += 1;
+    }
+    None
+}
+
+/// 字段分割：按替换结果中没有转义的空白把单词分成多个参数。
+/// 和 bash 一样，没有引号的单词展开后为空时（例如 `$(true)`）不产生参数。
+pub fn split_fields(original: &str, word: &str) -> Vec<String> {
+    let mut fields = Vec::new();
+    let mut current = String::new();
+    let mut chars = word.chars();
+    while let Some(c) = chars.next() {
+        if c == '\\' {
+            current.push(c);
+            current.extend(chars.next());
+        } else if c.is_whitespace() {
+            if !current.is_empty() {
+                fields.push(std::mem::take(&mut current));
+            }
+        } else {
+            current.push(c);
+        }
+    }
+    if !current.is_empty() {
+        fields.push(current);
+    }
+
+    let unquoted = !original.is_empty() && !original.contains(['\\', '"']);
+    if fields.is_empty() && !unquoted {
+        fields.push(String::new());
+    }
+    fields
+}
+
+/// 去掉转义：`\x` 变成字面的 `x`，tokenize 加上的不带转义的双引号直接删除
+pub fn remove_quotes(word: &str) -> String {
+    let mut result = String::new();
+    let mut chars = word.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => result.extend(chars.next().or(Some('\\'))),
+            '"' => {},
+            _ => result.push(c),
+        }
+    }
+    result
+}
+
+/// 通配符模式中的一个元素
+#[derive(Debug, Clone, PartialEq)]
+enum GlobToken {
+    Literal(char),
+    AnyString, // *
+    AnyChar,   // ?
+    Class { negated: bool, ranges: Vec<(char, char)> }, // [abc]、[a-z]、[!0-9]，单个字符记为首尾相同的范围
+}
+
+/// 把（仍带转义的）单词编译成按 `/` 分段的模式
+fn compile_glob(word: &str) -> Vec<Vec<GlobToken>> {
+    let mut segments = vec![Vec::new()];
+    for token in compile_pattern(word) {
+        if token == GlobToken::Literal('/') {
+            segments.push(Vec::new());
+        } else if let Some(segment) = segments.last_mut() {
+            segment.push(token);
+        }
+    }
+    segments
+}
+
+/// 把（仍带转义的）单词编译成模式，被转义的 `*`、`?` 和 `[` 是普通字符
+fn compile_pattern(word: &str) -> Vec<GlobToken> {
+    let mut tokens = Vec::new();
+    let mut chars = word.chars();
+    while let Some(c) = chars.next() {
+        let token = match c {
+            '\\' => GlobToken::Literal(chars.next().unwrap_or('\\')),
+            '*' => GlobToken::AnyString,
+            '?' => GlobToken::AnyChar,
+            '[' => match compile_bracket(chars.clone()) {
+                Some((token, rest)) => {
+                    chars = rest;
+                    token
+                },
+                None => GlobToken::Literal('['),
+            },
+            _ => GlobToken::Literal(c),
+        };
+        tokens.push(token);
+    }
+    tokens
+}
+
+/// case 命令的模式匹配：和路径名展开使用同样的通配符，但 `/` 和开头的 `.` 没有特殊含义
+pub fn pattern_matches(pattern: &str, text: &str) -> bool {
+    glob_match(&compile_pattern(pattern), &text.chars().collect::<Vec<char>>())
+}
+
+/// 解析 `[` 之后的方括号表达式，返回字符类和 `]` 之后剩下的字符。
+/// `!` 或 `^` 开头表示取反，紧跟在开头的 `]` 是普通字符，`a-z` 表示范围，结尾的 `-` 是普通字符。
+/// 没有闭合的 `]`、或者其中包含 `/` 时不是字符类，`[` 按字面处理（所以 test 的 `[` 不受影响）。
+fn compile_bracket(mut chars: Chars<'_>) -> Option<(GlobToken, Chars<'_>)> {
+    let mut items = Vec::new(); // 类中的字符，被转义的字符标记为 true，不参与构成范围
+    let mut negated = false;
+    while let Some(c) = chars.next() {
+        match c {
+            '!' | '^' if items.is_empty() && !negated => negated = true,
+            ']' if !items.is_empty() => break,
+            '/' => return None,
+            '\\' => items.push((chars.next()?, true)),
+            _ => items.push((c, false)),
+        }
+        if chars.as_str().is_empty() {
+            return None;
+        }
+    }
+    let mut ranges = Vec::new();
+    let mut i = 0;
+    while i < items.len() {
+        let (start, _) = items[i];
+        if items.get(i + 1) == Some(&('-', false)) && i + 2 < items.len() {
+            ranges.push((start, items[i + 2].0));
+            i += 3;
+        } else {
+            ranges.push((start, start));
+            i += 1;
+        }
+    }
+    Some((GlobToken::Class { negated, ranges }, chars))
+}
+
+fn is_wildcard(segment: &[GlobToken]) -> bool {
+    segment.iter().any(|token| !matches!(token, GlobToken::Literal(_)))
+}
+
+/// 对单词做路径名展开，返回排序后的匹配路径；没有匹配时返回空 Vec，由调用者保留原来的单词。
+/// 和 bash 一样，以 `.` 开头的文件只有在模式也以 `.` 开头时才会被匹配。
+pub fn expand_glob(word: &str) -> Vec<String> {
+    let segments = compile_glob(word);
+    if !segments.iter().any(|segment| is_wildcard(segment)) {
+        return Vec::new();
+    }
+
+    let mut matches = Vec::new();
+    // 以 `/` 开头的模式：第一段为空，从根目录开始
+    match segments.split_first() {
+        Some((first, rest)) if first.is_empty() => glob_walk("/".to_string(), rest, &mut matches),
+        _ => glob_walk(String::new(), &segments, &mut matches),
+    }
+    matches.sort();
+    matches
+}
+
+/// 逐段匹配：`prefix` 是已经匹配好的路径前缀（为空或以 `/` 结尾）
+fn glob_walk(prefix: String, segments: &[Vec<GlobToken>], matches: &mut Vec<String>) {
+    let Some((segment, rest)) = segments.split_first() else {
+        return;
+    };
+
+    let candidates: Vec<String> = if is_wildcard(segment) {
+        let dir = if prefix.is_empty() { "." } else { prefix.as_str() };
+        let Ok(entries) = std::fs::read_dir(dir) else {
+            return;
+        };
+        let allow_hidden = segment.first() == Some(&GlobToken::Literal('.'));
+        let mut names: Vec<String> = entries
+            .filter_map(|entry| entry.ok()?.file_name().into_string().ok())
+            .filter(|name| allow_hidden || !name.starts_with('.'))
+            .filter(|name| glob_match(segment, &name.chars().collect::<Vec<char>>()))
+            .collect();
+        names.sort();
+        names
+    } else {
+        let literal = segment.iter().filter_map(|token| match token {
+            GlobToken::Literal(c) => Some(*c),
+            _ => None,
+        });
+        vec![literal.collect()]
+    };
+
+    for name in candidates {
+        let path = format!("{}{}", prefix, name);
+        if rest.is_empty() {
+            if std::fs::symlink_metadata(&path).is_ok() {
+                matches.push(path);
+            }
+        } else if rest.iter().all(|segment| segment.is_empty()) {
+            // 模式以 `/` 结尾，只匹配目录
+            if std::path::Path::new(&path).is_dir() {
+                matches.push(format!("{}/", path));
+            }
+        } else {
+            glob_walk(format!("{}/", path), rest, matches);
+        }
+    }
+}
+
+/// 判断文件名是否匹配一段模式（`*` 匹配任意多个字符，`?` 匹配一个字符，`[...]` 匹配字符类中的一个字符）
+fn glob_match(pattern: &[GlobToken], name: &[char]) -> bool {
+    match pattern.split_first() {
+        None => name.is_empty(),
+        Some((GlobToken::AnyString, rest)) => (0..=name.len()).any(|skip| glob_match(rest, &name[skip..])),
+        Some((GlobToken::AnyChar, rest)) => !name.is_empty() && glob_match(rest, &name[1..]),
+        Some((GlobToken::Literal(c), rest)) => name.first() == Some(c) && glob_match(rest, &name[1..]),
+        Some((GlobToken::Class { negated, ranges }, rest)) => match name.first() {
+            Some(c) => ranges.iter().any(|(start, end)| (start..=end).contains(&c)) != *negated && glob_match(rest, &name[1..]),
+            None => false,
+        },
+    }
+}
@@ -0,0 +1,98 @@
+//! prompt.rs
+
+use std::env;
+
+/// 未设置 PS1 时使用的提示符
+pub const DEFAULT_PROMPT: &str = "my_shell> ";
+
+/// 解释 PS1 中的反斜杠转义序列：
+///   - `\u`：用户名；`\h`：主机名中第一个 `.` 之前的部分，`\H`：完整的主机名
+///   - `\w`：当前目录，主目录开头的部分缩写成 `~`；`\W`：当前目录的最后一段
+///   - `\$`：root 用户显示 `#`，其他用户显示 `$`
+///   - `\n`：换行；`\e`：ESC，用于颜色等控制序列；`\\`：反斜杠
+///   - `\[` 和 `\]`：bash 用来标记不占宽度的控制序列，这里直接去掉
+///
+/// 不认识的转义序列原样保留。
+pub fn render_escapes(ps1: &str, home: &str) -> String {
+    let mut prompt = String::new();
+    let mut chars = ps1.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            prompt.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('u') => prompt.push_str(&username()),
+            Some('h') => prompt.push_str(hostname().split('.').next().unwrap_or("")),
+            Some('H') => prompt.push_str(&hostname()),
+            Some('w') => prompt.push_str(&working_directory(home)),
+            Some('W') => {
+                let dir = working_directory(home);
+                prompt.push_str(if dir == "/" { "/" } else { dir.rsplit('/').next().unwrap_or("") });
+            },
+            Some('$') => prompt.push(if is_root() { '#' } else { '$' }),
+            Some('n') => prompt.push('\n'),
+            Some('e') => prompt.push('\x1b'),
+            Some('\\') => prompt.push('\\'),
+            Some('[' | ']') => {},
+            Some(other) => {
+                prompt.push('\\');
+                prompt.push(other);
+            },
+            None => prompt.push('\\'),
+        }
+    }
+    prompt
+}
+
+/// 当前目录，位于主目录之下时用 `~` 代替主目录
+fn working_directory(home: &str) -> String {
+    match env::current_dir() {
+        Ok(dir) => abbreviate_home(&dir.display().to_string(), home),
+        Err(_) => ".".to_string(),
+    }
+}
+
+/// 位于主目录之下的路径，用 `~` 代替开头的主目录
+pub fn abbreviate_home(dir: &str, home: &str) -> String {
+    match dir.strip_prefix(home) {
+        Some(rest) if !home.is_empty() && home != "/" && (rest.is_empty() || rest.starts_with('/')) => format!("~{}", rest),
+        _ => dir.to_string(),
+    }
+}
+
+#[cfg(unix)]
+fn username() -> String {
+    use nix::unistd::{Uid, User};
+    match User::from_uid(Uid::effective()) {
+        Ok(Some(user)) => user.name,
+        _ => env::var("USER").unwrap_or_default(),
+    }
+}
+
+#[cfg(not(unix))]
+fn username() -> String {
+    env::var("USERNAME").unwrap_or_default()
+}
+
+#[cfg(unix)]
+fn hostname() -> String {
+    nix::unistd::gethostname()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
+
+#[cfg(not(unix))]
+fn hostname() -> String {
+    env::var("COMPUTERNAME").unwrap_or_default()
+}
+
+#[cfg(unix)]
+fn is_root() -> bool {
+    nix::unistd::Uid::effective().is_root()
+}
+
+#[cfg(not(unix))]
+fn is_root() -> bool {
+    false
+}
@@ -0,0 +1,976 @@
+//! shell.rs
+
+use std::collections::HashMap;
+use std::env;
+use std::ffi::{OsStr, OsString};
+use std::fs;
+use std::io::{self, BufRead, PipeReader, PipeWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use crate::builtins::{self, BUILTINS, exit_status, umask};
+use crate::command_hash::CommandHash;
+use crate::completion::CommandNameCache;
+use crate::debug::trace;
+use crate::executor;
+use crate::jobs::{JobState, JobTable};
+use crate::parser;
+use crate::prompt;
+
+/// 执行完一行命令后，主循环应该采取的动作
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Flow {
+    Continue, // 继续读取下一行
+    Exit,     // 用户执行了 exit，退出 Shell
+}
+
+/// 通过 `set` 内置命令切换的 Shell 选项
+#[derive(Debug, Default, Clone)]
+pub struct ShellOptions {
+    pub noexec: bool,           // set -n / set -o noexec：只解析并打印命令，不真正执行
+    pub helpcomplete: bool,     // set -o helpcomplete：补全 `--` 开头的参数时抓取 `command --help` 的输出
+    pub histignorefailed: bool, // set -o histignorefailed：退出状态非 0 的命令不保留在历史记录中
+    pub dotexpand: bool,        // shopt -s dotexpand：cd 时把 `...` 展开成 `../..`
+    pub xpg_echo: bool,         // shopt -s xpg_echo：echo 默认解释反斜杠转义序列
+    pub monitor: bool,          // set -m / set -o monitor：作业控制，交互模式下自动打开
+    pub errexit: bool,          // set -e / set -o errexit：命令失败时退出 Shell（作为 && 或 || 条件的命令除外）
+    pub xtrace: bool,           // set -x / set -o xtrace：执行前把展开后的每个命令以 `+ ` 开头打印到 stderr
+}
+
+/// 在子 Shell 中执行内置命令前保存的状态
+struct SavedState {
+    cwd: Option<PathBuf>,
+    dir_stack: Vec<PathBuf>,
+    env_journal_len: usize, // 保存时 env_journal 的长度，恢复时撤销之后记下的修改
+    variables: HashMap<String, String>,
+    aliases: HashMap<String, String>,
+    options: ShellOptions,
+    umask: Option<u32>,
+}
+
+/// 已经启动的进程替换：外层命令通过 /dev/fd/N 使用的管道一端，以及正在运行的命令
+#[cfg(unix)]
+#[derive(Debug)]
+struct ProcessSubstitution {
+    fd: std::os::fd::OwnedFd,
+    pipeline: executor::RunningPipeline,
+}
+
+/// Shell 的会话状态。
+/// REPL 和测试都通过 `run_line` 执行命令，保证两者走同一条路径。
+#[derive(Debug)]
+pub struct Shell {
+    pub options: ShellOptions,
+    pub script_name: String, // $0：正在执行的脚本名，交互模式下为 Shell 的名字
+    pub positional: Vec<String>, // $1、$2 ……：脚本的参数，$# 是它们的个数
+    pub lineno: usize,       // $LINENO：当前执行到的行号
+    pub last_status: i32,    // 上一条命令（管道）的退出状态
+    pub command_hash: CommandHash, // 命令名到绝对路径的缓存，由 hash 内置命令管理
+    pub command_names: CommandNameCache, // PATH 中的可执行文件，用于补全、高亮和拼错命令时的建议
+    pub jobs: JobTable,            // 以 & 启动的后台作业
+    pub aliases: HashMap<String, String>, // 由 alias 内置命令定义的别名
+    pub variables: HashMap<String, String>, // 没有导出的 Shell 变量，子进程看不到；导出的变量保存在环境中
+    pub(crate) dir_stack: Vec<PathBuf>,       // pushd 保存的目录，最后一个是栈顶
+    capture: Option<PipeWriter>,   // 执行命令替换时，标准输出写入这个管道
+    input: Option<PipeReader>,     // 启动 >(...) 进程替换时，管道的第一个命令从这个管道读取
+    #[cfg(unix)]
+    process_substitutions: Vec<ProcessSubstitution>, // 当前命令中已经启动的进程替换，命令结束后关闭管道并回收
+    pub(crate) exiting: bool,                 // source 的文件中执行了 exit，当前命令结束后退出 Shell
+    expansion_failed: bool,        // 展开单词时出错（例如 $((1/0))），放弃执行这一行剩下的命令
+    substitution_status: Option<i32>, // 展开当前命令时最后一个命令替换的退出状态，只有赋值的命令以它作为自己的状态
+    saved_states: usize,           // 还没有恢复的 save_state 的个数，不为 0 时修改环境要记入 env_journal
+    env_journal: Vec<(OsString, Option<OsString>)>, // 子 Shell 中修改过的环境变量和修改前的值
+    seconds_base: Instant, // $SECONDS 从这个时刻开始计时
+    random_state: u32,     // $RANDOM 使用的 xorshift 随机数状态，不能为 0
+}
+
+impl Default for Shell {
+    fn default() -> Self {
+        // 用当前时间和进程号作为随机数种子，保证每个会话的 $RANDOM 序列不同
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.subsec_nanos())
+            .unwrap_or(0);
+        Shell {
+            options: ShellOptions::default(),
+            script_name: "my_shell".to_string(),
+            positional: Vec::new(),
+            lineno: 0,
+            last_status: 0,
+            command_hash: CommandHash::new(),
+            command_names: CommandNameCache::new(),
+            jobs: JobTable::new(),
+            aliases: HashMap::new(),
+            variables: HashMap::new(),
+            capture: None,
+            input: None,
+            #[cfg(unix)]
+            process_substitutions: Vec::new(),
+            exiting: false,
+            expansion_failed: false,
+            substitution_status: None,
+            saved_states: 0,
+            env_journal: Vec::new(),
+            dir_stack: Vec::new(),
+            seconds_base: Instant::now(),
+            random_state: (nanos ^ process::id().rotate_left(16)) | 1,
+        }
+    }
+}
+
+impl Shell {
+    pub fn new() -> Self {
+        Shell::default()
+    }
+
+    /// 解析并执行一行命令（可能包含管道）。
+    /// 以反斜杠结尾或者引号没有闭合的行，后面紧跟着它的续行；
+    /// 如果命令使用了 here-document，正文紧跟在命令之后，以换行分隔。
+    /// 返回值告诉调用者是否应该退出 Shell。
+    pub fn run_line(&mut self, input: &str) -> Flow {
+        // 更新后台作业的状态，回收已经结束的子进程
+        self.jobs.reap();
+
+        // 行尾的反斜杠或者没有闭合的引号让命令接着写在下一行
+        let mut lines = input.lines();
+        let (command_line, _) = parser::join_continuation_lines(lines.next().unwrap_or(""), &mut lines);
+        self.run_command_line(command_line.trim(), &mut lines)
+    }
+
+    /// 执行一行命令，here-document 的正文从 `lines` 中读取。
+    /// 一行可以包含由 `&&` 和 `||` 连接的多条管道，每条管道在执行前才展开，
+    /// 这样前面的命令（例如 cd）对后面的展开立即生效。
+    fn run_command_line<'a>(&mut self, command_line: &str, lines: &mut impl Iterator<Item = &'a str>) -> Flow {
+        if command_line.is_empty() {
+            return Flow::Continue;
+        }
+
+        // 去掉注释、展开别名后解析用户输入的命令列表，每一项是一条管道
+        let command_line = parser::strip_comment(command_line).trim_end();
+        if command_line.is_empty() {
+            return Flow::Continue;
+        }
+        let command_line = parser::expand_aliases(command_line, &self.aliases);
+        let mut items = match parser::parse_command_list(&command_line) {
+            Ok(items) => items,
+            Err(e) => {
+                eprintln!("my_shell: 解析错误: {}", e);
+                self.last_status = 2;
+                return Flow::Continue;
+            }
+        };
+        for item in items.iter_mut() {
+            parser::collect_heredoc_bodies(&mut item.commands, lines);
+        }
+
+        let mut items = items.into_iter().peekable();
+        while let Some(mut item) = items.next() {
+            let run = match item.connector {
+                parser::Connector::Always => true,
+                parser::Connector::And => self.last_status == 0,
+                parser::Connector::Or => self.last_status != 0,
+            };
+            if !run {
+                continue;
+            }
+
+            self.expand_commands(&mut item.commands);
+            let background = item.commands.last().is_some_and(|cmd| cmd.background);
+            // 和 bash 一样，展开出错时不执行这条命令，也不再执行这一行后面的命令
+            if std::mem::take(&mut self.expansion_failed) {
+                self.finish_process_substitutions(background);
+                self.last_status = 1;
+                return Flow::Continue;
+            }
+            if self.options.xtrace {
+                // 子 Shell 组和复合命令中的命令执行时会各自打印
+                for cmd in item.commands.iter().filter(|cmd| cmd.group.is_none() && cmd.case.is_none() && cmd.select.is_none()) {
+                    eprintln!("+ {}", cmd.trace_line());
+                }
+            }
+            if item.commands.len() == 1 && item.commands[0].name == "exit" {
+                self.finish_process_substitutions(background);
+                // 和 bash 一样，参数太多时不退出
+                if let Some(status) = exit_status(&item.commands[0].args, self.last_status) {
+                    self.last_status = status;
+                    return Flow::Exit;
+                }
+                self.last_status = 1;
+                continue;
+            }
+
+            // 管道前的 ! 对整个管道的退出状态取反，但不影响 PIPESTATUS。
+            // time 统计整条管道从启动到全部结束的时间；后台管道不等待，也就不报告
+            self.share_process_substitutions();
+            let stopwatch = (item.timed && !background).then(Stopwatch::start);
+            let result = self.run_pipeline(&item.commands);
+            self.finish_process_substitutions(background);
+            if let Some(stopwatch) = stopwatch {
+                stopwatch.report();
+            }
+            self.set_pipestatus(&result.statuses);
+            self.last_status = if item.negated { (result.last == 0) as i32 } else { result.last };
+            trace!("status: {}", self.last_status);
+            if std::mem::take(&mut self.exiting) {
+                return Flow::Exit;
+            }
+            // 和 bash 一样，&& 或 || 左侧的命令和 ! 取反的管道是条件，失败时不触发 errexit
+            let condition = item.negated || items.peek().is_some_and(|next| next.connector != parser::Connector::Always);
+            if self.options.errexit && self.last_status != 0 && !condition {
+                trace!("errexit: 状态 {}，退出", self.last_status);
+                return Flow::Exit;
+            }
+        }
+        Flow::Continue
+    }
+
+    /// 执行命令时发生 panic 之后调用：撤销被打断的子 Shell 对环境的修改，清除只在执行一行命令期间有效的状态
+    pub fn recover_from_panic(&mut self) {
+        for (name, old) in std::mem::take(&mut self.env_journal).into_iter().rev() {
+            self.write_env(&name, old.as_deref());
+        }
+        self.saved_states = 0;
+        self.capture = None;
+        self.input = None;
+        self.exiting = false;
+        self.expansion_failed = false;
+    }
+
+    /// 根据 $PS1 生成提示符，未设置时使用 `my_shell> `。
+    /// 和 bash 一样先解释反斜杠转义序列，再展开其中的变量和命令替换，
+    /// 例如 `PS1='[$?] \w\$ '` 显示上一条命令的退出状态。
+    pub fn prompt(&mut self) -> String {
+        // 和 bash 一样，PS1 通常是不导出的 Shell 变量
+        let Some(ps1) = self.variables.get("PS1").cloned().or_else(|| env::var("PS1").ok()) else {
+            return prompt::DEFAULT_PROMPT.to_string();
+        };
+        let rendered = prompt::render_escapes(&ps1, &home_dir());
+        let status = self.last_status;
+        let prompt = parser::remove_quotes(&parser::substitute(&parser::heredoc_word(&rendered), self));
+        self.expansion_failed = false;
+        self.last_status = status; // 提示符中的命令替换不影响 $?
+        prompt
+    }
+
+    /// 交互模式下每次显示提示符之前调用：报告上一个提示符之后结束的后台作业，并把它们从作业表中删除。
+    /// 只回收已经结束的作业，不等待还在运行的作业
+    pub fn notify_finished_jobs(&mut self, out: &mut dyn Write) {
+        for notice in self.jobs.take_finished_notices() {
+            let _ = writeln!(out, "{}", notice);
+        }
+    }
+
+    /// 补全命令名：以 `prefix` 开头的别名、内置命令和 PATH 中的命令，已排序、去重。
+    /// 别名直接从别名表读取，刚用 alias 定义的别名立即可以补全
+    pub fn complete_command_name(&self, prefix: &str) -> Vec<String> {
+        let names: Vec<&str> = BUILTINS.iter().copied().chain(self.aliases.keys().map(String::as_str)).collect();
+        self.command_names.complete(prefix, &names)
+    }
+
+    /// 执行已经展开的管道，返回各阶段的退出状态
+    fn run_pipeline(&mut self, parsed_commands: &[parser::ParsedCommand]) -> executor::PipelineResult {
+        // dry-run 模式：打印展开后的命令，不启动进程。
+        // set 在 dry-run 模式下也要生效，否则无法关闭 noexec
+        let is_set = parsed_commands.len() == 1 && parsed_commands[0].name == "set";
+        if self.options.noexec && !is_set {
+            executor::print_pipeline(parsed_commands);
+            return executor::PipelineResult::single(0);
+        }
+
+        // 没有重定向的前台子 Shell 组直接执行，组内的命令照常使用终端；
+        // 其他情况下组和内置命令一样由 executor 执行，输出先被收集起来
+        if let [cmd] = parsed_commands
+            && let Some(body) = &cmd.group
+            && !cmd.background
+            && !cmd.has_redirections()
+        {
+            return executor::PipelineResult::single(self.run_subshell(body));
+        }
+        // 没有重定向的前台 case 和 select 命令在当前 Shell 中执行，其中的 cd 和赋值会保留下来
+        if let [cmd] = parsed_commands
+            && let Some(case) = &cmd.case
+            && !cmd.background
+            && !cmd.has_redirections()
+        {
+            return executor::PipelineResult::single(self.run_case(case));
+        }
+        if let [cmd] = parsed_commands
+            && let Some(select) = &cmd.select
+            && !cmd.background
+            && !cmd.has_redirections()
+        {
+            return executor::PipelineResult::single(self.run_select(select, None));
+        }
+
+        // 后台管道：启动后立即返回；和 bash 一样，只在作业控制打开时打印作业号和最后一个进程的 pid
+        if parsed_commands.last().is_some_and(|cmd| cmd.background) {
+            let running = executor::spawn_pipeline(parsed_commands, self);
+            if running.children.is_empty() {
+                // 没有启动任何进程：整条管道都是已经执行完的内置命令或者启动失败的命令
+                return running.wait();
+            }
+            let job = self.jobs.add(executor::format_pipeline(parsed_commands), running.children, JobState::Running);
+            if self.options.monitor {
+                eprintln!("[{}] {}", job.id, job.pid().unwrap_or(0));
+            }
+            return executor::PipelineResult::single(0);
+        }
+
+        // 执行管道中的命令；作业控制下被 Ctrl-Z 停止的管道登记成停止的作业，之后可以用 fg 或 bg 继续
+        match executor::spawn_pipeline(parsed_commands, self).wait_or_stop() {
+            Ok(result) => result,
+            Err(children) => {
+                let id = self.jobs.add(executor::format_pipeline(parsed_commands), children, JobState::Stopped).id;
+                if let Some(job) = self.jobs.get(id) {
+                    eprintln!("{}", self.jobs.describe(job));
+                }
+                executor::PipelineResult::single(executor::STOPPED_STATUS)
+            },
+        }
+    }
+
+    /// 把各阶段的状态记录到 PIPESTATUS。Shell 变量没有数组，所以和 `${PIPESTATUS[@]}` 一样用空格分隔
+    fn set_pipestatus(&mut self, statuses: &[i32]) {
+        let value: Vec<String> = statuses.iter().map(i32::to_string).collect();
+        self.variables.insert("PIPESTATUS".to_string(), value.join(" "));
+    }
+
+    /// 保存内置命令可能修改的状态，供子 Shell 结束后恢复。
+    /// 环境不整体保存，而是从这时起记下 Shell 对它的每次修改，恢复时只撤销这些修改
+    fn save_state(&mut self) -> SavedState {
+        self.saved_states += 1;
+        SavedState {
+            cwd: env::current_dir().ok(),
+            dir_stack: self.dir_stack.clone(),
+            env_journal_len: self.env_journal.len(),
+            variables: self.variables.clone(),
+            aliases: self.aliases.clone(),
+            options: self.options.clone(),
+            umask: umask(None).ok(),
+        }
+    }
+
+    /// 恢复 `save_state` 保存的状态
+    fn restore_state(&mut self, saved: SavedState) {
+        if let Some(cwd) = &saved.cwd
+            && env::current_dir().ok().as_ref() != Some(cwd)
+        {
+            let _ = env::set_current_dir(cwd);
+        }
+        self.saved_states -= 1;
+        for (name, old) in self.env_journal.split_off(saved.env_journal_len).into_iter().rev() {
+            self.write_env(&name, old.as_deref());
+        }
+        self.dir_stack = saved.dir_stack;
+        self.variables = saved.variables;
+        self.aliases = saved.aliases;
+        self.options = saved.options;
+        if let Some(mask) = saved.umask {
+            let _ = umask(Some(mask));
+        }
+        self.exiting = false; // 子 Shell 中的 exit 不会退出当前 Shell
+    }
+
+    /// 命令替换：执行命令并返回它写到标准输出的内容。
+    /// 和子 Shell 组一样，其中的 cd、变量赋值和 export 等不影响当前 Shell，只有退出状态留在 `$?` 中
+    fn capture_output(&mut self, command_line: &str) -> String {
+        let output = self.capture_bytes(|shell| {
+            let saved = shell.save_state();
+            shell.run_command_line(command_line.trim(), &mut std::iter::empty());
+            shell.restore_state(saved);
+        });
+        self.substitution_status = Some(self.last_status);
+        String::from_utf8_lossy(&output).into_owned()
+    }
+
+    /// 执行 `run` 并收集其中的命令写到标准输出的内容。
+    /// 输出在后台线程中读取，避免输出填满管道后命令阻塞。
+    pub(crate) fn capture_bytes(&mut self, run: impl FnOnce(&mut Self)) -> Vec<u8> {
+        let (mut reader, writer) = match io::pipe() {
+            Ok(pipe) => pipe,
+            Err(e) => {
+                eprintln!("my_shell: 无法创建管道: {}", e);
+                return Vec::new();
+            }
+        };
+        let output = thread::spawn(move || {
+            let mut bytes = Vec::new();
+            let _ = reader.read_to_end(&mut bytes);
+            bytes
+        });
+
+        // 嵌套的命令替换会临时换成自己的管道，结束后恢复外层的管道
+        let outer = self.capture.replace(writer);
+        run(self);
+        self.capture = outer; // 关闭写端，读取线程才能读到文件结束
+        output.join().unwrap_or_default()
+    }
+
+    /// 进程替换：启动 `command` 但不等待它结束，返回外层命令使用的路径 /dev/fd/N。
+    /// `<(...)` 中命令的输出写入管道，外层命令从读端读取；`>(...)` 中的命令从管道读取外层命令写入写端的内容。
+    /// Shell 持有外层命令使用的那一端，直到外层命令结束。命令列表（`a; b`）作为子 Shell 组执行。
+    /// 平台限制：需要 /dev/fd（Linux、macOS 等 Unix 系统），其他平台上展开失败；
+    /// 和 bash 一样管道不能 seek，只能顺序读写一次；其中的内置命令不读取 `>(...)` 的输入。
+    #[cfg(unix)]
+    fn spawn_process_substitution(&mut self, command: &str, read: bool) -> io::Result<String> {
+        use std::os::fd::{AsRawFd, OwnedFd};
+
+        let command_line = parser::expand_aliases(parser::strip_comment(command).trim(), &self.aliases);
+        let single = parser::parse_command_list(&command_line).is_ok_and(|items| items.len() == 1 && !items[0].negated);
+        let pipeline = if single { command_line } else { format!("({})", command_line) };
+        let mut commands = parser::parse_pipeline_commands(&pipeline).map_err(io::Error::other)?;
+        self.expand_commands(&mut commands);
+
+        let (reader, writer) = io::pipe()?;
+        let (fd, pipeline): (OwnedFd, _) = if read {
+            let outer = self.capture.replace(writer);
+            let pipeline = executor::spawn_pipeline(&commands, self);
+            self.capture = outer; // 关闭 Shell 中的写端，命令结束后外层命令才能读到文件结束
+            (reader.into(), pipeline)
+        } else {
+            let outer = self.input.replace(reader);
+            let pipeline = executor::spawn_pipeline(&commands, self);
+            self.input = outer;
+            (writer.into(), pipeline)
+        };
+        let path = format!("/dev/fd/{}", fd.as_raw_fd());
+        trace!("process substitution: {} -> {}", command.trim(), path);
+        self.process_substitutions.push(ProcessSubstitution { fd, pipeline });
+        Ok(path)
+    }
+
+    #[cfg(not(unix))]
+    fn spawn_process_substitution(&mut self, _command: &str, _read: bool) -> io::Result<String> {
+        Err(io::Error::new(io::ErrorKind::Unsupported, "当前平台没有 /dev/fd，不支持进程替换"))
+    }
+
+    /// 外层命令启动前去掉进程替换管道的 FD_CLOEXEC 标志，让外层命令继承它们。
+    /// 之前启动的进程替换中的命令不会继承其他进程替换的管道
+    fn share_process_substitutions(&self) {
+        #[cfg(unix)]
+        for substitution in &self.process_substitutions {
+            use nix::fcntl::{FcntlArg, FdFlag, fcntl};
+            use std::os::fd::AsRawFd;
+            let _ = fcntl(substitution.fd.as_raw_fd(), FcntlArg::F_SETFD(FdFlag::empty()));
+        }
+    }
+
+    /// 外层命令结束后关闭 Shell 持有的管道，再回收进程替换中的命令。
+    /// 前台命令等待它们结束，这样 `tee >(wc -l > count)` 之后立即可以读到 count；
+    /// 后台命令还在使用管道，由单独的线程等待，不阻塞 Shell
+    fn finish_process_substitutions(&mut self, background: bool) {
+        #[cfg(unix)]
+        {
+            let (fds, pipelines): (Vec<_>, Vec<_>) = self
+                .process_substitutions
+                .drain(..)
+                .map(|substitution| (substitution.fd, substitution.pipeline))
+                .unzip();
+            drop(fds);
+            for pipeline in pipelines {
+                if background {
+                    thread::spawn(move || pipeline.wait());
+                } else {
+                    pipeline.wait();
+                }
+            }
+        }
+        #[cfg(not(unix))]
+        let _ = background;
+    }
+
+    /// 子 Shell 组 `( ... )`：在当前进程中执行括号中的命令列表，结束后恢复工作目录、变量、别名和选项，
+    /// 所以组内的 cd、export 和赋值不会影响外面；组内的 exit 只结束这个组。
+    fn run_subshell(&mut self, body: &str) -> i32 {
+        let saved = self.save_state();
+        self.run_command_line(body, &mut std::iter::empty());
+        let status = self.last_status;
+        self.restore_state(saved);
+        status
+    }
+
+    /// case 命令：依次用各分支的模式匹配展开后的单词，执行第一个匹配的分支的命令列表。
+    /// 模式在轮到它时才展开；`;&` 接着执行下一个分支，`;;&` 继续匹配后面的分支。
+    /// 退出状态是最后执行的命令的状态，没有分支匹配时为 0。
+    fn run_case(&mut self, case: &parser::CaseCommand) -> i32 {
+        let home = home_dir();
+        let word = parser::remove_quotes(&parser::substitute(&parser::expand_tilde(&case.word, &home), self));
+        let mut status = 0;
+        let mut fall_through = false;
+        for clause in &case.clauses {
+            let matched = fall_through
+                || clause.patterns.iter().any(|pattern| {
+                    let pattern = parser::substitute(&parser::expand_tilde(pattern, &home), self);
+                    parser::pattern_matches(&pattern, &word)
+                });
+            if !matched {
+                continue;
+            }
+            status = 0;
+            if !clause.body.is_empty() {
+                if let Flow::Exit = self.run_command_line(&clause.body, &mut std::iter::empty()) {
+                    // 和 source 的文件中的 exit 一样，当前命令结束后退出 Shell
+                    self.exiting = true;
+                    return self.last_status;
+                }
+                status = self.last_status;
+            }
+            match clause.terminator {
+                parser::CaseTerminator::Break => break,
+                parser::CaseTerminator::FallThrough => fall_through = true,
+                parser::CaseTerminator::Continue => fall_through = false,
+            }
+        }
+        status
+    }
+
+    /// Shell 退出时调用：和 bash 结束会话时一样，给还在运行或停止的作业发送 SIGHUP，
+    /// disown 过的作业不受影响
+    pub fn hangup_jobs(&mut self) {
+        self.jobs.hangup();
+    }
+
+    /// 执行只有赋值的命令 `NAME=value ...`：已经导出的变量更新环境，其他的保存为 Shell 变量。
+    /// 给 `SECONDS` 赋值会让它从这个值重新开始计时。
+    /// 和 bash 一样，退出状态是展开时最后一个命令替换的状态，没有命令替换时为 0。
+    pub(crate) fn run_assignments(&mut self, assignments: &[(String, String)]) -> i32 {
+        for (name, value) in assignments {
+            if name == "SECONDS" {
+                let seconds = value.trim().parse().unwrap_or(0);
+                self.seconds_base = Instant::now().checked_sub(Duration::from_secs(seconds)).unwrap_or_else(Instant::now);
+            } else if env::var_os(name).is_some() {
+                self.set_env(name, value);
+            } else {
+                self.variables.insert(name.clone(), value.clone());
+            }
+        }
+        self.substitution_status.unwrap_or(0)
+    }
+
+    /// 执行内置命令前把命令前的赋值临时放进环境，返回需要恢复的旧值
+    fn apply_temporary_assignments(&mut self, assignments: &[(String, String)]) -> Vec<(String, Option<OsString>)> {
+        assignments
+            .iter()
+            .map(|(name, value)| {
+                let old = env::var_os(name);
+                self.set_env(name, value);
+                (name.clone(), old)
+            })
+            .collect()
+    }
+
+    /// 恢复 `apply_temporary_assignments` 修改之前的环境变量
+    fn restore_temporary_assignments(&mut self, temporary: Vec<(String, Option<OsString>)>) {
+        for (name, old) in temporary {
+            self.write_env(name.as_ref(), old.as_deref());
+        }
+    }
+
+    /// 设置环境变量，之后启动的命令都会继承它
+    pub(crate) fn set_env(&mut self, name: impl AsRef<OsStr>, value: impl AsRef<OsStr>) {
+        self.write_env(name.as_ref(), Some(value.as_ref()));
+    }
+
+    /// 从环境中删除变量
+    pub(crate) fn remove_env(&mut self, name: impl AsRef<OsStr>) {
+        self.write_env(name.as_ref(), None);
+    }
+
+    /// Shell 对环境的所有修改都经过这里；在子 Shell 中时先记下旧值，子 Shell 结束后由 restore_state 撤销
+    fn write_env(&mut self, name: &OsStr, value: Option<&OsStr>) {
+        if self.saved_states > 0 {
+            self.env_journal.push((name.to_os_string(), env::var_os(name)));
+        }
+        // SAFETY: 标准库的环境读写和 Command 启动子进程都持有同一把内部的锁，和它们同时执行是安全的；
+        // 不安全的只有其他线程中绕过标准库、直接读取 environ 的 C 代码（例如 getenv）。
+        // Shell 只在执行命令的线程中修改环境，它自己启动的线程只读写管道和等待子进程，不读取环境；
+        // 同一进程中的多个 Shell（例如并行运行的测试）修改环境时必须由调用者串行执行
+        unsafe {
+            match value {
+                Some(value) => env::set_var(name, value),
+                None => env::remove_var(name),
+            }
+        }
+    }
+
+    /// 逐行执行脚本文件，执行期间 `$0` 为脚本路径，`$1`、`$2` …… 为 `args`，`$#` 为参数个数。
+    pub fn run_file(&mut self, path: &str, args: &[String]) -> io::Result<Flow> {
+        let content = fs::read_to_string(path)?;
+        let saved_name = std::mem::replace(&mut self.script_name, path.to_string());
+        let saved_args = std::mem::replace(&mut self.positional, args.to_vec());
+        let flow = self.run_script(&content);
+        self.script_name = saved_name;
+        self.positional = saved_args;
+        Ok(flow)
+    }
+
+    /// 交互模式启动时执行配置文件（~/.rust_shellrc），用来定义别名、导出变量和设置提示符。
+    /// 和 source 一样在当前 Shell 中执行，`$0` 保持不变；文件不存在时直接跳过，
+    /// 其中的命令出错只打印到 stderr，不影响 Shell 启动。
+    pub fn run_rc_file(&mut self, path: &Path) -> Flow {
+        match fs::read_to_string(path) {
+            Ok(content) => self.run_script(&content),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Flow::Continue,
+            Err(e) => {
+                eprintln!("my_shell: {}: {}", path.display(), e);
+                Flow::Continue
+            },
+        }
+    }
+
+    /// 逐行执行脚本内容，`$LINENO` 为当前命令所在的行号，空行和 `#` 开头的注释行直接跳过。
+    /// here-document 的正文行会和命令行一起交给 `run_line`。遇到 exit 时停止并返回 `Flow::Exit`。
+    pub fn run_script(&mut self, content: &str) -> Flow {
+        let lines: Vec<&str> = content.lines().collect();
+        let saved_lineno = self.lineno;
+
+        let mut flow = Flow::Continue;
+        let mut i = 0;
+        while i < lines.len() {
+            self.lineno = i + 1;
+            let mut input = lines[i].to_string();
+            i += 1;
+            let trimmed = input.trim_start();
+            if trimmed.is_empty() || trimmed.starts_with('#') {
+                continue;
+            }
+            // 续行和命令行一起交给 run_line，由它重新拼接
+            let mut command_line = input.clone();
+            while i < lines.len() && let Some(incomplete) = parser::incomplete_line(&command_line) {
+                incomplete.join(&mut command_line, lines[i]);
+                input.push('\n');
+                input.push_str(lines[i]);
+                i += 1;
+            }
+            for heredoc in parser::pending_heredocs(&command_line) {
+                while i < lines.len() {
+                    input.push('\n');
+                    input.push_str(lines[i]);
+                    i += 1;
+                    if heredoc.is_terminator(lines[i - 1]) {
+                        break;
+                    }
+                }
+            }
+            flow = self.run_line(&input);
+            if flow == Flow::Exit {
+                break;
+            }
+        }
+
+        self.lineno = saved_lineno;
+        flow
+    }
+
+    /// 对命令名、参数和重定向文件名进行 `~`、变量和命令替换，对参数先做花括号展开，最后做字段分割和路径名展开；
+    /// 结束标记没有引号的 here-document 正文也做变量和命令替换
+    fn expand_commands(&mut self, commands: &mut [parser::ParsedCommand]) {
+        self.substitution_status = None;
+        let home = home_dir();
+        let mut substitute = |word: &str| parser::substitute(&parser::expand_tilde(word, &home), self);
+        for cmd in commands.iter_mut() {
+            // 赋值的值和重定向文件名一样不做字段分割和路径名展开
+            for (_, value) in cmd.assignments.iter_mut() {
+                *value = parser::remove_quotes(&substitute(value));
+            }
+            cmd.name = parser::remove_quotes(&substitute(&cmd.name));
+            cmd.args = expand_arguments(&cmd.args, &mut substitute);
+            if let Some(filepath) = &mut cmd.stdin_redirect {
+                *filepath = parser::remove_quotes(&substitute(filepath));
+            }
+            if let Some(word) = &mut cmd.herestring {
+                *word = parser::remove_quotes(&substitute(word));
+            }
+            for heredoc in cmd.heredocs.iter_mut().filter(|heredoc| heredoc.expand) {
+                heredoc.body = parser::remove_quotes(&substitute(&parser::heredoc_word(&heredoc.body)));
+            }
+            if let Some((filepath, _)) = &mut cmd.stdout_redirect {
+                *filepath = parser::remove_quotes(&substitute(filepath));
+            }
+            if let Some((filepath, _)) = &mut cmd.stderr_redirect {
+                *filepath = parser::remove_quotes(&substitute(filepath));
+            }
+            trace!("expanded: {:?} {:?}", cmd.name, cmd.args);
+        }
+    }
+
+    /// select 命令：在 stderr 上显示编号的菜单（省略 `in` 时列出位置参数）和 `$PS3` 提示符（默认为 `#? `），读取一行输入，
+    /// 把对应编号的单词赋给变量（无效的编号赋空字符串）、整行赋给 `REPLY`，然后执行循环体，直到输入结束。
+    /// 输入为空行时重新显示菜单。输入来自命令的 `<`、here-document 或 here-string，没有时读取 Shell 的 stdin。
+    /// 退出状态是最后执行的命令的状态，没有执行循环体时为 0。
+    fn run_select(&mut self, select: &parser::SelectCommand, mut input: Option<Box<dyn BufRead>>) -> i32 {
+        let home = home_dir();
+        let words = match &select.words {
+            Some(words) => expand_arguments(words, &mut |word| parser::substitute(&parser::expand_tilde(word, &home), self)),
+            None => self.positional.clone(),
+        };
+        let mut status = 0;
+        let mut show_menu = true;
+        while !words.is_empty() {
+            if std::mem::take(&mut show_menu) {
+                for (number, word) in words.iter().enumerate() {
+                    eprintln!("{}) {}", number + 1, word);
+                }
+            }
+            let ps3 = self.variables.get("PS3").cloned().or_else(|| env::var("PS3").ok()).unwrap_or_else(|| "#? ".to_string());
+            eprint!("{}", ps3);
+            let mut line = String::new();
+            let read = match &mut input {
+                Some(input) => input.read_line(&mut line),
+                None => io::stdin().read_line(&mut line),
+            };
+            if !matches!(read, Ok(count) if count > 0) {
+                break;
+            }
+            let reply = line.strip_suffix('\n').unwrap_or(&line);
+            if reply.trim().is_empty() {
+                show_menu = true;
+                continue;
+            }
+            let choice = reply.trim().parse::<usize>().ok().and_then(|number| words.get(number.checked_sub(1)?)).cloned();
+            self.run_assignments(&[(select.name.clone(), choice.unwrap_or_default()), ("REPLY".to_string(), reply.to_string())]);
+            if let Flow::Exit = self.run_command_line(&select.body, &mut std::iter::empty()) {
+                // 和 source 的文件中的 exit 一样，当前命令结束后退出 Shell
+                self.exiting = true;
+                return self.last_status;
+            }
+            status = self.last_status;
+        }
+        status
+    }
+
+    /// 查找变量的值：先查特殊变量，再查 Shell 变量和环境变量；和 bash 一样，未设置的变量展开为空字符串
+    pub(crate) fn variable_value(&mut self, name: &str) -> String {
+        self.special_variable(name)
+            .or_else(|| self.variables.get(name).cloned())
+            .or_else(|| env::var(name).ok())
+            .unwrap_or_default()
+    }
+
+    /// 每次引用时动态计算的特殊变量：
+    ///   - `$RANDOM`：0 到 32767 之间的随机整数，每次引用都不同
+    ///   - `$SECONDS`：Shell 启动以来经过的秒数
+    ///   - `$LINENO` 和 `$0`：当前行号和脚本名
+    ///   - `$1` 到 `$9` 和 `$#`：脚本的参数和参数个数，没有给出的参数为空
+    ///   - `$?`：上一条管道的退出状态
+    fn special_variable(&mut self, name: &str) -> Option<String> {
+        match name {
+            "0" => Some(self.script_name.clone()),
+            "#" => Some(self.positional.len().to_string()),
+            "?" => Some(self.last_status.to_string()),
+            _ if name.len() == 1 && name.as_bytes()[0].is_ascii_digit() => {
+                let index = (name.as_bytes()[0] - b'1') as usize;
+                Some(self.positional.get(index).cloned().unwrap_or_default())
+            },
+            "LINENO" => Some(self.lineno.to_string()),
+            "RANDOM" => Some(self.next_random().to_string()),
+            "SECONDS" => Some(self.seconds_base.elapsed().as_secs().to_string()),
+            _ => None,
+        }
+    }
+
+    /// xorshift32 伪随机数生成器，取高位得到 15 位的结果
+    fn next_random(&mut self) -> u32 {
+        let mut x = self.random_state;
+        x ^= x << 13;
+        x ^= x >> 17;
+        x ^= x << 5;
+        self.random_state = x;
+        (x >> 16) & 0x7fff
+    }
+}
+
+impl executor::PipelineHost for Shell {
+    fn command_hash(&mut self) -> &mut CommandHash {
+        &mut self.command_hash
+    }
+
+    fn capture(&self) -> Option<&PipeWriter> {
+        self.capture.as_ref()
+    }
+
+    fn input(&self) -> Option<&PipeReader> {
+        self.input.as_ref()
+    }
+
+    fn suggest_command(&self, name: &str) -> Option<String> {
+        self.command_names.closest(name, BUILTINS)
+    }
+
+    /// 命令替换和进程替换中的命令不做作业控制，它们的输入输出连接到 Shell 创建的管道，不能占有终端
+    fn job_control(&self) -> bool {
+        self.options.monitor && self.capture.is_none() && self.input.is_none()
+    }
+
+    fn run_builtin(&mut self, cmd: &parser::ParsedCommand, out: &mut dyn Write, subshell: bool) -> Option<i32> {
+        // 管道中或带重定向的子 Shell 组：收集组内所有命令的输出，交给 executor 写到组的输出去向
+        if let Some(body) = &cmd.group {
+            let mut status = 0;
+            let output = self.capture_bytes(|shell| status = shell.run_subshell(body));
+            let _ = out.write_all(&output);
+            return Some(status);
+        }
+        // 管道中或带重定向的 case 和 select 命令：同样收集输出；在管道中时和内置命令一样不保留对 Shell 状态的修改
+        if let Some(case) = &cmd.case {
+            let saved = subshell.then(|| self.save_state());
+            let mut status = 0;
+            let output = self.capture_bytes(|shell| status = shell.run_case(case));
+            let _ = out.write_all(&output);
+            if let Some(saved) = saved {
+                self.restore_state(saved);
+            }
+            return Some(status);
+        }
+        if let Some(select) = &cmd.select {
+            let Ok(input) = builtins::redirected_input(cmd) else {
+                return Some(1);
+            };
+            let saved = subshell.then(|| self.save_state());
+            let mut status = 0;
+            let output = self.capture_bytes(|shell| status = shell.run_select(select, input));
+            let _ = out.write_all(&output);
+            if let Some(saved) = saved {
+                self.restore_state(saved);
+            }
+            return Some(status);
+        }
+        let builtin = match cmd.name.as_str() {
+            "" => None,
+            name => Some(builtins::find_builtin(name)?),
+        };
+        trace!("builtin: {}", cmd);
+        // bash 在子进程中执行管道的各个命令，内置命令对 Shell 状态的修改不会保留下来
+        let saved = subshell.then(|| self.save_state());
+        // 内置命令前的赋值只在它执行期间生效
+        let status = match builtin {
+            Some(builtin) => {
+                let temporary = self.apply_temporary_assignments(&cmd.assignments);
+                let status = builtin.run(&mut builtins::Context { shell: self, cmd, out, subshell });
+                self.restore_temporary_assignments(temporary);
+                status
+            },
+            None => self.run_assignments(&cmd.assignments),
+        };
+        if let Some(saved) = saved {
+            self.restore_state(saved);
+        }
+        Some(status)
+    }
+}
+
+impl parser::Expander for Shell {
+    fn variable(&mut self, name: &str) -> Option<String> {
+        Some(self.variable_value(name))
+    }
+
+    fn command_output(&mut self, command: &str) -> Option<String> {
+        Some(self.capture_output(command))
+    }
+
+    fn assign(&mut self, name: &str, value: &str) {
+        self.run_assignments(&[(name.to_string(), value.to_string())]);
+    }
+
+    /// Shell 变量和环境变量都算在内，同名的只列一次
+    fn variable_names(&mut self, prefix: &str) -> Vec<String> {
+        let mut names: Vec<String> = self.variables.keys().cloned()
+            .chain(env::vars_os().filter_map(|(name, _)| name.into_string().ok()))
+            .filter(|name| name.starts_with(prefix))
+            .collect();
+        names.sort();
+        names.dedup();
+        names
+    }
+
+    /// dry-run 模式下不启动进程替换中的命令，保留原文
+    fn process_substitution(&mut self, command: &str, read: bool) -> Option<String> {
+        if self.options.noexec {
+            return None;
+        }
+        match self.spawn_process_substitution(command, read) {
+            Ok(path) => Some(path),
+            Err(e) => {
+                self.expansion_error(&format!("进程替换失败: {}", e));
+                None
+            },
+        }
+    }
+
+    fn expansion_error(&mut self, message: &str) {
+        eprintln!("my_shell: {}", message);
+        self.expansion_failed = true;
+    }
+}
+
+/// time 开始计时的时刻：墙上时间，以及到这时为止 Shell 自身和已回收的子进程用掉的 CPU 时间
+pub(crate) struct Stopwatch {
+    start: Instant,
+    cpu: Option<(Duration, Duration)>,
+}
+
+impl Stopwatch {
+    pub(crate) fn start() -> Self {
+        Stopwatch { start: Instant::now(), cpu: cpu_times() }
+    }
+
+    /// 和 bash 一样在 stderr 上先空一行，再打印 real、user 和 sys；拿不到 CPU 时间时只打印 real
+    pub(crate) fn report(&self) {
+        eprintln!("\nreal\t{}", format_time(self.start.elapsed()));
+        if let (Some((user_start, sys_start)), Some((user, sys))) = (self.cpu, cpu_times()) {
+            eprintln!("user\t{}", format_time(user.saturating_sub(user_start)));
+            eprintln!("sys\t{}", format_time(sys.saturating_sub(sys_start)));
+        }
+    }
+}
+
+/// 对参数做花括号展开、字段分割和路径名展开，一个单词可能展开成多个参数；
+/// `substitute` 对单词做 `~`、变量和命令替换
+fn expand_arguments(words: &[String], substitute: &mut impl FnMut(&str) -> String) -> Vec<String> {
+    let mut args = Vec::new();
+    for word in words.iter().flat_map(|arg| parser::expand_braces(arg)) {
+        for field in parser::split_fields(&word, &substitute(&word)) {
+            match parser::expand_glob(&field) {
+                matches if matches.is_empty() => args.push(parser::remove_quotes(&field)),
+                matches => args.extend(matches),
+            }
+        }
+    }
+    args
+}
+
+/// 把 time 统计的时长格式化成 bash 的样子，例如 `0m1.234s`
+pub fn format_time(duration: Duration) -> String {
+    let millis = duration.as_millis();
+    format!("{}m{}.{:03}s", millis / 60_000, millis / 1000 % 60, millis % 1000)
+}
+
+/// Shell 自身（内置命令）和已经回收的子进程用掉的 CPU 时间：(用户态, 内核态)
+#[cfg(unix)]
+fn cpu_times() -> Option<(Duration, Duration)> {
+    use nix::sys::resource::{UsageWho, getrusage};
+    use nix::sys::time::TimeVal;
+    fn duration(time: TimeVal) -> Duration {
+        Duration::from_secs(time.tv_sec() as u64) + Duration::from_micros(time.tv_usec() as u64)
+    }
+    let own = getrusage(UsageWho::RUSAGE_SELF).ok()?;
+    let children = getrusage(UsageWho::RUSAGE_CHILDREN).ok()?;
+    Some((
+        duration(own.user_time()) + duration(children.user_time()),
+        duration(own.system_time()) + duration(children.system_time()),
+    ))
+}
+
+#[cfg(not(unix))]
+fn cpu_times() -> Option<(Duration, Duration)> {
+    None
+}
+
+/// `~` 展开使用的主目录：优先取 $HOME，未设置时向系统查询
+pub fn home_dir() -> String {
+    env::var("HOME")
+        .ok()
+        .or_else(|| dirs_next::home_dir().map(|dir| dir.display().to_string()))
+        .unwrap_or_else(|| "~".to_string())
+}
@@ -0,0 +1,170 @@
+//! terminal.rs
+
+use std::io::{self, Write};
+
+/// reset 内置命令写出的终端复位序列：
+/// 离开备用屏幕、清除文字属性、显示光标，最后用 RIS (ESC c) 完全复位终端
+const RESET_SEQUENCE: &[u8] = b"\x1b[?1049l\x1b[0m\x1b[?25h\x1bc";
+
+/// 启动时保存的终端设置。
+/// 全屏程序崩溃后可能把终端留在 raw 模式，导致提示符错乱；
+/// 主循环在每次显示提示符前调用 `restore`，把终端恢复成启动时的样子。
+#[cfg(unix)]
+pub struct TerminalState {
+    saved: Option<nix::sys::termios::Termios>, // stdin 不是终端时为 None
+}
+
+#[cfg(unix)]
+impl TerminalState {
+    /// 保存 stdin 当前的 termios 设置
+    pub fn save() -> TerminalState {
+        use nix::sys::termios::tcgetattr;
+        use nix::unistd::isatty;
+
+        let fd = stdin_fd();
+        let saved = match isatty(fd) {
+            Ok(true) => tcgetattr(fd).ok(),
+            _ => None,
+        };
+        TerminalState { saved }
+    }
+
+    /// 恢复保存的 termios 设置，失败时静默忽略
+    pub fn restore(&self) {
+        use nix::sys::termios::{tcsetattr, SetArg};
+
+        if let Some(termios) = &self.saved {
+            let _ = tcsetattr(stdin_fd(), SetArg::TCSADRAIN, termios);
+        }
+    }
+}
+
+/// reset/sane 内置命令：把终端恢复成 cooked 模式并发送复位序列。
+/// 不依赖外部的 reset 程序，终端被彻底弄乱时也能用。
+pub fn reset() -> io::Result<()> {
+    #[cfg(unix)]
+    make_sane()?;
+
+    let mut stdout = io::stdout();
+    stdout.write_all(RESET_SEQUENCE)?;
+    stdout.flush()
+}
+
+/// 类似 `stty sane`：打开行缓冲、回显、信号和输出换行转换等常用标志
+#[cfg(unix)]
+fn make_sane() -> io::Result<()> {
+    use nix::sys::termios::{tcgetattr, tcsetattr, InputFlags, LocalFlags, OutputFlags, SetArg};
+    use nix::unistd::isatty;
+
+    let fd = stdin_fd();
+    if !isatty(fd).unwrap_or(false) {
+        return Ok(()); // stdin 不是终端，没有需要恢复的 termios
+    }
+
+    let mut termios = tcgetattr(fd)?;
+    termios.input_flags |= InputFlags::BRKINT | InputFlags::ICRNL | InputFlags::IXON;
+    termios.input_flags &= !(InputFlags::INLCR | InputFlags::IGNCR);
+    termios.output_flags |= OutputFlags::OPOST | OutputFlags::ONLCR;
+    termios.local_flags |= LocalFlags::ICANON
+        | LocalFlags::ECHO
+        | LocalFlags::ECHOE
+        | LocalFlags::ECHOK
+        | LocalFlags::ISIG
+        | LocalFlags::IEXTEN;
+    tcsetattr(fd, SetArg::TCSANOW, &termios)?;
+    Ok(())
+}
+
+/// 交互模式下打开作业控制：Shell 自己成为一个进程组并占有终端，
+/// 之后每条前台管道在自己的进程组中运行，终端的 Ctrl-C 只发给它们，不会杀死 Shell。
+/// Shell 在执行 fg 等内置命令时仍可能在前台，所以还要忽略 SIGINT、SIGQUIT 和 SIGTSTP。
+/// 这里用空的信号处理函数而不是 SIG_IGN：被忽略的信号会在 exec 后保持忽略，处理函数则会恢复默认。
+/// stdin 不是终端或者 Shell 不在前台时不打开，返回 false。
+#[cfg(unix)]
+pub fn init_job_control() -> bool {
+    use nix::sys::signal::{SaFlags, SigAction, SigHandler, SigSet, Signal, sigaction};
+    use nix::unistd::{Pid, getpgrp, isatty, setpgid, tcgetpgrp};
+
+    extern "C" fn ignore(_: nix::libc::c_int) {}
+
+    let fd = stdin_fd();
+    if !isatty(fd).unwrap_or(false) || tcgetpgrp(fd) != Ok(getpgrp()) {
+        return false;
+    }
+    let action = SigAction::new(SigHandler::Handler(ignore), SaFlags::SA_RESTART, SigSet::empty());
+    for signal in [Signal::SIGINT, Signal::SIGQUIT, Signal::SIGTSTP] {
+        // SAFETY: 处理函数什么也不做，是异步信号安全的
+        let _ = unsafe { sigaction(signal, &action) };
+    }
+    // 已经是进程组组长（例如会话组长）时 setpgid 会失败，这时直接使用原来的进程组
+    let _ = setpgid(Pid::from_raw(0), Pid::from_raw(0));
+    reclaim_terminal();
+    true
+}
+
+/// 把终端交给进程组 `pgid`，终端产生的 Ctrl-C、Ctrl-Z 之后只发给这个进程组。
+/// 进程组可能在拿到终端之前就读了终端而被 SIGTTIN 停住，所以再发一个 SIGCONT。
+/// 没有终端时不会有 SIGTTIN，也就不发 SIGCONT，否则刚启动就自己停止的进程会被意外地继续运行。
+#[cfg(unix)]
+pub fn give_terminal_to(pgid: u32) {
+    use nix::sys::signal::{Signal, killpg};
+    use nix::unistd::Pid;
+
+    let pgid = Pid::from_raw(pgid as i32);
+    if set_foreground_group(pgid) {
+        let _ = killpg(pgid, Signal::SIGCONT);
+    }
+}
+
+/// 前台命令结束或停止后，Shell 收回终端
+#[cfg(unix)]
+pub fn reclaim_terminal() {
+    let _ = set_foreground_group(nix::unistd::getpgrp());
+}
+
+/// Shell 不在前台时调用 tcsetpgrp 会收到 SIGTTOU 而被停住，所以调用期间先屏蔽它。
+/// 返回是否成功，stdin 不是终端时失败
+#[cfg(unix)]
+fn set_foreground_group(pgid: nix::unistd::Pid) -> bool {
+    use nix::sys::signal::{SigSet, SigmaskHow, Signal, pthread_sigmask};
+    use nix::unistd::tcsetpgrp;
+
+    let mut block = SigSet::empty();
+    block.add(Signal::SIGTTOU);
+    let mut saved = SigSet::empty();
+    let _ = pthread_sigmask(SigmaskHow::SIG_BLOCK, Some(&block), Some(&mut saved));
+    let changed = tcsetpgrp(stdin_fd(), pgid).is_ok();
+    let _ = pthread_sigmask(SigmaskHow::SIG_SETMASK, Some(&saved), None);
+    changed
+}
+
+#[cfg(unix)]
+fn stdin_fd() -> std::os::unix::io::RawFd {
+    use std::os::unix::io::AsRawFd;
+    std::io::stdin().as_raw_fd()
+}
+
+/// 非 Unix 平台没有进程组，不支持作业控制
+#[cfg(not(unix))]
+pub fn init_job_control() -> bool {
+    false
+}
+
+#[cfg(not(unix))]
+pub fn give_terminal_to(_pgid: u32) {}
+
+#[cfg(not(unix))]
+pub fn reclaim_terminal() {}
+
+/// 非 Unix 平台没有 termios，保存和恢复都是空操作
+#[cfg(not(unix))]
+pub struct TerminalState;
+
+#[cfg(not(unix))]
+impl TerminalState {
+    pub fn save() -> TerminalState {
+        TerminalState
+    }
+
+    pub fn restore(&self) {}
+}
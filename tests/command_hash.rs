@@ -0,0 +1,176 @@
+//! 命令路径缓存 (hash) 的测试
+
+mod common;
+
+use std::fs;
+
+use common::{TempDir, lock_env, output_of};
+use my_shell::builtins::BUILTINS;
+use my_shell::command_hash::CommandHash;
+use my_shell::shell::Shell;
+
+#[cfg(unix)]
+fn make_executable(path: &std::path::Path) {
+    use std::os::unix::fs::PermissionsExt;
+    fs::write(path, "#!/bin/sh\necho hashed\n").unwrap();
+    fs::set_permissions(path, fs::Permissions::from_mode(0o755)).unwrap();
+}
+
+#[test]
+fn resolves_and_counts_hits() {
+    let mut hash = CommandHash::new();
+
+    let first = hash.resolve_in("sh", "/usr/bin:/bin").unwrap();
+    let second = hash.resolve_in("sh", "/usr/bin:/bin").unwrap();
+
+    assert_eq!(first, second);
+    assert_eq!(hash.entries(), vec![("sh", first.as_path(), 2)]);
+    assert!(hash.resolve_in("./sh", "/usr/bin:/bin").is_none());
+}
+
+#[cfg(unix)]
+#[test]
+fn path_change_makes_new_directory_visible() {
+    let tmp = TempDir::new("hash_path");
+    make_executable(&tmp.path().join("my_hash_tool"));
+    let mut hash = CommandHash::new();
+
+    assert!(hash.resolve_in("my_hash_tool", "/usr/bin:/bin").is_none());
+    hash.resolve_in("sh", "/usr/bin:/bin").unwrap();
+
+    let extended = format!("{}:/usr/bin:/bin", tmp.path().display());
+    let found = hash.resolve_in("my_hash_tool", &extended).unwrap();
+
+    assert_eq!(found, tmp.path().join("my_hash_tool"));
+    // PATH 改变后旧的缓存被丢弃
+    assert_eq!(hash.entries().len(), 1);
+}
+
+#[cfg(unix)]
+#[test]
+fn removed_executable_is_searched_again() {
+    let tmp = TempDir::new("hash_removed");
+    let first_dir = tmp.path().join("first");
+    let second_dir = tmp.path().join("second");
+    fs::create_dir_all(&first_dir).unwrap();
+    fs::create_dir_all(&second_dir).unwrap();
+    make_executable(&first_dir.join("tool"));
+    make_executable(&second_dir.join("tool"));
+    let path_var = format!("{}:{}", first_dir.display(), second_dir.display());
+    let mut hash = CommandHash::new();
+
+    assert_eq!(hash.resolve_in("tool", &path_var).unwrap(), first_dir.join("tool"));
+    fs::remove_file(first_dir.join("tool")).unwrap();
+
+    assert_eq!(hash.resolve_in("tool", &path_var).unwrap(), second_dir.join("tool"));
+}
+
+#[test]
+fn hash_builtin_remembers_and_forgets() {
+    let mut shell = Shell::new();
+
+    shell.run_line("true");
+    assert_eq!(shell.command_hash.entries().len(), 1);
+
+    shell.run_line("hash -r");
+    assert!(shell.command_hash.entries().is_empty());
+
+    shell.run_line("hash sh definitely-not-a-command-xyz");
+    assert_eq!(shell.last_status, 1);
+    assert_eq!(shell.command_hash.entries()[0].0, "sh");
+}
+
+#[cfg(unix)]
+#[test]
+fn which_prints_first_executable_on_path() {
+    let tmp = TempDir::new("which");
+    let mut shell = Shell::new();
+
+    let paths = output_of(&mut shell, "which sh cat");
+    assert_eq!(shell.last_status, 0);
+    let paths: Vec<&str> = paths.lines().collect();
+    assert_eq!(paths.len(), 2);
+    assert!(paths[0].starts_with('/') && paths[0].ends_with("/sh"), "{:?}", paths);
+    assert!(paths[1].ends_with("/cat"), "{:?}", paths);
+
+    // 找不到的名字不输出，但整体返回 1
+    assert_eq!(output_of(&mut shell, "which definitely-not-a-command-xyz sh").lines().count(), 1);
+    assert_eq!(shell.last_status, 1);
+
+    // 没有执行权限的文件不算
+    fs::write(tmp.path().join("plain"), "").unwrap();
+    shell.run_line(&format!("which {}/plain", tmp.path().display()));
+    assert_eq!(shell.last_status, 1);
+}
+
+#[cfg(unix)]
+#[test]
+fn type_reports_aliases_builtins_and_files() {
+    let mut shell = Shell::new();
+
+    shell.run_line("alias ll='ls -la'");
+    let report = output_of(&mut shell, "type ll cd sh");
+    assert_eq!(shell.last_status, 0);
+    let lines: Vec<&str> = report.lines().collect();
+    assert_eq!(lines[..2], ["ll is aliased to `ls -la'", "cd is a shell builtin"]);
+    assert!(lines[2].starts_with("sh is /") && lines[2].ends_with("/sh"), "{:?}", lines);
+
+    assert_eq!(output_of(&mut shell, "type definitely-not-a-command-xyz type"), "type is a shell builtin\n");
+    assert_eq!(shell.last_status, 1);
+}
+
+#[test]
+fn every_registered_builtin_is_recognized() {
+    assert!(BUILTINS.windows(2).all(|pair| pair[0] < pair[1]), "内置命令表必须按名字排序");
+
+    let mut shell = Shell::new();
+
+    let expected: String = BUILTINS.iter().map(|name| format!("{} is a shell builtin\n", name)).collect();
+    assert_eq!(output_of(&mut shell, &format!("type {}", BUILTINS.join(" "))), expected);
+    assert_eq!(shell.last_status, 0);
+}
+
+#[cfg(unix)]
+#[test]
+fn path_prefix_assignment_is_used_to_find_the_command() {
+    let tmp = TempDir::new("path_prefix");
+    let bin = tmp.path().join("bin");
+    fs::create_dir(&bin).unwrap();
+    make_executable(&bin.join("ls"));
+    let mut shell = Shell::new();
+
+    // 先让缓存记住系统的 ls，再用命令前的 PATH 赋值换成另一个目录
+    shell.run_line("ls / > /dev/null");
+
+    assert_eq!(output_of(&mut shell, &format!("PATH={} ls", bin.display())), "hashed\n");
+    // 临时的 PATH 不影响缓存
+    let entries = shell.command_hash.entries();
+    assert!(entries.iter().all(|(_, path, _)| !path.starts_with(&bin)), "{:?}", entries);
+}
+
+#[cfg(unix)]
+#[test]
+fn changing_path_makes_new_commands_visible() {
+    let _env = lock_env();
+    let tmp = TempDir::new("path_change");
+    let exported = tmp.path().join("exported");
+    let assigned = tmp.path().join("assigned");
+    fs::create_dir(&exported).unwrap();
+    fs::create_dir(&assigned).unwrap();
+    make_executable(&exported.join("my_exported_tool"));
+    make_executable(&assigned.join("my_assigned_tool"));
+    let mut shell = Shell::new();
+
+    shell.run_line("saved_path=$PATH; my_exported_tool");
+    assert_eq!(shell.last_status, 127);
+
+    let line = format!("export PATH={}:$PATH; my_exported_tool", exported.display());
+    assert_eq!(output_of(&mut shell, &line), "hashed\n");
+
+    // PATH 已经在环境中，普通赋值同样更新它
+    let line = format!("PATH={}:$PATH; my_assigned_tool", assigned.display());
+    assert_eq!(output_of(&mut shell, &line), "hashed\n");
+
+    shell.run_line("PATH=$saved_path; my_assigned_tool");
+    assert_eq!(shell.last_status, 127);
+}
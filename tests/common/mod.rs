@@ -0,0 +1,58 @@
+//! 集成测试共用的辅助工具
+// 每个测试文件只用到其中一部分辅助函数
+#![allow(dead_code)]
+
+use std::fs;
+use std::path::Path;
+use std::sync::{Mutex, MutexGuard};
+
+use my_shell::shell::Shell;
+
+static CWD_LOCK: Mutex<()> = Mutex::new(());
+static ENV_LOCK: Mutex<()> = Mutex::new(());
+
+/// 测试用的临时目录，由 tempfile 创建，离开作用域时自动删除
+pub struct TempDir {
+    dir: tempfile::TempDir,
+}
+
+impl TempDir {
+    /// `name` 只作为目录名的前缀，方便在测试失败时认出是哪个测试留下的文件
+    pub fn new(name: &str) -> TempDir {
+        let dir = tempfile::Builder::new()
+            .prefix(&format!("my_shell_{}_", name))
+            .tempdir()
+            .expect("无法创建临时目录");
+        TempDir { dir }
+    }
+
+    pub fn path(&self) -> &Path {
+        self.dir.path()
+    }
+
+    /// 返回临时目录下某个文件的路径字符串，方便拼进命令行
+    pub fn file(&self, name: &str) -> String {
+        self.path().join(name).display().to_string()
+    }
+}
+
+/// 当前工作目录是进程级状态，修改它的测试需要串行执行
+pub fn lock_cwd() -> MutexGuard<'static, ()> {
+    CWD_LOCK.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// 环境变量也是进程级状态：export、unset、cd（更新 PWD 和 OLDPWD）以及直接调用 set_var 的测试需要串行执行。
+/// 同时需要两把锁时先取 lock_cwd，再取 lock_env
+pub fn lock_env() -> MutexGuard<'static, ()> {
+    ENV_LOCK.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// 执行一行命令，返回它写到标准输出的内容。
+/// 重定向直接加在命令行末尾，和手写 `line > file` 一样只作用于最后一条管道；
+/// 命令行在重定向之前就中止时返回空字符串
+pub fn output_of(shell: &mut Shell, line: &str) -> String {
+    let tmp = TempDir::new("output");
+    let out = tmp.file("out.txt");
+    shell.run_line(&format!("{} > {}", line, out));
+    fs::read_to_string(&out).unwrap_or_default()
+}
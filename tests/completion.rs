@@ -0,0 +1,148 @@
+//! 补全的测试：长选项抓取、命令名和变量名补全
+
+mod common;
+
+use std::fs;
+
+use common::TempDir;
+use my_shell::completion::{
+    CommandNameCache, HelpOptionCache, command_word_span, complete_variable, edit_distance, is_command_position, scrape_long_options,
+    segment_command,
+};
+use my_shell::shell::Shell;
+
+#[test]
+fn scrapes_long_options_from_help_text() {
+    let help = "Usage: tool [OPTION]...\n  -a, --all            show all\n      --color[=WHEN]   colorize\n  --block-size=SIZE\n  not--an-option\n  --all again\n";
+
+    assert_eq!(scrape_long_options(help), vec!["--all", "--block-size", "--color"]);
+}
+
+#[test]
+fn caches_options_from_real_command() {
+    let cache = HelpOptionCache::new();
+
+    let options = cache.options_for("ls");
+    assert!(options.iter().any(|option| option == "--help"));
+    assert_eq!(cache.options_for("ls"), options);
+    assert!(cache.options_for("definitely-not-a-command-xyz").is_empty());
+}
+
+#[test]
+fn command_names_merge_builtins_and_path_executables() {
+    let first = TempDir::new("complete_a");
+    let second = TempDir::new("complete_b");
+    for (dir, name) in [(&first, "mytool"), (&first, "myecho"), (&second, "mytool"), (&second, "myplain")] {
+        fs::write(dir.file(name), "#!/bin/sh\n").unwrap();
+        #[cfg(unix)]
+        if name != "myplain" {
+            use std::os::unix::fs::PermissionsExt;
+            fs::set_permissions(dir.file(name), fs::Permissions::from_mode(0o755)).unwrap();
+        }
+    }
+    let path_var = format!("{}:{}", first.file(""), second.file(""));
+    let cache = CommandNameCache::new();
+
+    assert_eq!(cache.complete_in("my", &["myecho", "cd"], &path_var), vec!["myecho", "mytool"]);
+    assert_eq!(cache.complete_in("c", &["myecho", "cd"], &path_var), vec!["cd"]);
+    assert_eq!(cache.complete_in("my", &[], &second.file("")), vec!["mytool"]);
+    assert!(cache.contains_in("mytool", &path_var));
+    assert!(!cache.contains_in("myplain", &path_var));
+    assert!(!cache.contains_in("my", &path_var));
+}
+
+#[test]
+fn misspelled_commands_get_the_closest_suggestion() {
+    assert_eq!(edit_distance("gti", "git", 2), Some(1));
+    assert_eq!(edit_distance("ehco", "chcon", 2), Some(2));
+    assert_eq!(edit_distance("kitten", "sitting", 3), Some(3));
+    assert_eq!(edit_distance("kitten", "sitting", 2), None);
+    assert_eq!(edit_distance("", "ab", 2), Some(2));
+
+    let dir = TempDir::new("suggest");
+    for name in ["git", "grep", "gzip"] {
+        fs::write(dir.file(name), "#!/bin/sh\n").unwrap();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            fs::set_permissions(dir.file(name), fs::Permissions::from_mode(0o755)).unwrap();
+        }
+    }
+    let path_var = dir.file("");
+    let cache = CommandNameCache::new();
+
+    assert_eq!(cache.closest_in("gti", &["echo"], &path_var).as_deref(), Some("git"));
+    assert_eq!(cache.closest_in("grpe", &["echo"], &path_var).as_deref(), Some("grep"));
+    assert_eq!(cache.closest_in("ehco", &["echo"], &path_var).as_deref(), Some("echo"));
+    assert_eq!(cache.closest_in("gitt", &["echo"], &path_var).as_deref(), Some("git"));
+    // 差得太远或者名字太短时不给建议
+    assert_eq!(cache.closest_in("python", &["echo"], &path_var), None);
+    assert_eq!(cache.closest_in("gx", &["echo"], &path_var), None);
+}
+
+#[test]
+fn dollar_prefix_completes_variable_names() {
+    let names: Vec<String> = ["HOSTNAME", "HOME", "PATH", "HOME", "my_var"].iter().map(|s| s.to_string()).collect();
+
+    assert_eq!(complete_variable("echo $HO", 8, &names), Some((6, vec!["HOME".to_string(), "HOSTNAME".to_string()])));
+    assert_eq!(complete_variable("echo ${HO", 9, &names), Some((7, vec!["HOME}".to_string(), "HOSTNAME}".to_string()])));
+    assert_eq!(complete_variable("cd a$my", 7, &names), Some((5, vec!["my_var".to_string()])));
+    assert_eq!(complete_variable("echo $", 6, &names).map(|(_, c)| c.len()), Some(4));
+    // 光标在单词中间时只看光标之前的部分
+    assert_eq!(complete_variable("echo $PAxx", 8, &names), Some((6, vec!["PATH".to_string()])));
+
+    assert_eq!(complete_variable("echo HO", 7, &names), None);
+    assert_eq!(complete_variable(r"echo \$HO", 9, &names), None);
+}
+
+#[test]
+fn aliases_are_completed_as_soon_as_they_are_defined() {
+    let mut shell = Shell::new();
+    assert!(!shell.complete_command_name("gs").contains(&"gsq".to_string()));
+
+    shell.run_line("alias gsq='git status' ech0='echo'");
+    let candidates = shell.complete_command_name("gs");
+    assert!(candidates.contains(&"gsq".to_string()));
+    assert_eq!(candidates.iter().filter(|name| *name == "gsq").count(), 1);
+    assert_eq!(shell.complete_command_name("ech")[..2], ["ech0".to_string(), "echo".to_string()]);
+
+    shell.run_line("unalias gsq");
+    assert!(!shell.complete_command_name("gs").contains(&"gsq".to_string()));
+}
+
+#[test]
+fn command_position_is_first_word_of_each_segment() {
+    assert!(is_command_position("ex", 0));
+    assert!(is_command_position("ls | gr", 5));
+    assert!(is_command_position("true && ! ec", 10));
+    assert!(is_command_position("time ! ec", 7));
+    assert!(!is_command_position("timeout 5 ec", 10));
+    assert!(!is_command_position("ls sr", 3));
+    assert!(!is_command_position("cat x | grep pa", 13));
+}
+
+#[test]
+fn segment_command_is_the_command_of_the_current_segment() {
+    assert_eq!(segment_command("ls --co", 3), Some("ls"));
+    assert_eq!(segment_command("cd /tmp; ls --co", 12), Some("ls"));
+    assert_eq!(segment_command("sleep 1 & grep -i --co", 18), Some("grep"));
+    assert_eq!(segment_command("true && ! time grep --co", 20), Some("grep"));
+    assert_eq!(segment_command("cat x | sort --re", 13), Some("sort"));
+    assert_eq!(segment_command("ls; --co", 4), None);
+}
+
+#[test]
+fn command_word_skips_leading_spaces_and_assignments() {
+    fn word(line: &str) -> Option<&str> {
+        command_word_span(line).map(|span| &line[span])
+    }
+
+    assert_eq!(word("ls -l"), Some("ls"));
+    assert_eq!(word("  grep x"), Some("grep"));
+    assert_eq!(word("A=1 B=\"2\" env"), Some("env"));
+    assert_eq!(word("cat|wc"), Some("cat"));
+    assert_eq!(word("./run.sh>out"), Some("./run.sh"));
+    assert_eq!(word("A=1"), None);
+    assert_eq!(word("   "), None);
+    assert_eq!(word(""), None);
+}
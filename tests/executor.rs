@@ -0,0 +1,51 @@
+//! 执行器的测试：管道的退出状态
+
+use my_shell::command_hash::CommandHash;
+use my_shell::executor::{PipelineResult, execute_pipeline};
+use my_shell::parser::{expand_variables, parse_pipeline_commands};
+
+/// 解析并执行一条管道（只去掉引号，不展开变量），返回各阶段的退出状态
+fn run(line: &str) -> PipelineResult {
+    let mut commands = parse_pipeline_commands(line).unwrap();
+    for cmd in commands.iter_mut() {
+        cmd.args = cmd.args.iter().map(|arg| expand_variables(arg, &mut |_| None)).collect();
+    }
+    execute_pipeline(&commands, &mut CommandHash::new())
+}
+
+/// 管道的退出状态
+fn status_of(line: &str) -> i32 {
+    run(line).last
+}
+
+#[test]
+fn pipeline_status_is_the_last_command_status() {
+    assert_eq!(status_of("true"), 0);
+    assert_eq!(status_of("sh -c 'exit 3'"), 3);
+    assert_eq!(status_of("false | true"), 0);
+    assert_eq!(status_of("true | sh -c 'exit 5'"), 5);
+}
+
+#[test]
+fn spawn_failures_and_signals_use_conventional_codes() {
+    assert_eq!(status_of("my_shell_no_such_command_xyz"), 127);
+    assert_eq!(status_of("echo hi | my_shell_no_such_command_xyz"), 127);
+    #[cfg(unix)]
+    assert_eq!(status_of("sh -c 'kill -9 $$'"), 128 + 9);
+}
+
+#[test]
+fn every_stage_status_is_recorded_in_order() {
+    assert_eq!(run("false | sh -c 'exit 4' | true").statuses, vec![1, 4, 0]);
+    assert_eq!(run("true | false"), PipelineResult { statuses: vec![0, 1], last: 1 });
+    #[cfg(unix)]
+    assert_eq!(run("sh -c 'kill -TERM $$' | sh -c 'exit 2'").statuses, vec![128 + 15, 2]);
+
+    // 无法启动的阶段记录为 127，其他阶段照常执行：后一个阶段读到空的输入，前一个阶段的输出被丢弃
+    assert_eq!(run("true | my_shell_no_such_command_xyz | echo hi"), PipelineResult { statuses: vec![0, 127, 0], last: 0 });
+    // 写入已经关闭的管道的 echo 可能在写之前结束，也可能被 SIGPIPE 结束
+    let failed = run("echo hi | my_shell_no_such_command_xyz");
+    assert_eq!(failed.last, 127);
+    assert!(matches!(failed.statuses[..], [0 | 141, 127]), "{:?}", failed.statuses);
+    assert_eq!(run("my_shell_no_such_command_xyz | cat").statuses, vec![127, 0]);
+}
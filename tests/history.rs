@@ -0,0 +1,38 @@
+//! 历史展开的测试：!!、!n、!-n 和 !prefix
+
+use my_shell::history::expand_history;
+
+fn entries() -> Vec<String> {
+    ["ls -l", "echo hello", "cd /tmp", "echo world"].iter().map(|s| s.to_string()).collect()
+}
+
+#[test]
+fn events_refer_to_previous_commands() {
+    let entries = entries();
+    let expand = |line: &str| expand_history(line, &entries);
+
+    assert_eq!(expand("!!"), Ok(Some("echo world".to_string())));
+    assert_eq!(expand("!! | wc -c"), Ok(Some("echo world | wc -c".to_string())));
+    assert_eq!(expand("!1"), Ok(Some("ls -l".to_string())));
+    assert_eq!(expand("!-2"), Ok(Some("cd /tmp".to_string())));
+    assert_eq!(expand("!ec"), Ok(Some("echo world".to_string())));
+    assert_eq!(expand("sudo !ls; !cd"), Ok(Some("sudo ls -l; cd /tmp".to_string())));
+}
+
+#[test]
+fn missing_events_are_reported() {
+    let entries = entries();
+
+    assert_eq!(expand_history("!42", &entries), Err("!42".to_string()));
+    assert_eq!(expand_history("!nosuch arg", &entries), Err("!nosuch".to_string()));
+    assert_eq!(expand_history("!!", &[]), Err("!!".to_string()));
+}
+
+#[test]
+fn bang_without_an_event_is_left_alone() {
+    let entries = entries();
+
+    for line in ["! false", "[ 1 != 2 ]", "echo hi!", r"echo \!!", "echo '!!'", "echo $!", "echo ${!ref}"] {
+        assert_eq!(expand_history(line, &entries), Ok(None), "{}", line);
+    }
+}
@@ -0,0 +1,659 @@
+//! 解析器的测试：单词切分、引号和重定向
+
+use std::collections::HashMap;
+
+use my_shell::parser::{
+    CaseTerminator, Connector, Incomplete, OutputOperation, ParsedCommand, evaluate_arithmetic, expand_aliases, expand_braces, expand_tilde, expand_variables, incomplete_line, input_complete,
+    parse_command_list, parse_pipeline_commands, parse_single_command, pattern_matches, remove_quotes, strip_comment, tokenize,
+};
+
+/// 切分单词并去掉引号（不展开任何变量），得到命令最终看到的参数
+fn words(line: &str) -> Vec<String> {
+    tokenize(line)
+        .unwrap()
+        .iter()
+        .map(|token| expand_variables(token, &mut |_| None))
+        .collect()
+}
+
+#[test]
+fn double_quotes_keep_spaces_in_one_argument() {
+    assert_eq!(words(r#"echo "hello world"  x"#), vec!["echo", "hello world", "x"]);
+}
+
+#[test]
+fn single_quotes_are_literal() {
+    assert_eq!(words(r#"echo '$HOME "quoted" \n'"#), vec!["echo", r#"$HOME "quoted" \n"#]);
+}
+
+#[test]
+fn quotes_concatenate_mid_token() {
+    assert_eq!(words(r#"a"b c"d 'e'"f"g"#), vec!["ab cd", "efg"]);
+    assert_eq!(words(r#"echo "" ''"#), vec!["echo", "", ""]);
+}
+
+#[test]
+fn double_quotes_still_allow_expansion() {
+    let tokens = tokenize(r#""$NAME is ${NAME}" '$NAME'"#).unwrap();
+    let mut lookup = |name: &str| (name == "NAME").then(|| "bob".to_string());
+    let expanded: Vec<String> = tokens.iter().map(|t| expand_variables(t, &mut lookup)).collect();
+
+    assert_eq!(expanded, vec!["bob is bob", "$NAME"]);
+}
+
+#[test]
+fn closing_quote_ends_the_variable_name() {
+    let tokens = tokenize(r#""$NAME"x "$NAME"_1 "$1"2 "cost: $"5 "a$""#).unwrap();
+    let mut lookup = |name: &str| match name {
+        "NAME" => Some("bob".to_string()),
+        "1" => Some("one".to_string()),
+        _ => None,
+    };
+    let expanded: Vec<String> = tokens.iter().map(|t| expand_variables(t, &mut lookup)).collect();
+
+    assert_eq!(expanded, vec!["bobx", "bob_1", "one2", "cost: $5", "a$"]);
+}
+
+#[test]
+fn default_value_modifiers_treat_empty_and_unset_alike() {
+    let expand = |word: &str| {
+        let mut lookup = |name: &str| match name {
+            "SET" => Some("val".to_string()),
+            "EMPTY" => Some(String::new()),
+            _ => None,
+        };
+        tokenize(word).unwrap().iter().map(|t| expand_variables(t, &mut lookup)).collect::<Vec<_>>().join("|")
+    };
+
+    assert_eq!(expand("${SET:-d} ${EMPTY:-d} ${UNSET:-d}"), "val|d|d");
+    assert_eq!(expand("${SET:=d} ${EMPTY:=d} ${UNSET:=d}"), "val|d|d");
+    assert_eq!(expand("[${SET:+alt}] [${EMPTY:+alt}] [${UNSET:+alt}]"), "[alt]|[]|[]");
+    assert_eq!(expand("${SET:?msg} [${EMPTY:?msg}] [${UNSET:?}]"), "val|[]|[]");
+
+    // word 中可以有空格、引号和其他展开，没有用到的 word 不展开
+    assert_eq!(expand(r#"${UNSET:-a "b c" $SET ${UNSET:-${SET}x}} "${UNSET:-'q'}""#), "a b c val valx|'q'");
+    assert_eq!(expand("${SET:-$(never)} ${UNSET}"), "val|${UNSET}");
+}
+
+#[test]
+fn unterminated_quotes_are_errors() {
+    assert!(tokenize(r#"echo "open"#).unwrap_err().contains("双引号"));
+    assert!(tokenize("echo 'open").unwrap_err().contains("单引号"));
+    assert!(parse_single_command("echo 'open").is_err());
+}
+
+#[test]
+fn quoted_operators_are_arguments() {
+    let cmd = parse_single_command(r#"echo ">" "<" out"#).unwrap();
+
+    assert_eq!(cmd.args.len(), 3);
+    assert!(cmd.stdout_redirect.is_none());
+    assert!(cmd.stdin_redirect.is_none());
+}
+
+#[test]
+fn backslash_escapes_spaces_and_quotes() {
+    assert_eq!(words(r"touch my\ file.txt"), vec!["touch", "my file.txt"]);
+    assert_eq!(words(r#"echo \"hi\" \'x\' a\\b"#), vec!["echo", "\"hi\"", "'x'", r"a\b"]);
+    assert_eq!(words(r#"echo "a \"b\" \$c \d""#), vec!["echo", r#"a "b" $c \d"#]);
+}
+
+#[test]
+fn backslash_escapes_redirection_operators() {
+    let cmd = parse_single_command(r"echo \> out \< in 2\> err").unwrap();
+
+    assert!(cmd.stdout_redirect.is_none());
+    assert!(cmd.stdin_redirect.is_none());
+    assert!(cmd.stderr_redirect.is_none());
+    let args: Vec<String> = cmd.args.iter().map(|a| expand_variables(a, &mut |_| None)).collect();
+    assert_eq!(args, vec![">", "out", "<", "in", "2>", "err"]);
+}
+
+#[test]
+fn backslash_escapes_pipe() {
+    let commands = parse_pipeline_commands(r"echo a\|b | cat").unwrap();
+
+    assert_eq!(commands.len(), 2);
+    assert_eq!(expand_variables(&commands[0].args[0], &mut |_| None), "a|b");
+}
+
+#[test]
+fn pipes_inside_quotes_do_not_split_the_pipeline() {
+    let args = |command: &ParsedCommand| -> Vec<String> { command.args.iter().map(|a| expand_variables(a, &mut |_| None)).collect() };
+
+    let double = parse_pipeline_commands(r#"echo "a|b" | cat"#).unwrap();
+    assert_eq!(double.len(), 2);
+    assert_eq!(args(&double[0]), vec!["a|b"]);
+
+    let single = parse_pipeline_commands("grep '|' file").unwrap();
+    assert_eq!(single.len(), 1);
+    assert_eq!(args(&single[0]), vec!["|", "file"]);
+
+    let escaped = parse_pipeline_commands(r"echo a \| b").unwrap();
+    assert_eq!(escaped.len(), 1);
+    assert_eq!(args(&escaped[0]), vec!["a", "|", "b"]);
+
+    // 双引号中被转义的引号不会提前结束引号
+    let nested = parse_pipeline_commands(r#"echo "x \"|\" y" 'p|q' | wc -c > "o|ut""#).unwrap();
+    assert_eq!(nested.len(), 2);
+    assert_eq!(args(&nested[0]), vec![r#"x "|" y"#, "p|q"]);
+    assert_eq!(expand_variables(&nested[1].stdout_redirect.clone().unwrap().0, &mut |_| None), "o|ut");
+}
+
+#[test]
+fn trailing_backslash_is_an_error() {
+    assert!(tokenize(r"echo abc \").is_err());
+}
+
+#[test]
+fn tilde_expands_only_at_word_start() {
+    let expand = |line: &str| -> Vec<String> {
+        tokenize(line)
+            .unwrap()
+            .iter()
+            .map(|token| expand_variables(&expand_tilde(token, "/home/u"), &mut |_| None))
+            .collect()
+    };
+
+    assert_eq!(
+        expand(r#"ls ~ ~/notes.txt foo~bar ~x "~" '~/a' \~"#),
+        vec!["ls", "/home/u", "/home/u/notes.txt", "foo~bar", "~x", "~", "~/a", "~"]
+    );
+    assert_eq!(expand_variables(&expand_tilde("~/x", "/a $b"), &mut |_| None), "/a $b/x");
+}
+
+#[test]
+fn tilde_user_expands_to_that_users_home() {
+    let expand = |word: &str| expand_variables(&expand_tilde(word, "/home/u"), &mut |_| None);
+
+    assert_eq!(expand("~"), "/home/u");
+    assert_eq!(expand("~/path"), "/home/u/path");
+    assert_eq!(expand("~nonexistentuser"), "~nonexistentuser");
+    assert_eq!(expand("~nonexistentuser/x"), "~nonexistentuser/x");
+    #[cfg(target_os = "linux")]
+    {
+        assert_eq!(expand("~root"), "/root");
+        assert_eq!(expand("~root/bin"), "/root/bin");
+    }
+}
+
+#[test]
+fn trailing_ampersand_marks_pipeline_as_background() {
+    let commands = parse_pipeline_commands("sleep 10 | cat &").unwrap();
+    assert!(commands.iter().all(|cmd| cmd.background));
+    assert_eq!(commands[1].args, Vec::<String>::new());
+
+    let glued = parse_pipeline_commands("sleep 10&").unwrap();
+    assert!(glued[0].background);
+    assert_eq!(glued[0].args, vec!["10"]);
+
+    let escaped = parse_pipeline_commands(r"echo a \&").unwrap();
+    assert!(!escaped[0].background);
+    assert_eq!(escaped[0].args.len(), 2);
+}
+
+#[test]
+fn command_substitution_stays_one_token() {
+    let tokens = tokenize(r#"echo $(ls -l | wc -l) "$(date +%s)" `uname -a` x$(echo "(a)")"#).unwrap();
+
+    assert_eq!(tokens, vec!["echo", "$(ls -l | wc -l)", "\"$(date +%s)\"", "$(uname -a)", "x$(echo \"(a)\")"]);
+    assert!(tokenize("echo $(ls").is_err());
+    assert_eq!(parse_pipeline_commands("echo $(ls | wc -l) | cat").unwrap().len(), 2);
+}
+
+#[test]
+fn process_substitution_stays_one_token() {
+    let tokens = tokenize("diff <(sort a | uniq) >(tee x) '<(q)' x<(y)").unwrap();
+    assert_eq!(tokens[1..3], ["<(sort a | uniq)", ">(tee x)"]);
+    assert_eq!(tokens.len(), 5);
+    assert!(tokenize("cat <(ls").is_err());
+
+    let commands = parse_pipeline_commands("paste <(ls | head) - | cat < <(echo a; echo b) > >(wc -l)").unwrap();
+    assert_eq!(commands.len(), 2);
+    assert_eq!(commands[0].args, vec!["<(ls | head)", "-"]);
+    assert_eq!(commands[1].stdin_redirect.as_deref(), Some("<(echo a; echo b)"));
+    assert_eq!(commands[1].stdout_redirect, Some((">(wc -l)".to_string(), false)));
+    assert_eq!(strip_comment("cat <(echo #x) # y"), "cat <(echo #x) ");
+}
+
+#[test]
+fn and_or_lists_bind_looser_than_pipes() {
+    let items = parse_command_list("ls | wc -l && echo ok || ! echo failed").unwrap();
+
+    let connectors: Vec<Connector> = items.iter().map(|item| item.connector).collect();
+    assert_eq!(connectors, vec![Connector::Always, Connector::And, Connector::Or]);
+    assert_eq!(items[0].commands.len(), 2);
+    assert!(items[2].negated);
+
+    assert_eq!(parse_command_list(r#"echo "a && b" 'c && d' e\&\& $(true && false)"#).unwrap().len(), 1);
+    assert!(parse_command_list("&& ls").is_err());
+    assert!(parse_command_list("ls ||").is_err());
+}
+
+#[test]
+fn time_keyword_covers_the_whole_pipeline() {
+    let items = parse_command_list("time ls | wc -l; ! time false; time ! true; time; timeout 1 ls").unwrap();
+
+    let flags: Vec<(bool, bool)> = items.iter().map(|item| (item.timed, item.negated)).collect();
+    assert_eq!(flags, vec![(true, false), (true, true), (true, true), (false, false), (false, false)]);
+    assert_eq!(items[0].commands.len(), 2);
+    assert_eq!(items[0].commands[0].name, "ls");
+    // 后面没有命令的 time 是普通的命令名
+    assert_eq!(items[3].commands[0].name, "time");
+    assert_eq!(expand_aliases("time ll", &HashMap::from([("ll".to_string(), "ls -l".to_string())])), "time ls -l");
+}
+
+#[test]
+fn single_ampersand_ends_a_background_pipeline() {
+    let items = parse_command_list("sleep 1 & echo hi &>/dev/null & ls 2>&1 |& cat &").unwrap();
+
+    let names: Vec<&str> = items.iter().map(|item| item.commands[0].name.as_str()).collect();
+    assert_eq!(names, vec!["sleep", "echo", "ls"]);
+    let background: Vec<bool> = items.iter().map(|item| item.commands.last().unwrap().background).collect();
+    assert_eq!(background, vec![true, true, true]);
+    assert_eq!(items[0].commands[0].args, vec!["1"]);
+    assert_eq!(items[1].commands[0].args, vec!["hi"]);
+    assert_eq!(items[2].commands.len(), 2);
+
+    assert_eq!(parse_command_list(r#"echo a\& "b & c" 'd&e'"#).unwrap().len(), 1);
+    assert!(parse_command_list("& ls").is_err());
+    assert!(parse_command_list("echo a &; echo b").is_err());
+    assert!(parse_command_list("echo a & ; echo b").is_err());
+    assert_eq!(parse_command_list(r"echo a\&; echo b").unwrap().len(), 2);
+}
+
+#[test]
+fn semicolons_separate_pipelines_and_skip_empty_segments() {
+    let items = parse_command_list("; pwd ;; ls | wc -l ; echo done && true;").unwrap();
+
+    let names: Vec<&str> = items.iter().map(|item| item.commands[0].name.as_str()).collect();
+    assert_eq!(names, vec!["pwd", "ls", "echo", "true"]);
+    let connectors: Vec<Connector> = items.iter().map(|item| item.connector).collect();
+    assert_eq!(connectors, vec![Connector::Always, Connector::Always, Connector::Always, Connector::And]);
+    assert_eq!(items[1].commands.len(), 2);
+
+    assert_eq!(parse_command_list(r#"echo "a;b" 'c;d' e\;f"#).unwrap().len(), 1);
+    assert!(parse_command_list("ls && ; pwd").is_err());
+}
+
+#[test]
+fn stderr_duplication_records_order() {
+    use OutputOperation::*;
+
+    let after = parse_single_command("cmd > out 2>&1").unwrap();
+    assert_eq!(after.output_operations, vec![StdoutToFile, Duplicate { fd: 2, target: 1 }]);
+    assert!(after.args.is_empty());
+
+    let before = parse_single_command("cmd 2>&1 > out").unwrap();
+    assert_eq!(before.output_operations, vec![Duplicate { fd: 2, target: 1 }, StdoutToFile]);
+    assert_eq!(before.to_string(), "cmd 2>&1 > out");
+
+    // 后出现的 stderr 重定向覆盖前面的
+    let overridden = parse_single_command("cmd 2>&1 2> err").unwrap();
+    assert_eq!(overridden.output_operations, vec![Duplicate { fd: 2, target: 1 }, StderrToFile]);
+    assert_eq!(overridden.stderr_redirect, Some(("err".to_string(), false)));
+
+    // >&2 是 1>&2 的简写
+    let to_stderr = parse_single_command("echo hi >&2 2>err 1>&2").unwrap();
+    assert_eq!(to_stderr.args.len(), 1);
+    assert_eq!(to_stderr.output_operations, vec![Duplicate { fd: 1, target: 2 }, StderrToFile, Duplicate { fd: 1, target: 2 }]);
+    assert_eq!(to_stderr.to_string(), "echo hi 1>&2 2> err 1>&2");
+    assert!(parse_single_command("cmd 3>&1").is_err());
+    assert!(parse_single_command("cmd 2>&x").is_err());
+
+    let appended = parse_single_command("cmd 2>> err").unwrap();
+    assert_eq!(appended.stderr_redirect, Some(("err".to_string(), true)));
+    assert_eq!(appended.to_string(), "cmd 2>> err");
+    assert!(parse_single_command("cmd 2>>").is_err());
+}
+
+#[test]
+fn trace_line_quotes_expanded_words_and_omits_redirections() {
+    let mut cmd = parse_single_command(r#"A=1 B="x y" echo $HOME "it's" '' a*b > out"#).unwrap();
+    let vars = HashMap::from([("HOME".to_string(), "/home/u".to_string())]);
+    let expand = |word: &String| expand_variables(word, &mut |name| vars.get(name).cloned());
+    cmd.assignments = cmd.assignments.iter().map(|(name, value)| (name.clone(), expand(value))).collect();
+    cmd.args = cmd.args.iter().map(expand).collect();
+
+    assert_eq!(cmd.trace_line(), r#"A=1 B='x y' echo /home/u 'it'\''s' '' 'a*b'"#);
+    assert_eq!(parse_single_command("X=1").unwrap().trace_line(), "X=1");
+}
+
+#[test]
+fn ampersand_redirect_sends_both_streams_to_file() {
+    let truncate = parse_single_command("cmd &> out").unwrap();
+    assert_eq!(truncate.stdout_redirect, Some(("out".to_string(), false)));
+    assert_eq!(truncate.output_operations, vec![OutputOperation::StdoutToFile, OutputOperation::Duplicate { fd: 2, target: 1 }]);
+
+    // 和 bash 一样 err 仍会被创建，但 stderr 最终跟随 stdout 写入 log
+    let append = parse_single_command("cmd 2> err &>> log").unwrap();
+    assert_eq!(append.stdout_redirect, Some(("log".to_string(), true)));
+    assert_eq!(append.output_operations.last(), Some(&OutputOperation::Duplicate { fd: 2, target: 1 }));
+    assert!(parse_single_command("cmd &>").is_err());
+}
+
+#[test]
+fn redirection_targets_can_be_quoted_or_attached() {
+    let target = |redirect: Option<String>| expand_variables(&redirect.unwrap(), &mut |_| None);
+
+    let quoted = parse_single_command(r#"cat < "input file.txt" > 'my file.txt' 2>> my\ log"#).unwrap();
+    assert!(quoted.args.is_empty());
+    assert_eq!(target(quoted.stdin_redirect), "input file.txt");
+    assert_eq!(target(quoted.stdout_redirect.map(|(file, _)| file)), "my file.txt");
+    assert_eq!(target(quoted.stderr_redirect.map(|(file, _)| file)), "my log");
+
+    let attached = parse_single_command(r#"cat <in.txt >>"out file" 2>err.txt"#).unwrap();
+    assert!(attached.args.is_empty());
+    assert_eq!(attached.stdin_redirect.as_deref(), Some("in.txt"));
+    assert_eq!(attached.stdout_redirect.as_ref().map(|(_, append)| *append), Some(true));
+    assert_eq!(target(attached.stdout_redirect.map(|(file, _)| file)), "out file");
+    assert_eq!(attached.stderr_redirect, Some(("err.txt".to_string(), false)));
+
+    let both = parse_single_command("cmd &>>all.log").unwrap();
+    assert_eq!(both.stdout_redirect, Some(("all.log".to_string(), true)));
+    assert_eq!(both.output_operations, vec![OutputOperation::StdoutToFile, OutputOperation::Duplicate { fd: 2, target: 1 }]);
+
+    // 被引号保护的 > 仍然是普通参数
+    let literal = parse_single_command(r#"echo ">x" \>y"#).unwrap();
+    assert_eq!(literal.args.len(), 2);
+    assert!(literal.stdout_redirect.is_none());
+}
+
+#[test]
+fn quoted_heredoc_delimiter_disables_expansion() {
+    let cmd = parse_single_command(r#"cat <<'EOF' <<"A"B <<-\END << X <<-TAB"#).unwrap();
+
+    let heredocs: Vec<(&str, bool, bool)> = cmd
+        .heredocs
+        .iter()
+        .map(|h| (h.delimiter.as_str(), h.expand, h.strip_tabs))
+        .collect();
+    assert_eq!(
+        heredocs,
+        vec![("EOF", false, false), ("AB", false, false), ("END", false, true), ("X", true, false), ("TAB", true, true)]
+    );
+    assert!(cmd.heredocs[4].is_terminator("\t\tTAB"));
+    assert!(!cmd.heredocs[3].is_terminator("\tX"));
+}
+
+#[test]
+fn herestring_is_recorded_and_excludes_input_redirect() {
+    let spaced = parse_single_command(r#"grep foo <<< "a b""#).unwrap();
+    assert_eq!(spaced.args, vec!["foo"]);
+    assert_eq!(spaced.herestring.as_deref().map(|w| expand_variables(w, &mut |_| None)), Some("a b".to_string()));
+
+    let glued = parse_single_command("cat <<<word").unwrap();
+    assert_eq!(glued.stdin_text().as_deref(), Some("word\n"));
+    assert!(glued.heredocs.is_empty());
+
+    assert!(parse_single_command("cat < in <<< word").is_err());
+    assert!(parse_single_command("cat <<<").is_err());
+}
+
+#[test]
+fn aliases_replace_the_first_word_of_each_command() {
+    let aliases: HashMap<String, String> = [("ll", "ls -la"), ("ls", "ls -F"), ("g", "grep")]
+        .iter()
+        .map(|(name, value)| (name.to_string(), value.to_string()))
+        .collect();
+
+    assert_eq!(expand_aliases("ll /tmp | g ll", &aliases), "ls -la /tmp | grep ll");
+    assert_eq!(expand_aliases("ls; ! ll && x || g&ll", &aliases), "ls -F; ! ls -la && x || grep&ls -la");
+    assert_eq!(expand_aliases("cmd 2>&1 ll >& g &> ll", &aliases), "cmd 2>&1 ll >& g &> ll");
+    assert_eq!(expand_aliases(r#"'ll' "g" \ll echo "a; ll" $(ll)"#, &aliases), r#"'ll' "g" \ll echo "a; ll" $(ll)"#);
+}
+
+#[test]
+fn braces_expand_lists_and_nest() {
+    let braces = |line: &str| -> Vec<String> {
+        tokenize(line)
+            .unwrap()
+            .iter()
+            .flat_map(|token| expand_braces(token))
+            .map(|word| expand_variables(&word, &mut |_| None))
+            .collect()
+    };
+
+    assert_eq!(braces("file{1,2,3}.txt"), vec!["file1.txt", "file2.txt", "file3.txt"]);
+    assert_eq!(braces("{a,b{c,d}}x"), vec!["ax", "bcx", "bdx"]);
+    assert_eq!(braces("{a,b}{1,2}"), vec!["a1", "a2", "b1", "b2"]);
+    assert_eq!(braces("{a,}b"), vec!["ab", "b"]);
+    assert_eq!(braces(r#"{abc} {} {a} "{a,b}" \{a,b} {abc}{x,y}"#), vec!["{abc}", "{}", "{a}", "{a,b}", "{a,b}", "{abc}x", "{abc}y"]);
+    assert_eq!(braces(r#"{"a b",c}"#), vec!["a b", "c"]);
+    assert_eq!(expand_braces("${HOME}"), vec!["${HOME}"]);
+    assert_eq!(expand_braces("$(echo {a,b}){1,2}"), vec!["$(echo {a,b})1", "$(echo {a,b})2"]);
+}
+
+#[test]
+fn braces_expand_ranges_with_steps() {
+    assert_eq!(expand_braces("{1..5}"), vec!["1", "2", "3", "4", "5"]);
+    assert_eq!(expand_braces("{3..-1}"), vec!["3", "2", "1", "0", "-1"]);
+    assert_eq!(expand_braces("{0..10..2}"), vec!["0", "2", "4", "6", "8", "10"]);
+    assert_eq!(expand_braces("{10..1..3}"), vec!["10", "7", "4", "1"]);
+    assert_eq!(expand_braces("{1..6..-2}"), vec!["1", "3", "5"]);
+    assert_eq!(expand_braces("{08..11}"), vec!["08", "09", "10", "11"]);
+    assert_eq!(expand_braces("x{a..e..2}"), vec!["xa", "xc", "xe"]);
+    assert_eq!(expand_braces("{e..c}"), vec!["e", "d", "c"]);
+    assert_eq!(expand_braces("{1..a} {1..2..x} {ab..c}"), vec!["{1..a} {1..2..x} {ab..c}"]);
+}
+
+#[test]
+fn leading_assignments_are_separated_from_the_command() {
+    let cmd = parse_single_command(r#"A=1 MSG="hello world" env B=2 > out"#).unwrap();
+    let assignments: Vec<(&str, String)> = cmd
+        .assignments
+        .iter()
+        .map(|(name, value)| (name.as_str(), expand_variables(value, &mut |_| None)))
+        .collect();
+    assert_eq!(assignments, vec![("A", "1".to_string()), ("MSG", "hello world".to_string())]);
+    assert_eq!(cmd.name, "env");
+    assert_eq!(cmd.args, vec!["B=2"]);
+
+    let only = parse_single_command("GREETING=hello EMPTY=").unwrap();
+    assert_eq!(only.name, "");
+    assert_eq!(only.assignments.len(), 2);
+    assert_eq!(only.to_string(), "GREETING=hello EMPTY=");
+
+    for quoted in [r#""A=1" x"#, r"A\=1 x", "1A=1 x", "=1 x"] {
+        assert!(parse_single_command(quoted).unwrap().assignments.is_empty(), "{}", quoted);
+    }
+}
+
+#[test]
+fn trailing_backslash_and_open_quotes_need_continuation() {
+    assert_eq!(incomplete_line(r"echo a \"), Some(Incomplete::Backslash));
+    assert_eq!(incomplete_line(r#"echo "a \"#), Some(Incomplete::Backslash));
+    assert_eq!(incomplete_line(r#"echo "foo"#), Some(Incomplete::Quote));
+    assert_eq!(incomplete_line("echo 'it\\"), Some(Incomplete::Quote));
+    assert_eq!(incomplete_line(r"echo $'a\'"), Some(Incomplete::Quote));
+    assert_eq!(incomplete_line(r#"echo a\\ "x" 'y' \"z"#), None);
+
+    let mut line = r"echo con\".to_string();
+    Incomplete::Backslash.join(&mut line, "tinued");
+    assert_eq!(line, "echo continued");
+    let mut quoted = "echo 'a".to_string();
+    Incomplete::Quote.join(&mut quoted, "b'");
+    assert_eq!(quoted, "echo 'a\nb'");
+}
+
+#[test]
+fn open_parentheses_and_pending_heredocs_keep_input_incomplete() {
+    assert_eq!(incomplete_line("echo $(date"), Some(Incomplete::Paren));
+    assert_eq!(incomplete_line("(cd /tmp"), Some(Incomplete::Paren));
+    assert_eq!(incomplete_line("echo $((1 + 2)) '(' \\( \"(\" # ("), None);
+
+    let mut line = "(echo a".to_string();
+    Incomplete::Paren.join(&mut line, "echo b)");
+    assert_eq!(line, "(echo a; echo b)");
+    let mut line = "echo $(".to_string();
+    Incomplete::Paren.join(&mut line, "date)");
+    assert_eq!(line, "echo $( date)");
+
+    assert!(input_complete("echo a"));
+    assert!(input_complete(""));
+    assert!(!input_complete("echo 'a"));
+    assert!(!input_complete("echo a \\"));
+    assert!(!input_complete("echo $(date"));
+    assert!(!input_complete("cat <<EOF"));
+    assert!(!input_complete("cat <<EOF\nbody"));
+    assert!(!input_complete("cat <<EOF\nEOF\ncat <<-END"));
+    assert!(input_complete("echo 'a\nb'"));
+    assert!(input_complete("echo a \\\n"));
+    assert!(input_complete("(echo a\necho b)"));
+    assert!(input_complete("cat <<EOF | tr a b\nbody\nEOF"));
+    assert!(input_complete("cat <<-END\n\tEND"));
+}
+
+#[test]
+fn unquoted_hash_at_word_start_begins_a_comment() {
+    assert_eq!(words("ls -l # list files"), vec!["ls", "-l"]);
+    assert_eq!(words("# note"), Vec::<String>::new());
+    assert_eq!(words(r##"echo foo#bar http://x/#top "#q" '#s' \#e"##), vec!["echo", "foo#bar", "http://x/#top", "#q", "#s", "#e"]);
+
+    assert_eq!(strip_comment("ls; # a | b && c"), "ls; ");
+    assert_eq!(strip_comment("ls;# x"), "ls;");
+    assert_eq!(strip_comment(r#"echo "a # b" 'c # d' e\ #f"#), r#"echo "a # b" 'c # d' e\ "#);
+    assert_eq!(strip_comment("echo $(echo #x) # y"), "echo $(echo #x) ");
+    assert_eq!(incomplete_line("echo a # it's \\"), None);
+    assert_eq!(incomplete_line("echo 'a # b"), Some(Incomplete::Quote));
+}
+
+/// 计算不含变量的算术表达式
+fn arithmetic(expression: &str) -> Result<i64, String> {
+    evaluate_arithmetic(expression, &mut |_| None)
+}
+
+#[test]
+fn arithmetic_follows_c_precedence() {
+    assert_eq!(arithmetic("2 + 3 * 4"), Ok(14));
+    assert_eq!(arithmetic("(2 + 3) * 4"), Ok(20));
+    assert_eq!(arithmetic("17 % 5 - 10 / 3"), Ok(-1));
+    assert_eq!(arithmetic("-2 ** 2"), Ok(4));
+    assert_eq!(arithmetic("2 ** 3 ** 2"), Ok(512));
+    assert_eq!(arithmetic("1 + 2 == 3 && 4 > 5 || !0"), Ok(1));
+    assert_eq!(arithmetic("1 ? 2 : 3"), Ok(2));
+    assert_eq!(arithmetic("0x10 + 010"), Ok(24));
+    assert_eq!(arithmetic(""), Ok(0));
+}
+
+#[test]
+fn arithmetic_reads_variables_and_rejects_bad_input() {
+    let vars = HashMap::from([("x", "5"), ("y", "x * 2"), ("empty", "")]);
+    let mut lookup = |name: &str| vars.get(name).map(|v| v.to_string());
+    assert_eq!(evaluate_arithmetic("x + 1", &mut lookup), Ok(6));
+    assert_eq!(evaluate_arithmetic("y + unset + empty", &mut lookup), Ok(10));
+
+    assert!(arithmetic("1 / 0").unwrap_err().contains("除数为 0"));
+    assert!(arithmetic("5 % 0").is_err());
+    assert!(arithmetic("2 +").is_err());
+    assert!(arithmetic("(1 + 2").is_err());
+    assert!(arithmetic("1 2").is_err());
+    // 短路求值不计算另一侧，所以不会报除数为 0
+    assert_eq!(arithmetic("0 && 1 / 0"), Ok(0));
+    assert_eq!(arithmetic("1 ? 7 : 1 / 0"), Ok(7));
+}
+
+#[test]
+fn parentheses_group_a_command_list_into_one_pipeline_stage() {
+    let items = parse_command_list("(cd /tmp && ls; (echo a | cat)) | wc -l > out; echo done").unwrap();
+    assert_eq!(items.len(), 2);
+    let stages = &items[0].commands;
+    assert_eq!(stages.len(), 2);
+    assert_eq!(stages[0].name, "");
+    assert_eq!(stages[0].group.as_deref(), Some("cd /tmp && ls; (echo a | cat)"));
+    assert_eq!(stages[1].name, "wc");
+    assert_eq!(stages[0].to_string(), "(cd /tmp && ls; (echo a | cat))");
+
+    let redirected = parse_single_command("(echo a; echo b) > out.txt").unwrap();
+    assert_eq!(redirected.group.as_deref(), Some("echo a; echo b"));
+    assert_eq!(redirected.stdout_redirect, Some(("out.txt".to_string(), false)));
+
+    // 引号中的括号是普通字符
+    assert_eq!(words(r#"echo "(a)" \(b\)"#), vec!["echo", "(a)", "(b)"]);
+
+    assert!(parse_single_command("(echo a").is_err());
+    assert!(parse_single_command("(echo a) extra").is_err());
+    assert!(parse_single_command("( )").is_err());
+}
+
+#[test]
+fn case_is_parsed_up_to_its_esac_as_one_pipeline_stage() {
+    let items = parse_command_list("case $x in a|b) echo ab;; (c) echo c | cat;& *) case $y in y) ;; esac;;& esac | wc -l; echo done").unwrap();
+    assert_eq!(items.len(), 2);
+    let stages = &items[0].commands;
+    assert_eq!(stages.len(), 2);
+    assert_eq!(stages[1].name, "wc");
+    let case = stages[0].case.as_ref().unwrap();
+    assert_eq!(case.word, "$x");
+    let clauses: Vec<(Vec<&str>, &str, CaseTerminator)> = case
+        .clauses
+        .iter()
+        .map(|clause| (clause.patterns.iter().map(String::as_str).collect(), clause.body.as_str(), clause.terminator))
+        .collect();
+    assert_eq!(clauses, vec![
+        (vec!["a", "b"], "echo ab", CaseTerminator::Break),
+        (vec!["c"], "echo c | cat", CaseTerminator::FallThrough),
+        (vec!["*"], "case $y in y) ;; esac", CaseTerminator::Continue),
+    ]);
+    assert_eq!(stages[0].to_string(), "case $x in a|b) echo ab;; (c) echo c | cat;& *) case $y in y) ;; esac;;& esac");
+
+    // 不在命令名位置上的 case 和 esac 是普通的单词
+    assert_eq!(parse_command_list("echo case; echo esac").unwrap().len(), 2);
+    assert_eq!(parse_command_list("echo 中文; echo esac").unwrap().len(), 2);
+
+    let redirected = parse_single_command("case a in a) echo a;; esac > out.txt").unwrap();
+    assert_eq!(redirected.stdout_redirect, Some(("out.txt".to_string(), false)));
+    assert!(parse_single_command("case a in a) echo a;; esac extra").is_err());
+    assert!(parse_single_command("case a in a) echo a;;").is_err());
+    assert!(parse_single_command("case a a) echo a;; esac").is_err());
+    assert!(parse_single_command("case a in a echo a;; esac").is_err());
+}
+
+#[test]
+fn unfinished_case_continues_on_the_next_line() {
+    assert_eq!(incomplete_line("case $x in"), Some(Incomplete::Compound));
+    assert_eq!(incomplete_line("case $x in a) echo a;; esac"), None);
+    assert_eq!(incomplete_line("echo case in"), None);
+
+    let mut line = "case $x in # 注释".to_string();
+    Incomplete::Compound.join(&mut line, "a) echo a # 注释");
+    Incomplete::Compound.join(&mut line, "esac");
+    assert_eq!(line, "case $x in \na) echo a \nesac");
+    assert_eq!(incomplete_line(&line), None);
+    let case = parse_single_command(&line).unwrap().case.unwrap();
+    assert_eq!(case.clauses[0].body, "echo a");
+    assert!(input_complete("case $x in\na) echo a\n   echo b\n;;\nesac"));
+    assert!(!input_complete("case $x in\na) echo a;;"));
+}
+
+#[test]
+fn case_patterns_do_not_treat_slash_and_dot_specially() {
+    assert!(pattern_matches("*", ".hidden/file"));
+    assert!(pattern_matches("[0-9]*", "42"));
+    assert!(pattern_matches("a?c", "abc"));
+    assert!(pattern_matches(r"\*", "*"));
+    assert!(!pattern_matches(r"\*", "x"));
+    assert!(!pattern_matches("a*", "bab"));
+}
+
+#[test]
+fn select_is_parsed_up_to_its_done() {
+    let items = parse_command_list("select f in a 'b c'; do echo $f; case $f in a) echo;; esac; done < in.txt; echo done").unwrap();
+    assert_eq!(items.len(), 2);
+    let command = &items[0].commands[0];
+    assert_eq!(command.stdin_redirect.as_deref(), Some("in.txt"));
+    let select = command.select.as_ref().unwrap();
+    assert_eq!(select.name, "f");
+    assert_eq!(select.words.as_ref().map(|words| words.iter().map(|word| remove_quotes(word)).collect::<Vec<_>>()), Some(vec!["a".to_string(), "b c".to_string()]));
+    assert_eq!(select.body, "echo $f; case $f in a) echo;; esac;");
+
+    let multiline = parse_single_command("select f\ndo\n  echo $f\ndone").unwrap().select.unwrap();
+    assert!(multiline.words.is_none());
+    assert_eq!(multiline.body, "echo $f");
+    assert_eq!(incomplete_line("select f in a b; do"), Some(Incomplete::Compound));
+
+    assert!(parse_single_command("select f in a; do echo").is_err());
+    assert!(parse_single_command("select f in a; done").is_err());
+    assert!(parse_single_command("select 1 in a; do echo; done").is_err());
+    assert!(parse_single_command("select f on a; do echo; done").is_err());
+}
@@ -0,0 +1,35 @@
+//! 提示符的测试：PS1 转义序列和其中的变量展开
+
+mod common;
+
+use std::env;
+
+use common::lock_env;
+use my_shell::prompt::{DEFAULT_PROMPT, render_escapes};
+use my_shell::shell::Shell;
+
+#[test]
+fn escapes_abbreviate_home_and_strip_width_markers() {
+    let cwd = env::current_dir().unwrap().display().to_string();
+    let parent = cwd.rsplit_once('/').unwrap().0;
+
+    assert_eq!(render_escapes(r"\w|\W", &cwd), "~|~");
+    assert_eq!(render_escapes(r"\w", parent), format!("~/{}", cwd.rsplit('/').next().unwrap()));
+    assert_eq!(render_escapes(r"\[\e[31m\]>\n\\ \q", "/nonexistent"), "\x1b[31m>\n\\ \\q");
+}
+
+#[test]
+fn ps1_expands_variables_and_falls_back_to_default() {
+    let _env = lock_env();
+    let mut shell = Shell::new();
+
+    // SAFETY: 持有 lock_env，其他修改环境的测试不会同时执行
+    unsafe { env::remove_var("PS1") };
+    assert_eq!(shell.prompt(), DEFAULT_PROMPT);
+
+    unsafe { env::set_var("PS1", r#"[$?] "$(echo hi)" \$ "#) };
+    shell.run_line("false");
+    let prompt = shell.prompt();
+    assert!(prompt == "[1] \"hi\" $ " || prompt == "[1] \"hi\" # ", "{:?}", prompt);
+    assert_eq!(shell.last_status, 1);
+}
@@ -0,0 +1,1682 @@
+//! 通过 Shell::run_line 驱动解析器和执行器的集成测试
+
+mod common;
+
+use std::env;
+use std::fs;
+use std::time::{Duration, Instant};
+
+use common::{TempDir, lock_cwd, lock_env, output_of};
+use my_shell::shell::{Flow, Shell, format_time};
+
+#[test]
+fn pipe_output_reaches_last_stage() {
+    let mut shell = Shell::new();
+
+    assert_eq!(output_of(&mut shell, "echo foo | cat"), "foo\n");
+}
+
+#[test]
+fn redirect_truncates_and_appends() {
+    let tmp = TempDir::new("redirect");
+    let out = tmp.file("out.txt");
+    let mut shell = Shell::new();
+
+    shell.run_line(&format!("echo first > {}", out));
+    shell.run_line(&format!("echo second > {}", out));
+    shell.run_line(&format!("echo third >> {}", out));
+
+    assert_eq!(fs::read_to_string(&out).unwrap(), "second\nthird\n");
+}
+
+#[test]
+fn stderr_append_accumulates_across_runs() {
+    let tmp = TempDir::new("stderr_append");
+    let log = tmp.file("errors.log");
+    let mut shell = Shell::new();
+
+    shell.run_line(&format!("sh -c 'echo stale 1>&2' 2> {}", log));
+    shell.run_line(&format!("sh -c 'echo first 1>&2' 2> {}", log));
+    shell.run_line(&format!("sh -c 'echo second 1>&2' 2>> {}", log));
+    shell.run_line(&format!("sh -c 'echo out; echo third 1>&2' 2>> {} > /dev/null", log));
+
+    assert_eq!(fs::read_to_string(&log).unwrap(), "first\nsecond\nthird\n");
+}
+
+#[test]
+fn input_redirect_feeds_command() {
+    let tmp = TempDir::new("stdin");
+    let input = tmp.file("in.txt");
+    fs::write(&input, "b\na\n").unwrap();
+    let mut shell = Shell::new();
+
+    assert_eq!(output_of(&mut shell, &format!("sort < {}", input)), "a\nb\n");
+}
+
+#[test]
+fn cd_changes_working_directory() {
+    let _guard = lock_cwd();
+    let _env = lock_env();
+    let tmp = TempDir::new("cd");
+    let original = env::current_dir().unwrap();
+    let mut shell = Shell::new();
+
+    shell.run_line(&format!("cd {}", tmp.path().display()));
+    let changed = env::current_dir().unwrap();
+    env::set_current_dir(&original).unwrap();
+
+    assert_eq!(changed, tmp.path().canonicalize().unwrap());
+}
+
+#[test]
+fn exit_stops_the_shell() {
+    let mut shell = Shell::new();
+    assert_eq!(shell.run_line("   "), Flow::Continue);
+    assert_eq!(shell.run_line("exit"), Flow::Exit);
+}
+
+#[test]
+fn exit_sets_the_status_the_shell_exits_with() {
+    let mut shell = Shell::new();
+
+    assert_eq!(shell.run_line("exit 3"), Flow::Exit);
+    assert_eq!(shell.last_status, 3);
+    // 没有参数时使用上一条命令的状态，数字取低 8 位
+    assert_eq!(shell.run_line("sh -c 'exit 4'; exit"), Flow::Exit);
+    assert_eq!(shell.last_status, 4);
+    shell.run_line("exit -1");
+    assert_eq!(shell.last_status, 255);
+
+    assert_eq!(shell.run_line("exit foo"), Flow::Exit);
+    assert_eq!(shell.last_status, 2);
+    // 参数太多时报错但不退出
+    assert_eq!(shell.run_line("exit 1 2"), Flow::Continue);
+    assert_eq!(shell.last_status, 1);
+
+    // 子 Shell 和管道中的 exit 只决定它们自己的状态
+    assert_eq!(shell.run_line("(exit 7)"), Flow::Continue);
+    assert_eq!(shell.last_status, 7);
+    shell.run_line("exit 5 | true");
+    assert_eq!(shell.variables.get("PIPESTATUS").map(String::as_str), Some("5 0"));
+}
+
+#[test]
+fn writers_die_quietly_when_the_reader_exits() {
+    let mut shell = Shell::new();
+
+    let start = Instant::now();
+    assert_eq!(output_of(&mut shell, "yes | head -n5"), "y\ny\ny\ny\ny\n");
+    assert_eq!(shell.last_status, 0);
+    // yes 被 SIGPIPE 结束，状态为 128 + 13
+    assert_eq!(shell.variables.get("PIPESTATUS").map(String::as_str), Some("141 0"));
+
+    shell.run_line("yes | true");
+    assert_eq!(shell.last_status, 0);
+    assert_eq!(output_of(&mut shell, "echo $(yes | head -n2)"), "y y\n");
+    assert!(start.elapsed() < Duration::from_secs(10));
+}
+
+#[test]
+fn pipeline_keeps_running_past_a_missing_command() {
+    let mut shell = Shell::new();
+
+    assert_eq!(output_of(&mut shell, "true | my_shell_no_such_command_xyz | echo hi"), "hi\n");
+    assert_eq!(shell.last_status, 0);
+    assert_eq!(shell.variables.get("PIPESTATUS").map(String::as_str), Some("0 127 0"));
+}
+
+#[test]
+fn time_runs_the_pipeline_and_keeps_its_status() {
+    let mut shell = Shell::new();
+
+    shell.run_line("time sh -c 'exit 3'");
+    assert_eq!(shell.last_status, 3);
+    shell.run_line("! time false");
+    assert_eq!(shell.last_status, 0);
+    shell.run_line("time");
+    assert_eq!(shell.last_status, 0);
+
+    assert_eq!(output_of(&mut shell, "time echo abc | tr a x"), "xbc\n");
+    assert_eq!(shell.variables.get("PIPESTATUS").map(String::as_str), Some("0 0"));
+
+    assert_eq!(format_time(Duration::from_millis(1234)), "0m1.234s");
+    assert_eq!(format_time(Duration::from_millis(61_005)), "1m1.005s");
+}
+
+#[test]
+fn noexec_prints_instead_of_running() {
+    let tmp = TempDir::new("noexec");
+    let out = tmp.file("out.txt");
+    let mut shell = Shell::new();
+
+    shell.run_line("set -o noexec");
+    assert!(shell.options.noexec);
+    shell.run_line(&format!("echo dry > {}", out));
+    assert!(!tmp.path().join("out.txt").exists());
+
+    shell.run_line("set +n");
+    assert!(!shell.options.noexec);
+    assert_eq!(output_of(&mut shell, "echo wet"), "wet\n");
+}
+
+#[test]
+fn pipe_with_stderr_captures_both_streams() {
+    let tmp = TempDir::new("pipe_stderr");
+    let mut shell = Shell::new();
+
+    let missing = tmp.file("missing");
+    assert!(output_of(&mut shell, &format!("ls {} |& cat", missing)).contains(&missing));
+}
+
+#[test]
+fn pipe_with_stderr_lets_the_filter_see_error_lines() {
+    let mut shell = Shell::new();
+
+    let script = "echo ok; echo 'error: one' 1>&2; echo fine; echo 'error: two' 1>&2";
+    assert_eq!(output_of(&mut shell, &format!("sh -c \"{}\" |& grep error", script)), "error: one\nerror: two\n");
+
+    // 只用 | 时 stderr 不进入管道
+    assert_eq!(output_of(&mut shell, &format!("sh -c \"{}\" 2> /dev/null | grep -c error", script)), "0\n");
+}
+
+#[test]
+fn heredoc_feeds_first_pipeline_stage() {
+    let tmp = TempDir::new("heredoc_pipe");
+    let out = tmp.file("out.txt");
+    let mut shell = Shell::new();
+
+    shell.run_line(&format!("cat <<EOF | grep foo > {}\nfoo 1\nbar\nfoo 2\nEOF", out));
+
+    assert_eq!(fs::read_to_string(&out).unwrap(), "foo 1\nfoo 2\n");
+}
+
+#[test]
+fn large_heredoc_does_not_deadlock_pipeline() {
+    let tmp = TempDir::new("heredoc_large");
+    let out = tmp.file("out.txt");
+    let body = "0123456789abcdef\n".repeat(20000);
+    let mut shell = Shell::new();
+
+    shell.run_line(&format!("cat << END | cat | wc -l > {}\n{}END", out, body));
+
+    assert_eq!(fs::read_to_string(&out).unwrap().trim(), "20000");
+}
+
+#[test]
+fn random_expands_to_fresh_values_in_range() {
+    let mut shell = Shell::new();
+
+    let values: Vec<u32> = output_of(&mut shell, "echo $RANDOM ${RANDOM} $RANDOM $RANDOM")
+        .split_whitespace()
+        .map(|v| v.parse().unwrap())
+        .collect();
+    assert_eq!(values.len(), 4);
+    assert!(values.iter().all(|&v| v <= 32767));
+    assert!(values.windows(2).any(|w| w[0] != w[1]));
+}
+
+#[test]
+fn seconds_counts_from_shell_start() {
+    let mut shell = Shell::new();
+
+    assert_eq!(output_of(&mut shell, "echo ${SECONDS}s [$UNKNOWN_SPECIAL]"), "0s []\n");
+}
+
+#[test]
+fn script_exposes_lineno_and_name() {
+    let tmp = TempDir::new("script");
+    let script = tmp.file("script.sh");
+    let out = tmp.file("out.txt");
+    fs::write(
+        &script,
+        format!("cat <<EOF > {out}\nbody\nEOF\necho $LINENO $0 >> {out}\n", out = out),
+    )
+    .unwrap();
+    let mut shell = Shell::new();
+
+    assert_eq!(shell.run_file(&script, &[]).unwrap(), Flow::Continue);
+
+    assert_eq!(fs::read_to_string(&out).unwrap(), format!("body\n4 {}\n", script));
+    assert_eq!(shell.script_name, "my_shell");
+}
+
+#[test]
+fn script_arguments_are_positional_parameters() {
+    let tmp = TempDir::new("script_args");
+    let script = tmp.file("script.sh");
+    let sourced = tmp.file("sourced.sh");
+    let out = tmp.file("out.txt");
+    fs::write(&script, format!("echo \"$0 $# [$1] [$2] ${{3:-none}}\" > {out}\nsource {sourced} x\necho $# $1 >> {out}\n", out = out, sourced = sourced)).unwrap();
+    fs::write(&sourced, format!("echo \"$# $1\" >> {}\n", out)).unwrap();
+    let mut shell = Shell::new();
+
+    let args = vec!["a b".to_string(), "c".to_string()];
+    assert_eq!(shell.run_file(&script, &args).unwrap(), Flow::Continue);
+
+    assert_eq!(fs::read_to_string(&out).unwrap(), format!("{} 2 [a b] [c] none\n1 x\n2 a b\n", script));
+    // 脚本结束后恢复交互模式下的参数
+    assert!(shell.positional.is_empty());
+    assert_eq!(output_of(&mut shell, "echo \"$#[$1]\""), "0[]\n");
+}
+
+#[test]
+fn last_heredoc_on_a_command_wins() {
+    let tmp = TempDir::new("heredoc_multi");
+    let out = tmp.file("out.txt");
+    let mut shell = Shell::new();
+
+    shell.run_line(&format!("cat <<A <<B > {}\nfirst\nA\nsecond\nB", out));
+
+    assert_eq!(fs::read_to_string(&out).unwrap(), "second\n");
+}
+
+#[test]
+fn heredocs_across_pipeline_are_read_in_order() {
+    let tmp = TempDir::new("heredoc_stages");
+    let script = tmp.file("script.sh");
+    let out = tmp.file("out.txt");
+    fs::write(
+        &script,
+        format!("cat <<ONE | cat - <<TWO > {out}\nfrom one\nONE\nfrom two\nTWO\necho after >> {out}\n", out = out),
+    )
+    .unwrap();
+    let mut shell = Shell::new();
+
+    shell.run_file(&script, &[]).unwrap();
+
+    assert_eq!(fs::read_to_string(&out).unwrap(), "from two\nafter\n");
+}
+
+#[test]
+fn ansi_c_quotes_interpret_escapes() {
+    let mut shell = Shell::new();
+
+    assert_eq!(
+        output_of(&mut shell, r"echo $'a\tb' $'x y' $'\x41\u00e9\101' $'$RANDOM\''"),
+        "a\tb x y AéA $RANDOM'\n"
+    );
+}
+
+#[test]
+fn locale_quotes_expand_without_translation() {
+    let mut shell = Shell::new();
+
+    assert_eq!(
+        output_of(&mut shell, r#"echo $"a  b \$SECONDS" $"${SECONDS}s [$NOT_SPECIAL]""#),
+        "a  b $SECONDS 0s []\n"
+    );
+}
+
+#[test]
+fn bang_negates_pipeline_status() {
+    let mut shell = Shell::new();
+
+    shell.run_line("false");
+    assert_eq!(shell.last_status, 1);
+    shell.run_line("! false");
+    assert_eq!(shell.last_status, 0);
+    shell.run_line("! echo hi | grep -q hi");
+    assert_eq!(shell.last_status, 1);
+    shell.run_line("! ! true");
+    assert_eq!(shell.last_status, 0);
+    shell.run_line("! definitely-not-a-command-xyz");
+    assert_eq!(shell.last_status, 0);
+    shell.run_line("definitely-not-a-command-xyz");
+    assert_eq!(shell.last_status, 127);
+}
+
+#[test]
+fn cd_dash_and_dotexpand() {
+    let _guard = lock_cwd();
+    let _env = lock_env();
+    let tmp = TempDir::new("cd_dots");
+    let deep = tmp.path().join("a/b/c");
+    fs::create_dir_all(&deep).unwrap();
+    let original = env::current_dir().unwrap();
+    let mut shell = Shell::new();
+
+    shell.run_line(&format!("cd {}", deep.display()));
+    shell.run_line("cd ...");
+    let without_shopt = shell.last_status;
+    shell.run_line("shopt -s dotexpand");
+    shell.run_line("cd ...");
+    let after_dots = env::current_dir().unwrap();
+    shell.run_line("cd -");
+    let after_dash = env::current_dir().unwrap();
+    env::set_current_dir(&original).unwrap();
+
+    assert_eq!(without_shopt, 1);
+    assert_eq!(after_dots, tmp.path().join("a").canonicalize().unwrap());
+    assert_eq!(after_dash, deep.canonicalize().unwrap());
+}
+
+#[test]
+fn cd_exports_pwd_and_oldpwd() {
+    let _guard = lock_cwd();
+    let _env = lock_env();
+    let tmp = TempDir::new("cd_pwd");
+    fs::create_dir_all(tmp.path().join("first")).unwrap();
+    fs::create_dir_all(tmp.path().join("second")).unwrap();
+    let first = tmp.path().join("first").canonicalize().unwrap();
+    let second = tmp.path().join("second").canonicalize().unwrap();
+    let original = env::current_dir().unwrap();
+    let mut shell = Shell::new();
+
+    shell.run_line("unset OLDPWD; cd -");
+    assert_eq!(shell.last_status, 1);
+
+    shell.run_line(&format!("cd {}; cd {}", first.display(), second.display()));
+    let exported = output_of(&mut shell, "echo $PWD $OLDPWD") + &output_of(&mut shell, "sh -c 'echo $PWD $OLDPWD'");
+    let printed = output_of(&mut shell, "cd -");
+    let after_dash = env::current_dir().unwrap();
+    let swapped = output_of(&mut shell, "echo $PWD $OLDPWD");
+    env::set_current_dir(&original).unwrap();
+
+    let (first, second) = (first.display(), second.display());
+    assert_eq!(after_dash.display().to_string(), first.to_string());
+    assert_eq!(exported, format!("{second} {first}\n{second} {first}\n", first = first, second = second));
+    assert_eq!(printed, format!("{}\n", first));
+    assert_eq!(swapped, format!("{} {}\n", first, second));
+}
+
+#[test]
+fn cd_without_arguments_goes_home() {
+    let _guard = lock_cwd();
+    let _env = lock_env();
+    let tmp = TempDir::new("cd_home");
+    let home = tmp.path().canonicalize().unwrap();
+    let original = env::current_dir().unwrap();
+    let mut shell = Shell::new();
+
+    // HOME 作为 Shell 变量设置，不影响其他测试的环境
+    shell.variables.insert("HOME".to_string(), home.display().to_string());
+    shell.run_line("cd");
+    let after_cd = env::current_dir().unwrap();
+    let oldpwd = env::var("OLDPWD").unwrap_or_default();
+    env::set_current_dir(&original).unwrap();
+    assert_eq!(shell.last_status, 0);
+    assert_eq!(after_cd, home);
+    assert_eq!(oldpwd, original.display().to_string());
+
+    shell.run_line("cd a b");
+    assert_eq!(shell.last_status, 1);
+}
+
+#[test]
+fn cd_searches_cdpath_for_relative_directories() {
+    let _guard = lock_cwd();
+    let _env = lock_env();
+    let tmp = TempDir::new("cdpath");
+    for dir in ["work/first/project", "work/second/project", "work/second/other", "cwd/local"] {
+        fs::create_dir_all(tmp.path().join(dir)).unwrap();
+    }
+    let root = tmp.path().canonicalize().unwrap();
+    let original = env::current_dir().unwrap();
+    let mut shell = Shell::new();
+    let mut printed = String::new();
+    let mut cd = |line: &str| {
+        env::set_current_dir(root.join("cwd")).unwrap();
+        printed += &output_of(&mut shell, line);
+        let status = shell.last_status;
+        (status, env::current_dir().unwrap())
+    };
+
+    // 按顺序查找，通过非空的项找到时打印新的目录
+    let cdpath = format!("CDPATH={}:{}", root.join("work/first").display(), root.join("work/second").display());
+    assert_eq!(cd(&format!("{}; cd project", cdpath)), (0, root.join("work/first/project")));
+    assert_eq!(cd("cd other"), (0, root.join("work/second/other")));
+    // CDPATH 中没有时按普通的相对路径处理，不打印
+    assert_eq!(cd("cd local"), (0, root.join("cwd/local")));
+    // 空项表示当前目录，排在前面时优先于后面的项
+    fs::create_dir_all(root.join("cwd/project")).unwrap();
+    assert_eq!(cd(&format!("CDPATH=:{}; cd project", root.join("work/first").display())), (0, root.join("cwd/project")));
+    // 以 . 开头的路径不查找 CDPATH
+    assert_eq!(cd(&format!("CDPATH={}; cd ./other", root.join("work/second").display())).0, 1);
+    env::set_current_dir(&original).unwrap();
+
+    assert_eq!(
+        printed,
+        format!("{}\n{}\n", root.join("work/first/project").display(), root.join("work/second/other").display())
+    );
+}
+
+#[test]
+fn set_toggles_long_options() {
+    let mut shell = Shell::new();
+
+    shell.run_line("set -o histignorefailed");
+    assert!(shell.options.histignorefailed);
+    shell.run_line("set +o histignorefailed");
+    assert!(!shell.options.histignorefailed);
+    shell.run_line("set -o no-such-option");
+    assert_eq!(shell.last_status, 2);
+}
+
+#[test]
+fn environment_variables_expand_in_arguments_and_redirects() {
+    let _env = lock_env();
+    let tmp = TempDir::new("envvars");
+    let out = tmp.file("out.txt");
+    // SAFETY: 持有 lock_env，其他修改环境的测试不会同时执行
+    unsafe { env::set_var("MY_SHELL_TEST_OUT", &out) };
+    let home = env::var("HOME").unwrap_or_default();
+    let mut shell = Shell::new();
+
+    shell.run_line("echo $HOME ${HOME}x \"[$MY_SHELL_TEST_UNSET]\" '$HOME' > $MY_SHELL_TEST_OUT");
+
+    assert_eq!(
+        fs::read_to_string(&out).unwrap(),
+        format!("{home} {home}x [] $HOME\n", home = home)
+    );
+}
+
+#[test]
+fn default_value_expansion_assigns_splits_and_reports_errors() {
+    let mut shell = Shell::new();
+
+    // 没有引号时默认值做字段分割，双引号中保持一个参数
+    assert_eq!(
+        output_of(&mut shell, "EMPTY=; printf '[%s]' ${NAME:-a b} \"${EMPTY:-a  b}\" ${NAME:-'c d'}"),
+        "[a][b][a  b][c d]"
+    );
+
+    // := 把默认值赋给变量，之后的引用看到新的值
+    assert_eq!(output_of(&mut shell, "echo ${NAME:=guest} $NAME ${NAME:+set} ${NAME:=other}"), "guest guest set guest\n");
+    assert_eq!(shell.variables.get("NAME").map(String::as_str), Some("guest"));
+
+    // :? 报告错误，不再执行这一行剩下的命令
+    assert_eq!(output_of(&mut shell, "echo ${MISSING:?没有设置} > /dev/null; echo reached"), "");
+    assert_eq!(shell.last_status, 1);
+}
+
+#[test]
+fn tilde_expands_in_arguments_and_cd() {
+    let _guard = lock_cwd();
+    let _env = lock_env();
+    let original = env::current_dir().unwrap();
+    let home = env::var("HOME").unwrap();
+    let mut shell = Shell::new();
+
+    let expanded = output_of(&mut shell, "echo ~ ~/notes.txt foo~bar \"~\"");
+    shell.run_line("cd ~");
+    let changed = env::current_dir().unwrap();
+    env::set_current_dir(&original).unwrap();
+
+    assert_eq!(expanded, format!("{home} {home}/notes.txt foo~bar ~\n", home = home));
+    assert_eq!(changed, std::path::Path::new(&home).canonicalize().unwrap());
+}
+
+#[test]
+fn globs_expand_sorted_and_keep_unmatched_patterns() {
+    let tmp = TempDir::new("glob");
+    let dir = tmp.path().display().to_string();
+    for name in ["b.rs", "a.rs", "foo1.txt", "foo22.txt", ".hidden.rs"] {
+        fs::write(tmp.file(name), "").unwrap();
+    }
+    fs::create_dir(tmp.file("sub")).unwrap();
+    fs::write(tmp.file("sub/c.rs"), "").unwrap();
+    let mut shell = Shell::new();
+
+    let line = format!(r#"echo {d}/*.rs {d}/foo?.txt "{d}/*.rs" {d}/\*.rs {d}/*/*.rs {d}/*.none"#, d = dir);
+
+    assert_eq!(
+        output_of(&mut shell, &line),
+        format!("{d}/a.rs {d}/b.rs {d}/foo1.txt {d}/*.rs {d}/*.rs {d}/sub/c.rs {d}/*.none\n", d = dir)
+    );
+}
+
+#[test]
+fn background_pipeline_returns_immediately() {
+    let tmp = TempDir::new("background");
+    let out = tmp.file("out.txt");
+    let mut shell = Shell::new();
+
+    let start = std::time::Instant::now();
+    shell.run_line(&format!("sh -c 'sleep 0.3; echo done' > {} &", out));
+    assert!(start.elapsed() < std::time::Duration::from_millis(250));
+    assert_eq!(shell.last_status, 0);
+    assert_eq!(shell.jobs.len(), 1);
+
+    // 作业结束后，下一条命令执行前会被回收，报告一次之后从作业表中删除
+    std::thread::sleep(std::time::Duration::from_millis(600));
+    shell.run_line("true");
+    assert_eq!(shell.jobs.len(), 1);
+    shell.run_line(&format!("jobs > {}", out));
+    assert!(shell.jobs.is_empty());
+    assert_eq!(fs::read_to_string(&out).unwrap(), format!("[1]+  Done                    sh -c sleep 0.3; echo done > {}\n", out));
+}
+
+#[test]
+fn ampersand_runs_the_pipeline_in_the_background_and_continues() {
+    let tmp = TempDir::new("background_list");
+    let out = tmp.file("out.txt");
+    let late = tmp.file("late.txt");
+    let mut shell = Shell::new();
+
+    let start = std::time::Instant::now();
+    shell.run_line(&format!("sh -c 'sleep 0.3; echo late > {}' & echo now > {}", late, out));
+    assert!(start.elapsed() < std::time::Duration::from_millis(250));
+    assert_eq!(fs::read_to_string(&out).unwrap(), "now\n");
+    assert_eq!(shell.jobs.len(), 1);
+
+    // |&、&> 和 >& 中的 & 不分隔命令
+    let copy = tmp.file("copy.txt");
+    shell.run_line(&format!("sh -c 'echo err >&2' |& cat > {} & wait; cat {} &>> {}", out, out, copy));
+    assert_eq!(fs::read_to_string(&copy).unwrap(), "err\n");
+    assert_eq!(fs::read_to_string(&late).unwrap(), "late\n");
+}
+
+#[test]
+fn command_substitution_splices_output() {
+    let mut shell = Shell::new();
+
+    let line = r#"echo $(echo a   b) "$(printf 'c   d')" x$(printf 'y\n\n') `echo bq` $(echo $(echo nested)) $(true) $(echo hi | tr a-z A-Z)"#;
+
+    assert_eq!(output_of(&mut shell, line), "a b c   d xy bq nested HI\n");
+}
+
+#[test]
+fn command_substitution_captures_builtin_output() {
+    let _guard = lock_cwd();
+    let mut shell = Shell::new();
+
+    let cwd = env::current_dir().unwrap();
+    assert_eq!(output_of(&mut shell, "echo \"[$(pwd)]\""), format!("[{}]\n", cwd.display()));
+}
+
+#[test]
+fn and_or_run_based_on_previous_status() {
+    let tmp = TempDir::new("and_or");
+    let mut shell = Shell::new();
+
+    shell.run_line(&format!("true && echo yes > {}", tmp.file("and_ok")));
+    shell.run_line(&format!("false && echo no > {}", tmp.file("and_skipped")));
+    shell.run_line(&format!("false || echo fallback > {}", tmp.file("or_ok")));
+    shell.run_line(&format!("false && echo x > {} || echo y > {}", tmp.file("skipped"), tmp.file("or_after_and")));
+    shell.run_line(&format!("echo a | cat && echo piped > {}", tmp.file("pipe_then")));
+
+    assert_eq!(fs::read_to_string(tmp.file("and_ok")).unwrap(), "yes\n");
+    assert!(!tmp.path().join("and_skipped").exists());
+    assert_eq!(fs::read_to_string(tmp.file("or_ok")).unwrap(), "fallback\n");
+    assert!(!tmp.path().join("skipped").exists());
+    assert_eq!(fs::read_to_string(tmp.file("or_after_and")).unwrap(), "y\n");
+    assert_eq!(fs::read_to_string(tmp.file("pipe_then")).unwrap(), "piped\n");
+
+    shell.run_line("true && false");
+    assert_eq!(shell.last_status, 1);
+    shell.run_line("true || false");
+    assert_eq!(shell.last_status, 0);
+}
+
+#[test]
+fn semicolons_run_every_pipeline_in_order() {
+    let tmp = TempDir::new("semicolon");
+    let out = tmp.file("out.txt");
+    let mut shell = Shell::new();
+
+    shell.run_line(&format!(
+        "echo one > {out} ; false ; echo two | cat >> {out};; echo three >> {out};",
+        out = out
+    ));
+
+    assert_eq!(fs::read_to_string(&out).unwrap(), "one\ntwo\nthree\n");
+    assert_eq!(shell.last_status, 0);
+    assert_eq!(shell.run_line("echo skipped > /dev/null; exit; echo never"), Flow::Exit);
+}
+
+#[test]
+fn stderr_follows_stdout_with_2_to_1() {
+    let tmp = TempDir::new("dup_stderr");
+    let both = tmp.file("both.txt");
+    let piped = tmp.file("piped.txt");
+    let only_out = tmp.file("only_out.txt");
+    let captured = tmp.file("captured.txt");
+    let mut shell = Shell::new();
+
+    shell.run_line(&format!("sh -c 'echo out; echo err 1>&2' > {} 2>&1", both));
+    shell.run_line(&format!("sh -c 'echo err 1>&2' 2>&1 | tr a-z A-Z > {}", piped));
+    // 2>&1 在 > 之前：stderr 复制的是原来的 stdout（这里是命令替换的管道）
+    shell.run_line(&format!("echo \"[$(sh -c 'echo out; echo err 1>&2' 2>&1 > {})]\" > {}", only_out, captured));
+
+    assert_eq!(fs::read_to_string(&both).unwrap(), "out\nerr\n");
+    assert_eq!(fs::read_to_string(&piped).unwrap(), "ERR\n");
+    assert_eq!(fs::read_to_string(&only_out).unwrap(), "out\n");
+    assert_eq!(fs::read_to_string(&captured).unwrap(), "[err]\n");
+}
+
+#[test]
+fn stdout_can_be_duplicated_onto_stderr_in_order() {
+    let tmp = TempDir::new("dup_stdout");
+    let err = tmp.file("err.txt");
+    let swapped = tmp.file("swapped.txt");
+    let builtin = tmp.file("builtin.txt");
+    let captured = tmp.file("captured.txt");
+    let mut shell = Shell::new();
+
+    // 2> 在 1>&2 之前：两个流都写入 err
+    shell.run_line(&format!("sh -c 'echo out; echo err >&2' 2> {} 1>&2", err));
+    // 1>&2 在 2> 之前：stdout 复制的是原来的 stderr，只有 stderr 写入文件
+    shell.run_line(&format!("sh -c 'echo out; echo err >&2' 1>&2 2> {}", swapped));
+    // 内置命令的输出同样可以改道到 stderr
+    shell.run_line(&format!("echo to-stderr >&2 2>> {}", builtin));
+    shell.run_line(&format!("echo first 2> {} >&2; echo second 1>&2 2>> {}", builtin, builtin));
+    // 命令替换只捕获 stdout，被送到 stderr 的输出不会出现在结果里
+    shell.run_line(&format!("echo \"[$(echo hidden 2> /dev/null >&2; echo shown)]\" > {}", captured));
+
+    assert_eq!(fs::read_to_string(&err).unwrap(), "out\nerr\n");
+    assert_eq!(fs::read_to_string(&swapped).unwrap(), "err\n");
+    assert_eq!(fs::read_to_string(&builtin).unwrap(), "first\n");
+    assert_eq!(fs::read_to_string(&captured).unwrap(), "[shown]\n");
+}
+
+#[test]
+fn ampersand_redirect_truncates_and_appends_both_streams() {
+    let tmp = TempDir::new("amp_redirect");
+    let log = tmp.file("log.txt");
+    let middle = tmp.file("middle.txt");
+    let piped = tmp.file("piped.txt");
+    let mut shell = Shell::new();
+
+    shell.run_line(&format!("sh -c 'echo old' &> {}", log));
+    shell.run_line(&format!("sh -c 'echo out; echo err 1>&2' &> {}", log));
+    shell.run_line(&format!("sh -c 'echo again 1>&2' &>> {}", log));
+    // 管道中间的 &> 和 > 一样优先于管道，下一个命令读到空输入
+    shell.run_line(&format!("sh -c 'echo out; echo err 1>&2' &> {} | wc -c > {}", middle, piped));
+
+    assert_eq!(fs::read_to_string(&log).unwrap(), "out\nerr\nagain\n");
+    assert_eq!(fs::read_to_string(&middle).unwrap(), "out\nerr\n");
+    assert_eq!(fs::read_to_string(&piped).unwrap().trim(), "0");
+}
+
+#[test]
+fn heredoc_body_expands_unless_delimiter_is_quoted() {
+    let tmp = TempDir::new("heredoc_expand");
+    let expanded = tmp.file("expanded.txt");
+    let literal = tmp.file("literal.txt");
+    let mut shell = Shell::new();
+
+    shell.run_line(&format!(
+        "cat <<EOF > {}\nname=$0 \"q\" '$(echo sub)' \\$0 `echo bq`\nEOF",
+        expanded
+    ));
+    shell.run_line(&format!("cat <<'EOF' > {}\nname=$0 $(echo sub)\nEOF", literal));
+
+    assert_eq!(fs::read_to_string(&expanded).unwrap(), "name=my_shell \"q\" 'sub' $0 bq\n");
+    assert_eq!(fs::read_to_string(&literal).unwrap(), "name=$0 $(echo sub)\n");
+}
+
+#[test]
+fn heredoc_dash_strips_leading_tabs() {
+    let tmp = TempDir::new("heredoc_tabs");
+    let out = tmp.file("out.txt");
+    let mut shell = Shell::new();
+
+    shell.run_line(&format!("cat <<-END > {}\n\t\tindented\n  spaces kept\n\tEND", out));
+
+    assert_eq!(fs::read_to_string(&out).unwrap(), "indented\n  spaces kept\n");
+}
+
+#[test]
+fn herestring_feeds_expanded_word_to_stdin() {
+    let mut shell = Shell::new();
+
+    assert_eq!(output_of(&mut shell, "tr a-z A-Z <<< \"name $0 $(echo sub)\""), "NAME MY_SHELL SUB\n");
+    assert_eq!(output_of(&mut shell, "echo ignored | cat <<<piped"), "piped\n");
+}
+
+#[test]
+fn question_mark_expands_to_last_status() {
+    let mut shell = Shell::new();
+
+    let output: String = [
+        "true; echo $?",
+        "false; echo \"[$?]\"",
+        "cd /nonexistent_my_shell_dir; echo ${?}",
+        "my_shell_no_such_command; echo $?",
+        "sh -c 'kill -9 $$'; echo $?",
+        "! true; echo $? '$?'",
+    ]
+    .iter()
+    .map(|line| output_of(&mut shell, line))
+    .collect();
+
+    assert_eq!(output, "0\n[1]\n1\n127\n137\n1 $?\n");
+}
+
+#[test]
+fn pipestatus_records_every_stage_of_the_last_pipeline() {
+    let mut shell = Shell::new();
+
+    assert_eq!(output_of(&mut shell, "false | sh -c 'exit 3' | true; echo $? $PIPESTATUS"), "0 1 3 0\n");
+    assert_eq!(output_of(&mut shell, "! sh -c 'exit 2'; echo $? \"$PIPESTATUS\""), "0 2\n");
+    assert_eq!(output_of(&mut shell, "echo a | my_shell_no_such_command 2>/dev/null; echo $PIPESTATUS"), "0 127\n");
+}
+
+#[test]
+fn export_sets_variables_for_expansion_and_children() {
+    let _env = lock_env();
+    let mut shell = Shell::new();
+
+    shell.run_line("export MY_SHELL_EXPORT_A=hello MY_SHELL_EXPORT_B='a \"b\" $c'");
+    assert_eq!(output_of(&mut shell, "echo $MY_SHELL_EXPORT_A"), "hello\n");
+    assert_eq!(output_of(&mut shell, "sh -c 'echo $MY_SHELL_EXPORT_A'"), "hello\n");
+    assert_eq!(
+        output_of(&mut shell, "echo \"$(export)\" | grep MY_SHELL_EXPORT_"),
+        "declare -x MY_SHELL_EXPORT_A=\"hello\"\ndeclare -x MY_SHELL_EXPORT_B=\"a \\\"b\\\" \\$c\"\n"
+    );
+    assert_eq!(output_of(&mut shell, "export 1BAD MY_SHELL_EXPORT_A; echo $?"), "1\n");
+}
+
+#[test]
+fn unset_removes_variables() {
+    let _env = lock_env();
+    let mut shell = Shell::new();
+
+    shell.run_line("export MY_SHELL_UNSET_A=1 MY_SHELL_UNSET_B=2");
+    assert_eq!(
+        output_of(
+            &mut shell,
+            "unset MY_SHELL_UNSET_A MY_SHELL_UNSET_B MY_SHELL_UNSET_MISSING; echo \"[$MY_SHELL_UNSET_A$MY_SHELL_UNSET_B]\" $?"
+        ),
+        "[] 0\n"
+    );
+    assert_eq!(output_of(&mut shell, "sh -c 'echo \"[$MY_SHELL_UNSET_A]\"'"), "[]\n");
+    assert_eq!(output_of(&mut shell, "unset 9x; echo $?"), "1\n");
+}
+
+#[test]
+fn alias_expands_first_word_and_unalias_removes_it() {
+    let mut shell = Shell::new();
+
+    shell.run_line("alias say='echo said' printf='printf [%s]' bad/name=x");
+    assert_eq!(shell.last_status, 1);
+    assert_eq!(output_of(&mut shell, "say hi"), "said hi\n");
+    assert_eq!(output_of(&mut shell, "printf once"), "[once]");
+    assert_eq!(output_of(&mut shell, "echo \"$(alias)\""), "alias printf='printf [%s]'\nalias say='echo said'\n");
+    shell.run_line("unalias say printf");
+    assert_eq!(output_of(&mut shell, "say hi 2>/dev/null; echo $?"), "127\n");
+}
+
+#[test]
+fn echo_builtin_handles_options_and_runs_in_pipelines() {
+    let mut shell = Shell::new();
+
+    assert_eq!(output_of(&mut shell, "echo -e 'b\\ta\\na' | sort"), "a\nb\ta\n");
+    assert_eq!(output_of(&mut shell, "echo -n no newline"), "no newline");
+    assert_eq!(output_of(&mut shell, "echo -ne ' x\\x41\\0102\\\\ \\cignored'"), " xAB\\ ");
+    assert_eq!(output_of(&mut shell, "echo -E -x '\\n' \"[$(echo -n in)]\""), "-x \\n [in]\n");
+    assert_eq!(shell.last_status, 0);
+}
+
+#[test]
+fn xpg_echo_makes_echo_interpret_escapes() {
+    let mut shell = Shell::new();
+
+    assert_eq!(output_of(&mut shell, "echo 'a\\tb'"), "a\\tb\n");
+    shell.run_line("shopt -s xpg_echo");
+    assert_eq!(output_of(&mut shell, "echo 'a\\tb'"), "a\tb\n");
+    assert_eq!(output_of(&mut shell, "echo -E 'a\\tb'"), "a\\tb\n");
+    assert_eq!(output_of(&mut shell, "shopt xpg_echo"), "xpg_echo            \ton\n");
+    shell.run_line("shopt -u xpg_echo");
+    assert_eq!(output_of(&mut shell, "echo 'a\\tb'"), "a\\tb\n");
+    assert_eq!(output_of(&mut shell, "shopt"), "dotexpand           \toff\nxpg_echo            \toff\n");
+}
+
+#[test]
+fn builtins_in_pipelines_run_in_a_subshell() {
+    let _guard = lock_cwd();
+    let _env = lock_env();
+    let before = env::current_dir().unwrap();
+    let mut shell = Shell::new();
+
+    assert_eq!(output_of(&mut shell, "pwd | tr / _"), format!("{}\n", before.display().to_string().replace('/', "_")));
+    assert_eq!(output_of(&mut shell, "cd / | pwd"), format!("{}\n", before.display()));
+    shell.run_line("export MY_SHELL_SUBSHELL_VAR=leaked | true; alias gone=x | true");
+    assert_eq!(output_of(&mut shell, "echo \"[$MY_SHELL_SUBSHELL_VAR]\""), "[]\n");
+    assert_eq!(output_of(&mut shell, "alias"), "");
+    assert_eq!(output_of(&mut shell, "true | exit; echo still here"), "still here\n");
+
+    assert_eq!(env::current_dir().unwrap(), before);
+}
+
+#[test]
+fn source_runs_file_in_current_shell() {
+    let _guard = lock_cwd();
+    let _env = lock_env();
+    let tmp = TempDir::new("source");
+    let out = tmp.file("out.txt");
+    let script = tmp.file("setup.sh");
+    let before = env::current_dir().unwrap();
+    fs::write(
+        &script,
+        format!("# 注释行\n\nexport MY_SHELL_SOURCED=yes\nmy_shell_no_such_command\ncd {}\nfalse\n", tmp.path().display()),
+    )
+    .unwrap();
+    let mut shell = Shell::new();
+
+    let sourced = output_of(&mut shell, &format!("source {} 2>/dev/null; echo $? $MY_SHELL_SOURCED", script));
+    let sourced_dir = env::current_dir().unwrap();
+    env::set_current_dir(&before).unwrap();
+    let missing = output_of(&mut shell, &format!(". {}/missing.sh; echo $?", tmp.path().display()));
+
+    assert_eq!(sourced_dir, tmp.path().canonicalize().unwrap());
+    assert_eq!(sourced, "1 yes\n");
+    assert_eq!(missing, "1\n");
+
+    fs::write(&script, "echo before\nexit\necho after\n").unwrap();
+    assert_eq!(shell.run_line(&format!("source {} > /dev/null; echo not reached > {}", script, out)), Flow::Exit);
+    assert!(!tmp.path().join("out.txt").exists());
+}
+
+#[test]
+fn jobs_lists_running_and_finished_jobs() {
+    let mut shell = Shell::new();
+
+    shell.run_line("sleep 5 &");
+    shell.run_line("sh -c 'exit 3' &");
+    std::thread::sleep(std::time::Duration::from_millis(300));
+
+    assert_eq!(
+        output_of(&mut shell, "jobs"),
+        "[1]-  Running                 sleep 5 &\n[2]+  Exit 3                  sh -c exit 3\n"
+    );
+    assert_eq!(output_of(&mut shell, "jobs"), "[1]+  Running                 sleep 5 &\n");
+    let pid = shell.jobs.iter().next().and_then(|job| job.pid()).unwrap();
+    shell.run_line(&format!("kill {}", pid));
+}
+
+#[test]
+fn finished_jobs_are_reported_once_before_the_prompt() {
+    let mut shell = Shell::new();
+    let notices = |shell: &mut Shell| {
+        let mut out = Vec::new();
+        shell.notify_finished_jobs(&mut out);
+        String::from_utf8(out).unwrap()
+    };
+
+    shell.run_line("sleep 5 &");
+    shell.run_line("true &");
+    shell.run_line("sh -c 'exit 2' &");
+    std::thread::sleep(std::time::Duration::from_millis(300));
+
+    assert_eq!(
+        notices(&mut shell),
+        "[2]-  Done                    true\n[3]+  Exit 2                  sh -c exit 2\n"
+    );
+    assert_eq!(notices(&mut shell), "");
+    // 还在运行的作业留在作业表中
+    assert_eq!(shell.jobs.len(), 1);
+    let pid = shell.jobs.iter().next().and_then(|job| job.pid()).unwrap();
+    shell.run_line(&format!("kill {}", pid));
+}
+
+#[test]
+fn disown_removes_jobs_from_the_table() {
+    let mut shell = Shell::new();
+
+    shell.run_line("sleep 5 &");
+    let first = shell.jobs.iter().next().and_then(|job| job.pid()).unwrap();
+    shell.run_line("disown %1");
+    assert_eq!(shell.last_status, 0);
+    assert_eq!(output_of(&mut shell, "jobs"), "");
+    shell.run_line("disown %1");
+    assert_eq!(shell.last_status, 1);
+
+    // -h 只做标记，作业仍然列出；-a 删除所有作业
+    shell.run_line("sleep 5 &");
+    shell.run_line("sleep 5 &");
+    let pids: Vec<u32> = shell.jobs.iter().filter_map(|job| job.pid()).collect();
+    shell.run_line("disown -h");
+    assert!(shell.jobs.iter().map(|job| job.nohup).eq([false, true]));
+    assert_eq!(output_of(&mut shell, "jobs").lines().count(), 2);
+    shell.run_line(&format!("disown {}", pids[0]));
+    assert_eq!(shell.jobs.len(), 1);
+    shell.run_line("disown -a");
+    assert!(shell.jobs.is_empty());
+
+    shell.run_line(&format!("kill {} {} {}", first, pids[0], pids[1]));
+    assert_eq!(shell.last_status, 0);
+}
+
+#[test]
+fn fg_waits_for_job_and_bg_resumes_stopped_job() {
+    let tmp = TempDir::new("fg_bg");
+    let flag = tmp.file("flag.txt");
+    let mut shell = Shell::new();
+
+    shell.run_line("sh -c 'sleep 0.2; exit 4' &");
+    shell.run_line("fg");
+    assert_eq!(shell.last_status, 4);
+    assert!(shell.jobs.is_empty());
+    shell.run_line("fg %9");
+    assert_eq!(shell.last_status, 1);
+
+    shell.run_line(&format!("sh -c 'sleep 0.2; echo resumed > {}' &", flag));
+    let pid = shell.jobs.iter().next().and_then(|job| job.pid()).unwrap();
+    shell.run_line(&format!("kill -STOP {}", pid));
+    std::thread::sleep(std::time::Duration::from_millis(500));
+    assert!(fs::metadata(&flag).is_err());
+
+    let resumed = output_of(&mut shell, "bg %1");
+    shell.run_line("fg %1 > /dev/null");
+    assert_eq!(fs::read_to_string(&flag).unwrap(), "resumed\n");
+    assert_eq!(resumed, format!("[1]+ sh -c sleep 0.2; echo resumed > {} &\n", flag));
+}
+
+#[test]
+fn wait_returns_the_status_of_an_already_finished_job() {
+    let mut shell = Shell::new();
+
+    shell.run_line("sh -c 'exit 6' &");
+    shell.run_line("sleep 0.2");
+    shell.run_line("wait %1");
+    assert_eq!(shell.last_status, 6);
+
+    // 等待过的作业从作业表中删除
+    assert!(shell.jobs.is_empty());
+    shell.run_line("wait %1");
+    assert_eq!(shell.last_status, 127);
+}
+
+#[test]
+fn wait_blocks_until_background_jobs_finish() {
+    let tmp = TempDir::new("wait");
+    let first = tmp.file("first.txt");
+    let second = tmp.file("second.txt");
+    let mut shell = Shell::new();
+
+    // 没有作业时立即返回 0
+    shell.run_line("false; wait");
+    assert_eq!(shell.last_status, 0);
+
+    shell.run_line(&format!("sh -c 'sleep 0.2; echo one > {}' &", first));
+    shell.run_line(&format!("sh -c 'sleep 0.3; echo two > {}; exit 5' &", second));
+    shell.run_line("wait");
+    assert_eq!(shell.last_status, 0);
+    assert_eq!(fs::read_to_string(&first).unwrap(), "one\n");
+    assert_eq!(fs::read_to_string(&second).unwrap(), "two\n");
+    assert!(shell.jobs.is_empty());
+
+    // 按作业号或 pid 等待时返回作业的退出状态
+    shell.run_line("sh -c 'sleep 0.2; exit 3' &");
+    shell.run_line("sh -c 'exit 4' &");
+    shell.run_line("wait %1");
+    assert_eq!(shell.last_status, 3);
+    let pid = shell.jobs.iter().next().and_then(|job| job.pid()).unwrap();
+    shell.run_line(&format!("wait {}", pid));
+    assert_eq!(shell.last_status, 4);
+    assert!(shell.jobs.is_empty());
+
+    shell.run_line("wait %3");
+    assert_eq!(shell.last_status, 127);
+    shell.run_line("wait 1");
+    assert_eq!(shell.last_status, 127);
+}
+
+#[test]
+fn kill_signals_jobs_and_pids() {
+    let mut shell = Shell::new();
+
+    shell.run_line("sleep 5 | sleep 5 &");
+    shell.run_line("kill %1");
+    assert_eq!(shell.last_status, 0);
+    shell.run_line("fg > /dev/null");
+    assert_eq!(shell.last_status, 128 + 15);
+
+    shell.run_line("sleep 5 &");
+    let pid = shell.jobs.iter().next().and_then(|job| job.pid()).unwrap();
+    shell.run_line(&format!("kill -KILL {}", pid));
+    shell.run_line("fg > /dev/null");
+    assert_eq!(shell.last_status, 128 + 9);
+
+    for line in ["kill %7", "kill -NOSUCH 1", "kill abc", "kill"] {
+        shell.run_line(line);
+        assert_ne!(shell.last_status, 0, "{}", line);
+    }
+}
+
+#[test]
+fn timeout_kills_slow_commands_with_status_124() {
+    let mut shell = Shell::new();
+
+    let start = std::time::Instant::now();
+    shell.run_line("timeout 0.3 sleep 5");
+    assert_eq!(shell.last_status, 124);
+    assert!(start.elapsed() < std::time::Duration::from_secs(2));
+
+    // 命令先结束时返回它自己的状态
+    shell.run_line("timeout 5 sh -c 'exit 3'");
+    assert_eq!(shell.last_status, 3);
+    assert_eq!(output_of(&mut shell, "timeout 1m echo hi | tr a-z A-Z"), "HI\n");
+
+    shell.run_line("timeout soon sleep 1");
+    assert_eq!(shell.last_status, 125);
+}
+
+// 检测停止需要 waitid
+#[cfg(target_os = "linux")]
+#[test]
+fn stopped_foreground_job_can_be_resumed_with_fg_and_bg() {
+    let tmp = TempDir::new("stopped_job");
+    let flag = tmp.file("flag.txt");
+    let mut shell = Shell::new();
+    shell.run_line("set -m");
+
+    // 进程停止自己，和在终端上按 Ctrl-Z 收到 SIGTSTP 的效果一样
+    shell.run_line(&format!("sh -c 'kill -STOP $$; echo resumed > {}'", flag));
+    assert_eq!(shell.last_status, 128 + 20);
+    assert!(output_of(&mut shell, "jobs").starts_with("[1]+  Stopped "));
+    assert!(fs::metadata(&flag).is_err());
+
+    shell.run_line("fg > /dev/null");
+    assert_eq!(shell.last_status, 0);
+    assert_eq!(fs::read_to_string(&flag).unwrap(), "resumed\n");
+    assert!(shell.jobs.is_empty());
+
+    shell.run_line(&format!("sh -c 'kill -STOP $$; echo again > {}'", flag));
+    assert!(output_of(&mut shell, "bg").starts_with("[1]+ sh -c"));
+    shell.run_line("fg > /dev/null");
+    assert_eq!(fs::read_to_string(&flag).unwrap(), "again\n");
+
+    // 停止的作业收到 SIGTERM 后会被继续运行，从而结束
+    shell.run_line("sh -c 'kill -STOP $$; sleep 5'");
+    shell.run_line("kill %1");
+    shell.run_line("fg > /dev/null");
+    assert_eq!(shell.last_status, 128 + 15);
+}
+
+#[test]
+fn monitor_runs_each_pipeline_in_its_own_process_group() {
+    let mut shell = Shell::new();
+
+    shell.run_line("set -m");
+    shell.run_line("sleep 5 | sleep 5 &");
+    let pids = shell.jobs.iter().next().unwrap().pids();
+    let listing = output_of(&mut shell, &format!("ps -o pgid= -p {},{}", pids[0], pids[1]));
+    shell.run_line(&format!("kill {} {}", pids[0], pids[1]));
+    shell.run_line("fg > /dev/null");
+
+    let groups: Vec<u32> = listing
+        .split_whitespace()
+        .map(|group| group.parse().unwrap())
+        .collect();
+    assert_eq!(groups, vec![pids[0], pids[0]]);
+    assert_eq!(shell.last_status, 128 + 15);
+}
+
+#[test]
+fn bracket_globs_match_sets_ranges_and_negation() {
+    let _guard = lock_cwd();
+    let _env = lock_env();
+    let tmp = TempDir::new("glob_class");
+    let dir = tmp.path().display().to_string();
+    for name in ["file1.txt", "file2.txt", "file9.txt", "filea.txt", "fileb.txt", "file-.txt"] {
+        fs::write(tmp.file(name), "").unwrap();
+    }
+    let mut shell = Shell::new();
+
+    for (pattern, expected) in [
+        ("file[0-9].txt", "file1.txt file2.txt file9.txt"),
+        ("file[ab2].txt", "file2.txt filea.txt fileb.txt"),
+        ("file[!0-9].txt", "file-.txt filea.txt fileb.txt"),
+        ("file[^a-z0-8].txt", "file-.txt file9.txt"),
+        ("file[a-].txt", "file-.txt filea.txt"),
+        ("file[xyz].txt", "file[xyz].txt"),
+        ("file[0-9.txt", "file[0-9.txt"),
+    ] {
+        let output = output_of(&mut shell, &format!("(cd {} && echo {})", dir, pattern));
+        assert_eq!(output, format!("{}\n", expected), "{}", pattern);
+    }
+
+    // 被引用的方括号和 test 的 [ 都按字面处理
+    let quoted = output_of(&mut shell, &format!(r#"(cd {} && echo "file[12].txt" file\[12].txt)"#, dir));
+    assert_eq!(quoted, "file[12].txt file[12].txt\n");
+    shell.run_line(&format!("(cd {} && [ -f file1.txt ])", dir));
+    assert_eq!(shell.last_status, 0);
+}
+
+#[test]
+fn brace_expansion_runs_before_globbing() {
+    let tmp = TempDir::new("braces");
+    let dir = tmp.path().display();
+    let mut shell = Shell::new();
+
+    shell.run_line(&format!("touch {}/f{{1..3}}.txt", dir));
+
+    assert_eq!(output_of(&mut shell, &format!("echo {}/f{{1,3}}.*", dir)), format!("{0}/f1.txt {0}/f3.txt\n", dir));
+}
+
+#[test]
+fn assignments_set_shell_variables_and_per_command_environment() {
+    let _env = lock_env();
+    let mut shell = Shell::new();
+
+    shell.run_line("MY_SHELL_ASSIGN_MSG=\"hello world\" MY_SHELL_ASSIGN_N=1");
+    assert_eq!(output_of(&mut shell, "echo \"$MY_SHELL_ASSIGN_MSG\" $MY_SHELL_ASSIGN_N"), "hello world 1\n");
+    // 没有导出的变量子进程看不到
+    assert_eq!(output_of(&mut shell, "sh -c 'echo \"[$MY_SHELL_ASSIGN_MSG]\"'"), "[]\n");
+    // 命令前的赋值只进入这条命令的环境，参数在赋值之前展开
+    assert_eq!(output_of(&mut shell, "MY_SHELL_ASSIGN_N=2 sh -c 'echo $MY_SHELL_ASSIGN_N'"), "2\n");
+    assert_eq!(output_of(&mut shell, "MY_SHELL_ASSIGN_N=3 echo $MY_SHELL_ASSIGN_N"), "1\n");
+    assert_eq!(output_of(&mut shell, "MY_SHELL_ASSIGN_N=4 export; echo $MY_SHELL_ASSIGN_N"), "1\n");
+    shell.run_line("export MY_SHELL_ASSIGN_MSG");
+    assert_eq!(output_of(&mut shell, "sh -c 'echo \"[$MY_SHELL_ASSIGN_MSG]\"'"), "[hello world]\n");
+    // 导出的变量重新赋值后子进程看到新值
+    shell.run_line("MY_SHELL_ASSIGN_MSG=changed");
+    assert_eq!(output_of(&mut shell, "sh -c 'echo \"[$MY_SHELL_ASSIGN_MSG]\"'"), "[changed]\n");
+    shell.run_line("unset MY_SHELL_ASSIGN_MSG MY_SHELL_ASSIGN_N");
+    assert_eq!(output_of(&mut shell, "echo \"[$MY_SHELL_ASSIGN_MSG$MY_SHELL_ASSIGN_N]\""), "[]\n");
+    assert!(std::env::var("MY_SHELL_ASSIGN_N").is_err());
+}
+
+#[test]
+fn assigning_seconds_restarts_the_count() {
+    let mut shell = Shell::new();
+
+    assert_eq!(output_of(&mut shell, "SECONDS=100; echo $SECONDS"), "100\n");
+}
+
+#[test]
+fn redirections_apply_to_every_pipeline_stage() {
+    let tmp = TempDir::new("stage_redirect");
+    let input = tmp.file("in.txt");
+    let err = tmp.file("err.txt");
+    let middle = tmp.file("middle.txt");
+    fs::write(&input, "b\na\n").unwrap();
+    let mut shell = Shell::new();
+
+    // 中间阶段的 stderr 写入文件，stdout 仍然进入管道
+    assert_eq!(output_of(&mut shell, &format!("echo x | sh -c 'cat; echo oops 1>&2' 2> {} | tr x y", err)), "y\n");
+    assert_eq!(fs::read_to_string(&err).unwrap(), "oops\n");
+
+    // 后续阶段的 < 优先于管道输入
+    assert_eq!(output_of(&mut shell, &format!("echo ignored | sort < {}", input)), "a\nb\n");
+
+    // 中间阶段的 > 优先于管道，下一个命令读到空输入；|& 让 stderr 跟随 stdout 的去向
+    let counted = output_of(&mut shell, &format!("sh -c 'echo out; echo err 1>&2' > {} |& wc -l", middle));
+    assert_eq!(fs::read_to_string(&middle).unwrap(), "out\nerr\n");
+    assert_eq!(counted.trim(), "0");
+}
+
+#[test]
+fn test_builtin_checks_files_strings_and_numbers() {
+    let tmp = TempDir::new("test_builtin");
+    let file = tmp.file("file.txt");
+    let empty = tmp.file("empty.txt");
+    let dir = tmp.path().display().to_string();
+    fs::write(&file, "data").unwrap();
+    fs::write(&empty, "").unwrap();
+    let mut shell = Shell::new();
+    let mut status = |line: &str| {
+        shell.run_line(line);
+        shell.last_status
+    };
+
+    assert_eq!(status(&format!("test -f {}", file)), 0);
+    assert_eq!(status(&format!("[ -f {} ]", dir)), 1);
+    assert_eq!(status(&format!("[ -d {} ]", dir)), 0);
+    assert_eq!(status(&format!("[ -e {}/missing ]", dir)), 1);
+    assert_eq!(status(&format!("[ -s {} ] || [ -s {} ]", empty, file)), 0);
+    assert_eq!(status(&format!("[ ! -e {} ]", file)), 1);
+
+    assert_eq!(status("[ ]"), 1);
+    assert_eq!(status("test"), 1);
+    assert_eq!(status("[ -z \"\" ]"), 0);
+    assert_eq!(status("[ -n \"\" ]"), 1);
+    assert_eq!(status("[ -n ]"), 0);
+    assert_eq!(status("[ \"\" ]"), 1);
+    assert_eq!(status("[ abc = abc ] && [ abc != abd ]"), 0);
+    assert_eq!(status("[ = = = ]"), 0);
+    assert_eq!(status("[ a = b -o 1 -eq 1 ]"), 0);
+    assert_eq!(status("[ a -a \"\" ]"), 1);
+
+    assert_eq!(status("[ 10 -gt 9 ]"), 0);
+    assert_eq!(status("[ -3 -lt -4 ]"), 1);
+    assert_eq!(status("[ 5 -eq 5 ] && [ 5 -ne 6 ] && [ 5 -le 5 ] && [ 5 -ge 4 ]"), 0);
+    assert_eq!(status("[ abc -eq 1 ]"), 2);
+    assert_eq!(status("[ 1 -eq 1"), 2);
+    assert_eq!(status("[ 1 -bogus 1 ]"), 2);
+}
+
+#[test]
+fn read_splits_line_into_variables() {
+    let tmp = TempDir::new("read");
+    let input = tmp.file("in.txt");
+    fs::write(&input, "  one  two   three four  \nsecond line\n").unwrap();
+    let mut shell = Shell::new();
+
+    // 单词比变量多：最后一个变量得到剩下的内容
+    shell.run_line(&format!("read a b < {}", input));
+    assert_eq!(output_of(&mut shell, "echo \"[$a][$b]\""), "[one][two   three four]\n");
+    // 单词比变量少：多出的变量为空
+    shell.run_line("read x y z <<< 'only \"quoted\"'");
+    assert_eq!(output_of(&mut shell, "echo \"[$x][$y][$z]\""), "[only][\"quoted\"][]\n");
+    // 没有变量时整行保存在 REPLY 中；反斜杠转义空白，-r 保留反斜杠
+    shell.run_line("read <<< '  keep  spaces '");
+    assert_eq!(output_of(&mut shell, "echo \"[$REPLY]\""), "[  keep  spaces ]\n");
+    shell.run_line(r"read p q <<< 'a\ b c'");
+    assert_eq!(output_of(&mut shell, "echo \"[$p][$q]\""), "[a b][c]\n");
+    shell.run_line(r"read -r p q <<< 'a\ b c'");
+    assert_eq!(output_of(&mut shell, "echo \"[$p][$q]\""), "[a\\][b c]\n");
+    // 读到的变量不导出给子进程
+    assert!(std::env::var("a").is_err());
+}
+
+#[test]
+fn read_returns_failure_at_end_of_file() {
+    let tmp = TempDir::new("read_eof");
+    let empty = tmp.file("empty.txt");
+    let partial = tmp.file("partial.txt");
+    fs::write(&empty, "").unwrap();
+    fs::write(&partial, "no newline").unwrap();
+    let mut shell = Shell::new();
+
+    shell.run_line("line=old");
+    shell.run_line(&format!("read line < {}", empty));
+    assert_eq!(shell.last_status, 1);
+    assert_eq!(shell.variables.get("line").map(String::as_str), Some(""));
+
+    shell.run_line(&format!("read line < {}", partial));
+    assert_eq!(shell.last_status, 1);
+    assert_eq!(shell.variables.get("line").map(String::as_str), Some("no newline"));
+
+    shell.run_line("read 1bad <<< x");
+    assert_eq!(shell.last_status, 1);
+}
+
+#[test]
+fn rc_file_configures_the_shell_and_tolerates_errors() {
+    let _env = lock_env();
+    let tmp = TempDir::new("rc");
+    let rc = tmp.path().join(".rust_shellrc");
+    fs::write(&rc, "alias greet='echo hello'\nexport MY_SHELL_RC_VAR=set\nPS1='rc> '\nmy_shell_no_such_command\necho 'unterminated\n").unwrap();
+    let mut shell = Shell::new();
+
+    assert_eq!(shell.run_rc_file(&rc), Flow::Continue);
+
+    assert_eq!(output_of(&mut shell, "greet $MY_SHELL_RC_VAR $0"), "hello set my_shell\n");
+    assert_eq!(shell.prompt(), "rc> ");
+    assert_eq!(shell.run_rc_file(&tmp.path().join("missing")), Flow::Continue);
+}
+
+#[test]
+fn continuation_lines_are_joined_before_parsing() {
+    let tmp = TempDir::new("continuation");
+    let out = tmp.file("out.txt");
+    let script = tmp.file("script.sh");
+    let mut shell = Shell::new();
+
+    shell.run_line(&format!("echo con\\\ntinued \\\n> {}", out));
+    shell.run_line(&format!("echo \"two\nlines\" >> {}", out));
+    fs::write(&script, format!("echo 'in\nscript' \\\n  >> {}\necho $LINENO >> {}\n", out, out)).unwrap();
+    shell.run_line(&format!("source {}", script));
+
+    assert_eq!(fs::read_to_string(&out).unwrap(), "continued\ntwo\nlines\nin\nscript\n4\n");
+}
+
+#[test]
+fn open_parentheses_continue_on_the_next_line() {
+    let mut shell = Shell::new();
+
+    assert_eq!(output_of(&mut shell, "(echo a\necho b\n)"), "a\nb\n");
+    assert_eq!(output_of(&mut shell, "echo $(\necho c\n)"), "c\n");
+}
+
+#[test]
+fn comments_are_ignored() {
+    let tmp = TempDir::new("comments");
+    let out = tmp.file("out.txt");
+    let mut shell = Shell::new();
+
+    shell.run_line("# echo not run; false");
+    assert_eq!(shell.last_status, 0);
+    shell.run_line(&format!("echo run > {} # | tr a-z A-Z; it's a comment", out));
+    shell.run_line(&format!("echo \"a # b\" 'c # d' e#f >> {} #comment", out));
+
+    assert_eq!(fs::read_to_string(&out).unwrap(), "run\na # b c # d e#f\n");
+}
+
+#[test]
+fn arithmetic_expansion_uses_shell_variables() {
+    let mut shell = Shell::new();
+
+    assert_eq!(
+        output_of(&mut shell, "x=5; echo $((x + 1)) $(( (2+3)*4 )) \"$((1+2))\" $(($x * $(echo 3)))"),
+        "6 20 3 15\n"
+    );
+}
+
+#[test]
+fn division_by_zero_aborts_the_rest_of_the_line() {
+    let mut shell = Shell::new();
+
+    assert_eq!(output_of(&mut shell, "echo $((1 / 0)) > /dev/null; echo next"), "");
+    assert_eq!(shell.last_status, 1);
+
+    assert_eq!(output_of(&mut shell, "echo next"), "next\n");
+}
+
+#[test]
+fn subshell_changes_do_not_leak_out() {
+    let _cwd = lock_cwd();
+    let _env = lock_env();
+    let tmp = TempDir::new("subshell");
+    let out = tmp.file("out.txt");
+    let before = env::current_dir().unwrap();
+    let mut shell = Shell::new();
+
+    shell.run_line(&format!("x=1; (cd {} && x=2 && export SUBSHELL_ONLY=1 && echo $x > out.txt)", tmp.path().display()));
+
+    assert_eq!(env::current_dir().unwrap(), before);
+    assert_eq!(fs::read_to_string(&out).unwrap(), "2\n");
+    assert_eq!(shell.variables.get("x").map(String::as_str), Some("1"));
+    assert!(env::var("SUBSHELL_ONLY").is_err());
+}
+
+#[test]
+fn quoted_variable_can_be_followed_by_a_suffix() {
+    let mut shell = Shell::new();
+
+    assert_eq!(output_of(&mut shell, "name=file; echo \"$name\"x \"$name\".txt"), "filex file.txt\n");
+}
+
+#[test]
+fn unquoted_variables_are_split_into_fields() {
+    let mut shell = Shell::new();
+
+    shell.run_line("v='a  b'; empty=");
+
+    assert_eq!(
+        output_of(&mut shell, "printf '[%s]' $v ${v} ${v:-x} $empty \"$v\" \"${v}\" \"$empty\""),
+        "[a][b][a][b][a][b][a  b][a  b][]"
+    );
+}
+
+#[test]
+fn indirect_expansion_reads_the_named_variable() {
+    let mut shell = Shell::new();
+
+    shell.run_line("target='a  b'; ref=target; missing=nosuch");
+
+    assert_eq!(output_of(&mut shell, "echo \"[${!ref}]\" [${!missing}] [${!unset_ref}]"), "[a  b] [] []\n");
+}
+
+#[test]
+fn prefix_expansion_lists_matching_variable_names() {
+    let mut shell = Shell::new();
+
+    shell.run_line("MYPRE_b=1; MYPRE_a=2; MYPREFIX=3; OTHER=4");
+
+    assert_eq!(output_of(&mut shell, "printf '%s\\n' ${!MYPRE*}"), "MYPREFIX\nMYPRE_a\nMYPRE_b\n");
+    assert_eq!(output_of(&mut shell, "echo \"${!MYPRE_@}\" [${!NOSUCH_PREFIX*}]"), "MYPRE_a MYPRE_b []\n");
+}
+
+#[test]
+fn assignment_status_is_that_of_its_command_substitution() {
+    let tmp = TempDir::new("assignment_status");
+    let out = tmp.file("out.txt");
+    let mut shell = Shell::new();
+
+    assert_eq!(output_of(&mut shell, "x=$(exit 7); echo $?"), "7\n");
+    assert_eq!(output_of(&mut shell, "y=$(false) || echo failed"), "failed\n");
+    assert_eq!(output_of(&mut shell, "z=plain; echo $?"), "0\n");
+
+    assert_eq!(shell.run_line(&format!("set -e; y=$(false); echo reached > {}", out)), Flow::Exit);
+    assert!(fs::metadata(&out).is_err());
+    assert_eq!(shell.last_status, 1);
+}
+
+#[test]
+fn command_substitution_changes_do_not_leak_out() {
+    let _cwd = lock_cwd();
+    let _env = lock_env();
+    let tmp = TempDir::new("substitution_state");
+    let before = env::current_dir().unwrap();
+    let mut shell = Shell::new();
+
+    let dir = output_of(&mut shell, &format!("x=1; dir=$(cd {} && pwd); echo $dir", tmp.path().display()));
+    shell.run_line("y=$(x=2; export SUBSTITUTION_ONLY=1; echo $x)");
+
+    assert_eq!(env::current_dir().unwrap(), before);
+    assert_eq!(dir, format!("{}\n", tmp.path().display()));
+    assert_eq!(shell.variables.get("x").map(String::as_str), Some("1"));
+    assert_eq!(shell.variables.get("y").map(String::as_str), Some("2"));
+    assert!(env::var("SUBSTITUTION_ONLY").is_err());
+}
+
+#[test]
+fn subshell_output_can_be_piped_and_redirected() {
+    let tmp = TempDir::new("subshell_pipe");
+    let piped = tmp.file("piped.txt");
+    let redirected = tmp.file("redirected.txt");
+    let mut shell = Shell::new();
+
+    shell.run_line(&format!("(echo a; echo b) | tr a-z A-Z > {}", piped));
+    shell.run_line(&format!("(echo a; (echo c; echo b) | sort) > {}", redirected));
+
+    assert_eq!(fs::read_to_string(&piped).unwrap(), "A\nB\n");
+    assert_eq!(fs::read_to_string(&redirected).unwrap(), "a\nb\nc\n");
+
+    shell.run_line("(true; false)");
+    assert_eq!(shell.last_status, 1);
+}
+
+#[test]
+fn case_runs_the_first_matching_clause() {
+    let mut shell = Shell::new();
+
+    assert_eq!(output_of(&mut shell, "x=42; case $x in [0-9]*) echo numeric;; *) echo other;; esac"), "numeric\n");
+    assert_eq!(output_of(&mut shell, "case 'a b' in a) echo a;; \"a b\"|c) echo both;; esac"), "both\n");
+    assert_eq!(output_of(&mut shell, "p=x; case '*' in $p) echo var;; \\*) echo star;; esac"), "star\n");
+    assert_eq!(output_of(&mut shell, "case abc in a*) echo one;& x) echo two;; *) echo three;; esac"), "one\ntwo\n");
+    assert_eq!(output_of(&mut shell, "case abc in a*) echo one;;& x) echo no;;& *c) echo two;; esac"), "one\ntwo\n");
+    assert_eq!(output_of(&mut shell, "case y in (y) echo a; echo b;; esac | tr a-z A-Z"), "A\nB\n");
+
+    shell.run_line("false; case a in b) echo no;; esac");
+    assert_eq!(shell.last_status, 0);
+    shell.run_line("case a in a) false;; esac");
+    assert_eq!(shell.last_status, 1);
+    // 在当前 Shell 中执行，分支中的赋值保留下来
+    shell.run_line("case a in a) picked=yes;; esac");
+    assert_eq!(output_of(&mut shell, "echo $picked"), "yes\n");
+}
+
+#[test]
+fn case_in_a_script_spans_lines_and_can_exit_the_shell() {
+    let tmp = TempDir::new("case_script");
+    let script = tmp.file("script.sh");
+    let out = tmp.file("out.txt");
+    fs::write(
+        &script,
+        format!(
+            "case $1 in\n  # 注释\n  a|x) echo first # 注释\n    echo second\n    ;;\n  *) echo other\nesac > {out}\n\
+             case $1 in x) exit 3;; esac\necho not reached >> {out}\n",
+            out = out
+        ),
+    )
+    .unwrap();
+    let mut shell = Shell::new();
+
+    assert_eq!(shell.run_file(&script, &["x".to_string()]).unwrap(), Flow::Exit);
+
+    assert_eq!(fs::read_to_string(&out).unwrap(), "first\nsecond\n");
+    assert_eq!(shell.last_status, 3);
+}
+
+#[test]
+fn select_reads_choices_until_end_of_input() {
+    let tmp = TempDir::new("select");
+    let input = tmp.file("input.txt");
+    fs::write(&input, "2\n\n3\nx\n").unwrap();
+    let mut shell = Shell::new();
+
+    let line = format!("select f in apple banana 'cherry pie'; do echo \"[$f] [$REPLY]\"; done < {}", input);
+    assert_eq!(output_of(&mut shell, &line), "[banana] [2]\n[cherry pie] [3]\n[] [x]\n");
+    assert_eq!(shell.last_status, 0);
+
+    shell.positional = vec!["first".to_string(), "second".to_string()];
+    assert_eq!(output_of(&mut shell, "select f\ndo\n  echo $f\ndone <<< 1"), "first\n");
+
+    assert_eq!(shell.run_line("select f in a; do exit 4; done <<< 1; echo not reached"), Flow::Exit);
+    assert_eq!(shell.last_status, 4);
+}
+
+#[cfg(unix)]
+#[test]
+fn umask_controls_permissions_of_redirected_files() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let tmp = TempDir::new("umask");
+    let created = tmp.file("created.txt");
+    let mut shell = Shell::new();
+
+    let before = output_of(&mut shell, "umask");
+
+    // 子 Shell 中设置的掩码在组结束后恢复
+    let inner = output_of(&mut shell, &format!("(umask 027; echo x > {}; umask)", created));
+    let mode = fs::metadata(&created).unwrap().permissions().mode() & 0o777;
+    assert_eq!(mode, 0o640);
+    assert_eq!(inner, "0027\n");
+
+    assert_eq!(output_of(&mut shell, "umask"), before);
+
+    shell.run_line("umask 8");
+    assert_eq!(shell.last_status, 1);
+}
+
+#[test]
+fn redirections_accept_quoted_names_with_spaces_and_attached_operators() {
+    let tmp = TempDir::new("quoted_redirect");
+    let spaced = tmp.file("my file.txt");
+    let out = tmp.file("out.txt");
+    let mut shell = Shell::new();
+
+    shell.run_line(&format!("echo hello > \"{}\"", spaced));
+    assert_eq!(fs::read_to_string(&spaced).unwrap(), "hello\n");
+
+    shell.run_line(&format!("cat < '{}' >{}", spaced, out));
+    shell.run_line(&format!("echo again >>{}", out));
+    assert_eq!(fs::read_to_string(&out).unwrap(), "hello\nagain\n");
+}
+
+#[test]
+fn process_substitution_connects_commands_through_dev_fd() {
+    let tmp = TempDir::new("process_substitution");
+    let left = tmp.file("left.txt");
+    let right = tmp.file("right.txt");
+    let diff = tmp.file("diff.txt");
+    let upper = tmp.file("upper.txt");
+    let lines = tmp.file("lines.txt");
+    fs::write(&left, "b\na\nc\n").unwrap();
+    fs::write(&right, "c\nb\na\n").unwrap();
+    let mut shell = Shell::new();
+
+    shell.run_line(&format!("diff <(sort {}) <(sort {}) > {}", left, right, diff));
+    assert_eq!(shell.last_status, 0);
+    assert_eq!(fs::read_to_string(&diff).unwrap(), "");
+
+    // >(...) 从外层命令写入的管道读取；前台命令结束时它也已经结束
+    shell.run_line(&format!("echo hello | tee >(tr a-z A-Z > {}) > /dev/null", upper));
+    assert_eq!(fs::read_to_string(&upper).unwrap(), "HELLO\n");
+
+    // 也可以作为重定向的目标，其中可以是命令列表
+    shell.run_line(&format!("cat < <(echo one; echo two) > >(wc -l > {})", lines));
+    assert_eq!(fs::read_to_string(&lines).unwrap().trim(), "2");
+
+    // dry-run 模式下不启动其中的命令
+    let marker = tmp.file("marker.txt");
+    shell.run_line(&format!("set -n; cat <(echo skipped > {})", marker));
+    shell.run_line("set +n");
+    assert!(fs::metadata(&marker).is_err());
+}
+
+#[test]
+fn errexit_stops_at_the_first_failing_command() {
+    let tmp = TempDir::new("errexit");
+    let out = tmp.file("out.txt");
+    let mut shell = Shell::new();
+
+    assert_eq!(shell.run_line(&format!("set -e; false; echo reached > {}", out)), Flow::Exit);
+    assert!(fs::metadata(&out).is_err());
+    assert_eq!(shell.last_status, 1);
+
+    // && 和 || 左侧的命令以及取反的管道不会触发 errexit
+    let flow = shell.run_line(&format!("false && true; false || echo or > {out}; ! true; echo reached >> {out}", out = out));
+    assert_eq!(flow, Flow::Continue);
+    assert_eq!(fs::read_to_string(&out).unwrap(), "or\nreached\n");
+
+    // && 列表中最后一个命令失败时仍然退出
+    assert_eq!(shell.run_line("true && false"), Flow::Exit);
+
+    // 脚本在失败的命令处停止
+    let script = tmp.file("script.sh");
+    fs::write(&script, format!("echo one > {out}\nsh -c 'exit 3'\necho two >> {out}\n", out = out)).unwrap();
+    assert_eq!(shell.run_file(&script, &[]).unwrap(), Flow::Exit);
+    assert_eq!(fs::read_to_string(&out).unwrap(), "one\n");
+    assert_eq!(shell.last_status, 3);
+
+    shell.run_line("set +e");
+    assert_eq!(shell.run_line("false; true"), Flow::Continue);
+    assert!(!shell.options.errexit);
+}
+
+#[test]
+fn set_x_toggles_tracing_without_changing_output() {
+    let mut shell = Shell::new();
+
+    shell.run_line("set -x");
+    assert!(shell.options.xtrace);
+    assert_eq!(output_of(&mut shell, "echo traced | cat"), "traced\n");
+
+    assert!(output_of(&mut shell, "set -o").lines().any(|line| line.starts_with("xtrace") && line.ends_with("on")));
+    shell.run_line("set +o xtrace");
+    assert!(!shell.options.xtrace);
+}
+
+#[test]
+fn help_lists_builtins_and_describes_one() {
+    let mut shell = Shell::new();
+
+    let listing = output_of(&mut shell, "help");
+    let lines: Vec<&str> = listing.lines().skip(1).collect();
+    assert_eq!(lines.len(), my_shell::builtins::BUILTINS.len());
+    assert!(lines.iter().any(|line| line.starts_with("  cd ")));
+    // 说明从同一列开始
+    let column = |line: &str| line.find(|c: char| !c.is_ascii()).unwrap();
+    assert!(lines.iter().all(|line| column(line) == column(lines[0])));
+
+    assert!(output_of(&mut shell, "help cd").starts_with("cd: cd "));
+    assert_eq!(shell.last_status, 0);
+
+    assert!(output_of(&mut shell, "help echo no_such_builtin 2> /dev/null").starts_with("echo: "));
+    assert_eq!(shell.last_status, 1);
+}
+
+#[test]
+fn pushd_and_popd_maintain_a_directory_stack() {
+    let _guard = lock_cwd();
+    let _env = lock_env();
+    let tmp = TempDir::new("dirstack");
+    let root = tmp.path().canonicalize().unwrap().display().to_string();
+    fs::create_dir(tmp.file("a")).unwrap();
+    fs::create_dir(tmp.file("b")).unwrap();
+    let original = env::current_dir().unwrap();
+    let mut shell = Shell::new();
+
+    let listed = output_of(&mut shell, &format!("cd {} && pushd a > /dev/null && pushd ../b > /dev/null && dirs", root));
+    let swapped = output_of(&mut shell, "pushd");
+    let popped = output_of(&mut shell, "popd > /dev/null && popd");
+    let cwd = env::current_dir().unwrap();
+    shell.run_line("popd");
+    let empty_status = shell.last_status;
+    shell.run_line("pushd ./no_such_dir");
+    let missing_status = shell.last_status;
+    env::set_current_dir(&original).unwrap();
+
+    assert_eq!(listed, format!("{r}/b {r}/a {r}\n", r = root));
+    assert_eq!(swapped, format!("{r}/a {r}/b {r}\n", r = root));
+    assert_eq!(popped, format!("{}\n", root));
+    assert_eq!(cwd.display().to_string(), root);
+    assert_eq!(empty_status, 1);
+    assert_eq!(missing_status, 1);
+}